@@ -0,0 +1,61 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use pkmt::{
+    document_component::{DocumentComponent, ParsedDocument, PropValue},
+    parsing::{TextMode, parse_all_files_in_dir},
+    todoi::handlers::collect_urls,
+};
+
+/// writes 1000 logseq notes with a url property in a nested block each
+fn synthetic_tree() -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join("pkmt_bench_get_all_urls");
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    for i in 0..1000 {
+        let text = format!(
+            "- note {i} with some text\n  - a nested block\n    url:: https://example.com/{i}\n    tags:: #video\n  - more text [[other {i}]]\n- last block"
+        );
+        std::fs::write(dir.join(format!("note_{i}.md")), text).unwrap();
+    }
+    dir
+}
+
+/// url extraction as done before collect_urls, cloning every matching component
+fn cloning_urls(documents: &[ParsedDocument]) -> Vec<String> {
+    documents
+        .iter()
+        .flat_map(|pd| {
+            pd.get_all_document_components(&|dc| {
+                matches!(dc, DocumentComponent::Properties(props, _) if props.iter().any(|p| p.has_name("url")))
+            })
+        })
+        .flat_map(|dc| match dc {
+            DocumentComponent::Properties(props, _) => props
+                .into_iter()
+                .filter(|p| p.has_name("url"))
+                .flat_map(|p| p.values)
+                .filter_map(|v| match v {
+                    PropValue::String(s) => Some(s),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+fn bench_get_all_urls(c: &mut Criterion) {
+    let dir = synthetic_tree();
+    let documents = parse_all_files_in_dir(&dir, &TextMode::LogSeq).unwrap();
+    assert_eq!(collect_urls(&documents), cloning_urls(&documents));
+
+    let mut group = c.benchmark_group("get_all_urls");
+    group.bench_function("cloning", |b| b.iter(|| cloning_urls(&documents)));
+    group.bench_function("single_pass", |b| b.iter(|| collect_urls(&documents)));
+    group.finish();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, bench_get_all_urls);
+criterion_main!(benches);
@@ -0,0 +1,703 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::{
+    document_component::{
+        DocumentComponent, ListElem, ParsedDocument, PropValue, Property, dedupe_property_values,
+        normalize_heading_levels, sort_properties,
+    },
+    parsing::{
+        CalloutTitleStyle, FrontmatterListStyle, InputEncoding, LineEnding, LinkStyle,
+        NestedTagStyle, TagStyle, TextMode,
+    },
+    util::{IndentUnit, SPACES_PER_INDENT, default_substitutions},
+};
+
+/// conversion options read from `pkmt-convert.toml` files. A config applies to all files in its
+/// directory and below; options that are unset fall back to the config of the parent directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ConvertConfig {
+    /// number of spaces per indentation level in the output, overrides the indentation detected
+    /// in the source note
+    pub indent_width: Option<usize>,
+    /// convert the values of `tags` properties to lowercase
+    pub lowercase_tags: Option<bool>,
+    /// renames properties and frontmatter entries, e.g. `channel = "authors"`
+    pub property_map: Option<BTreeMap<String, String>>,
+    /// render top-level lists consisting of a single prose item as paragraphs
+    pub flatten_outline: Option<bool>,
+    /// link syntax of the output, `wiki` or `markdown` (default: the one of the output format)
+    pub link_style: Option<LinkStyle>,
+    /// whether tags properties are written as `#tag` or `tag` (default: the one of the output format),
+    /// inline tags always keep their `#`
+    pub tag_style: Option<TagStyle>,
+    /// write nested tags (`parent/child`) as they are or `explode` them into all their levels
+    /// (default: keep)
+    pub nested_tag_style: Option<NestedTagStyle>,
+    /// render multi-value entries of yaml frontmatter `inline` (`[a, b]`) or as a `block` with
+    /// one `- value` line per value (default: inline)
+    pub frontmatter_list_style: Option<FrontmatterListStyle>,
+    /// remove repeated values of properties and frontmatter entries
+    pub dedupe_property_values: Option<bool>,
+    /// sort properties and frontmatter entries by property_order, then alphabetically
+    pub sort_properties: Option<bool>,
+    /// properties that are sorted first (default: tags, date)
+    pub property_order: Option<Vec<String>>,
+    /// keep runs of blank lines between blocks in logseq output, zk output always keeps them
+    pub preserve_blank_lines: Option<bool>,
+    /// extension of the converted files, e.g. `org` (default: md)
+    pub output_extension: Option<String>,
+    /// replace the `.md` extension of link targets by output_extension
+    pub rewrite_link_extensions: Option<bool>,
+    /// fail on constructs the zk parser does not support instead of keeping them as text, only
+    /// affects zk input
+    pub strict: Option<bool>,
+    /// keep blocks that fail to parse as text instead of failing the whole note
+    pub best_effort: Option<bool>,
+    /// replace dataview queries by static lists of links to the matching notes
+    pub materialize_dataview: Option<bool>,
+    /// drop comments instead of converting them to html comments
+    pub strip_comments: Option<bool>,
+    /// deeper headings are converted to headings of this level
+    pub max_heading_level: Option<u16>,
+    /// renumber headings so that their levels never skip one
+    pub normalize_headings: Option<bool>,
+    /// rewrite file links to paths relative to the root of the converted tree
+    pub canonical_links: Option<bool>,
+    /// add an `original_path` property with the path of the note relative to the converted tree
+    pub emit_source_path: Option<bool>,
+    /// render embeds of notes as links in logseq output
+    pub embeds_as_links: Option<bool>,
+    /// add `collapsed:: true` to logseq blocks with children nested at least this deep
+    /// (top-level blocks: 0)
+    pub collapse_depth: Option<usize>,
+    /// render admonition titles in logseq output as `bold` text in the quote or as a `heading`
+    /// above it (default: bold)
+    pub callout_title_style: Option<CalloutTitleStyle>,
+    /// replacements applied to notes before parsing, in addition to the built-in ones
+    pub substitutions: Option<Vec<(String, String)>>,
+    /// apply only the configured substitutions instead of adding them to the built-in table
+    pub replace_default_substitutions: Option<bool>,
+    /// replacements applied to the converted text, e.g. to fix escaping in math
+    pub output_substitutions: Option<Vec<OutputSubstitution>>,
+    /// encoding of the notes, `utf8`, `utf16le` or `utf16be` (default: utf8)
+    pub input_encoding: Option<InputEncoding>,
+    /// recreate directories without notes in the output
+    pub preserve_empty_dirs: Option<bool>,
+    /// replace embeds of notes by their content
+    pub follow_embeds: Option<bool>,
+    /// end converted files with a single newline instead of none (default: true)
+    pub trailing_newline: Option<bool>,
+    /// line terminator of the converted files, `lf` or `crlf` (default: lf)
+    pub line_ending: Option<LineEnding>,
+    /// frontmatter entries that are removed before converting
+    pub drop_frontmatter_keys: Option<Vec<String>>,
+    /// if set, all other frontmatter entries are removed before converting
+    pub keep_frontmatter_keys: Option<Vec<String>>,
+}
+
+/// literal replacement applied to the text rendered when converting from `from` to `to`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OutputSubstitution {
+    pub from: TextMode,
+    pub to: TextMode,
+    pub find: String,
+    pub replace: String,
+}
+
+impl ConvertConfig {
+    pub const FILE_NAME: &str = "pkmt-convert.toml";
+    pub const DEFAULT_PROPERTY_ORDER: [&str; 2] = ["tags", "date"];
+
+    fn parse(file: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(file).context(format!("Failed to read {file:?}"))?;
+        toml::from_str(&text).context(format!("Failed to parse {file:?}"))
+    }
+
+    /// reads a property map file with one `old_name = "new_name"` entry per line.
+    /// If reverse is set, the map is applied in the opposite direction.
+    pub fn from_property_map_file(file: &Path, reverse: bool) -> Result<Self> {
+        let text = std::fs::read_to_string(file).context(format!("Failed to read {file:?}"))?;
+        let map: BTreeMap<String, String> =
+            toml::from_str(&text).context(format!("Failed to parse {file:?}"))?;
+        let map = if reverse {
+            let mut reversed = BTreeMap::new();
+            for (from, to) in map {
+                if let Some(other) = reversed.insert(to.clone(), from.clone()) {
+                    bail!("Cannot reverse {file:?}: both {other:?} and {from:?} map to {to:?}");
+                }
+            }
+            reversed
+        } else {
+            map
+        };
+        Ok(Self {
+            property_map: Some(map),
+            ..Default::default()
+        })
+    }
+
+    /// options set in self take precedence over the ones in parent
+    pub fn merge(self, parent: ConvertConfig) -> Self {
+        let property_map = match (parent.property_map, self.property_map) {
+            (Some(mut parent_map), Some(map)) => {
+                parent_map.extend(map);
+                Some(parent_map)
+            }
+            (parent_map, map) => map.or(parent_map),
+        };
+        let output_substitutions = match (parent.output_substitutions, self.output_substitutions) {
+            (Some(mut parent_subs), Some(subs)) => {
+                parent_subs.extend(subs);
+                Some(parent_subs)
+            }
+            (parent_subs, subs) => subs.or(parent_subs),
+        };
+        Self {
+            indent_width: self.indent_width.or(parent.indent_width),
+            lowercase_tags: self.lowercase_tags.or(parent.lowercase_tags),
+            property_map,
+            flatten_outline: self.flatten_outline.or(parent.flatten_outline),
+            link_style: self.link_style.or(parent.link_style),
+            tag_style: self.tag_style.or(parent.tag_style),
+            nested_tag_style: self.nested_tag_style.or(parent.nested_tag_style),
+            frontmatter_list_style: self
+                .frontmatter_list_style
+                .or(parent.frontmatter_list_style),
+            dedupe_property_values: self
+                .dedupe_property_values
+                .or(parent.dedupe_property_values),
+            sort_properties: self.sort_properties.or(parent.sort_properties),
+            property_order: self.property_order.or(parent.property_order),
+            preserve_blank_lines: self.preserve_blank_lines.or(parent.preserve_blank_lines),
+            output_extension: self.output_extension.or(parent.output_extension),
+            rewrite_link_extensions: self
+                .rewrite_link_extensions
+                .or(parent.rewrite_link_extensions),
+            strict: self.strict.or(parent.strict),
+            best_effort: self.best_effort.or(parent.best_effort),
+            materialize_dataview: self.materialize_dataview.or(parent.materialize_dataview),
+            strip_comments: self.strip_comments.or(parent.strip_comments),
+            max_heading_level: self.max_heading_level.or(parent.max_heading_level),
+            normalize_headings: self.normalize_headings.or(parent.normalize_headings),
+            canonical_links: self.canonical_links.or(parent.canonical_links),
+            emit_source_path: self.emit_source_path.or(parent.emit_source_path),
+            embeds_as_links: self.embeds_as_links.or(parent.embeds_as_links),
+            collapse_depth: self.collapse_depth.or(parent.collapse_depth),
+            callout_title_style: self.callout_title_style.or(parent.callout_title_style),
+            substitutions: self.substitutions.or(parent.substitutions),
+            replace_default_substitutions: self
+                .replace_default_substitutions
+                .or(parent.replace_default_substitutions),
+            output_substitutions,
+            input_encoding: self.input_encoding.or(parent.input_encoding),
+            preserve_empty_dirs: self.preserve_empty_dirs.or(parent.preserve_empty_dirs),
+            follow_embeds: self.follow_embeds.or(parent.follow_embeds),
+            trailing_newline: self.trailing_newline.or(parent.trailing_newline),
+            line_ending: self.line_ending.or(parent.line_ending),
+            drop_frontmatter_keys: self.drop_frontmatter_keys.or(parent.drop_frontmatter_keys),
+            keep_frontmatter_keys: self.keep_frontmatter_keys.or(parent.keep_frontmatter_keys),
+        }
+    }
+
+    /// the substitutions applied to notes before parsing: the built-in ones followed by the
+    /// configured ones, or only the configured ones if replace_default_substitutions is set
+    pub fn substitutions(&self) -> Vec<(String, String)> {
+        let configured = self.substitutions.clone().unwrap_or_default();
+        if self.replace_default_substitutions == Some(true) {
+            configured
+        } else {
+            default_substitutions()
+                .into_iter()
+                .chain(configured)
+                .collect()
+        }
+    }
+
+    pub fn property_order(&self) -> Vec<String> {
+        self.property_order.clone().unwrap_or_else(|| {
+            ConvertConfig::DEFAULT_PROPERTY_ORDER
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        })
+    }
+
+    /// output_extension without a leading `.`
+    pub fn output_extension(&self) -> Option<String> {
+        self.output_extension
+            .as_ref()
+            .map(|ext| ext.trim_start_matches('.').to_string())
+    }
+
+    /// merges all config files from dir upwards to root_dir
+    pub fn resolve(root_dir: &Path, dir: &Path) -> Result<Self> {
+        let mut res = ConvertConfig::default();
+        let mut current = Some(dir);
+        while let Some(d) = current {
+            let config_file = d.join(ConvertConfig::FILE_NAME);
+            if config_file.exists() {
+                res = res.merge(ConvertConfig::parse(&config_file)?);
+            }
+            if d == root_dir {
+                break;
+            }
+            current = d.parent().filter(|p| p.starts_with(root_dir));
+        }
+        Ok(res)
+    }
+
+    pub fn apply_to_document(&self, pd: &mut ParsedDocument) {
+        if self.flatten_outline == Some(true) {
+            flatten_outline(pd.components_mut());
+        }
+        if self.normalize_headings == Some(true) {
+            normalize_heading_levels(pd);
+        }
+        if self.drop_frontmatter_keys.is_some() || self.keep_frontmatter_keys.is_some() {
+            self.filter_frontmatter(pd.components_mut());
+        }
+        if let Some(map) = &self.property_map {
+            pd.for_each_property_mut(&mut |p| {
+                if let Some(name) = map.get(p.name()) {
+                    p.set_name(name.clone());
+                }
+            });
+        }
+        if self.lowercase_tags == Some(true) {
+            pd.for_each_property_mut(&mut |p| {
+                if p.has_name("tags") {
+                    p.values.iter_mut().for_each(|v| {
+                        if let PropValue::String(s) = v {
+                            *s = s.to_lowercase();
+                        }
+                    });
+                }
+            });
+        }
+        if self.dedupe_property_values == Some(true) {
+            dedupe_property_values(pd);
+        }
+        if self.sort_properties == Some(true) {
+            sort_properties(pd, &self.property_order());
+        }
+    }
+
+    /// with exploded nested tags, adds all levels of the nested inline tags to the `tags` entry of
+    /// the frontmatter of zk and obsidian output, e.g. `a` and `a/b` for `#a/b`. The inline tags
+    /// themselves are kept
+    pub fn explode_inline_tags(&self, pd: &mut ParsedDocument, outmode: &TextMode) {
+        if self.nested_tag_style != Some(NestedTagStyle::Explode) || *outmode == TextMode::LogSeq {
+            return;
+        }
+        let mut tags: Vec<String> = vec![];
+        crate::tags::inline_tags_in_document(pd)
+            .iter()
+            .filter(|t| t.contains('/'))
+            .flat_map(|t| NestedTagStyle::Explode.expand(t))
+            .for_each(|t| {
+                if !tags.contains(&t) {
+                    tags.push(t);
+                }
+            });
+        if tags.is_empty() {
+            return;
+        }
+        let values = |tags: Vec<String>| -> Vec<PropValue> {
+            tags.into_iter().map(PropValue::String).collect()
+        };
+        let frontmatter = pd.components_mut().iter_mut().find_map(|c| match c {
+            DocumentComponent::Frontmatter(props) => Some(props),
+            _ => None,
+        });
+        match frontmatter {
+            Some(props) => match props.iter_mut().find(|p| p.has_name("tags")) {
+                Some(p) => {
+                    let present = crate::tags::tags_in_document(&ParsedDocument::ParsedText(vec![
+                        DocumentComponent::Frontmatter(vec![p.clone()]),
+                    ]));
+                    tags.retain(|t| !present.contains(t));
+                    p.add_values(&values(tags));
+                }
+                None => props.push(Property::new("tags".to_string(), false, values(tags))),
+            },
+            None => pd.insert_component(
+                0,
+                DocumentComponent::Frontmatter(vec![Property::new(
+                    "tags".to_string(),
+                    false,
+                    values(tags),
+                )]),
+            ),
+        }
+    }
+
+    /// removes the frontmatter entries that are dropped or not kept, frontmatter without entries
+    /// is removed entirely
+    fn filter_frontmatter(&self, components: &mut Vec<DocumentComponent>) {
+        let keep = |p: &Property| {
+            self.keep_frontmatter_keys
+                .as_ref()
+                .is_none_or(|keys| keys.iter().any(|k| p.has_name(k)))
+                && !self
+                    .drop_frontmatter_keys
+                    .iter()
+                    .flatten()
+                    .any(|k| p.has_name(k))
+        };
+        components.retain_mut(|c| match c {
+            DocumentComponent::Frontmatter(props) => {
+                props.retain(keep);
+                !props.is_empty()
+            }
+            _ => true,
+        });
+    }
+
+    /// applies the output substitutions configured for converting inmode to outmode
+    pub fn apply_output_substitutions(
+        &self,
+        text: &str,
+        inmode: &TextMode,
+        outmode: &TextMode,
+    ) -> String {
+        self.output_substitutions
+            .iter()
+            .flatten()
+            .filter(|s| s.from == *inmode && s.to == *outmode)
+            .fold(text.to_string(), |text, s| {
+                text.replace(&s.find, &s.replace)
+            })
+    }
+
+    /// ends text with exactly one newline, or none if trailing_newline is disabled
+    pub fn apply_trailing_newline(&self, text: &str) -> String {
+        let text = text.trim_end_matches(['\n', '\r']);
+        if self.trailing_newline.unwrap_or(true) {
+            format!("{text}\n")
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn apply_line_ending(&self, text: &str) -> String {
+        self.line_ending.unwrap_or_default().apply(text)
+    }
+
+    /// re-indents the text to indent_width or, if it is not set, to the indentation unit detected
+    /// in the source note. Lines in code fences keep their indentation relative to the opening
+    /// fence
+    pub fn apply_to_text(&self, text: &str, detected: Option<IndentUnit>) -> String {
+        let Some(unit) = self.indent_width.map(IndentUnit::Spaces).or(detected) else {
+            return text.to_string();
+        };
+        if unit == IndentUnit::Spaces(SPACES_PER_INDENT) {
+            return text.to_string();
+        }
+        // original indentation and new indent of the opening line of the current code fence
+        let mut fence: Option<(usize, String)> = None;
+        text.lines()
+            .map(|l| {
+                let spaces = l.chars().take_while(|c| *c == ' ').count();
+                let is_fence = l[spaces..].starts_with("```");
+                let indent = match fence.clone() {
+                    Some((old, new)) => {
+                        if is_fence {
+                            fence = None;
+                        }
+                        if spaces < old {
+                            return l.to_string();
+                        }
+                        format!("{new}{}", " ".repeat(spaces - old))
+                    }
+                    None => {
+                        let indent = format!(
+                            "{}{}",
+                            unit.to_text().repeat(spaces / SPACES_PER_INDENT),
+                            " ".repeat(spaces % SPACES_PER_INDENT)
+                        );
+                        if is_fence {
+                            fence = Some((spaces, indent.clone()));
+                        }
+                        indent
+                    }
+                };
+                format!("{indent}{}", &l[spaces..])
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// replaces top-level lists that consist of a single item without children and containing only
+/// inline text by the contents of the item
+fn flatten_outline(components: &mut Vec<DocumentComponent>) {
+    let count = components.len();
+    *components = std::mem::take(components)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, c)| match c {
+            DocumentComponent::List(mut list_elements, terminated)
+                if list_elements.len() == 1 && is_prose(&list_elements[0]) =>
+            {
+                let mut res = list_elements.remove(0).contents.into_components();
+                // keep the following components in separate paragraphs
+                if i + 1 < count {
+                    res.push(DocumentComponent::Text(
+                        if terminated { "\n\n" } else { "\n" }.to_string(),
+                    ));
+                }
+                res
+            }
+            c => vec![c],
+        })
+        .collect();
+}
+
+fn is_prose(le: &ListElem) -> bool {
+    use DocumentComponent::*;
+    let components = le.contents.components();
+    le.children.is_empty()
+        && components
+            .iter()
+            .all(|c| matches!(c, Text(_) | Tag(_) | Url(..) | FileLink(..) | FileEmbed(..)))
+        && components
+            .iter()
+            .any(|c| matches!(c, Text(t) if !t.trim().is_empty()))
+}
+
+#[test]
+fn test_indent_width_keeps_code_blocks() {
+    let config = ConvertConfig {
+        indent_width: Some(2),
+        ..Default::default()
+    };
+    let text =
+        "- a\n    - b\n      ```python\n      def f():\n          return 1\n      ```\n    - c";
+    assert_eq!(
+        config.apply_to_text(text, Some(IndentUnit::Tab)),
+        "- a\n  - b\n    ```python\n    def f():\n        return 1\n    ```\n  - c"
+    );
+    // without indent_width, the unit detected in the source is used
+    assert_eq!(
+        ConvertConfig::default().apply_to_text(text, Some(IndentUnit::Tab)),
+        "- a\n\t- b\n\t  ```python\n\t  def f():\n\t      return 1\n\t  ```\n\t- c"
+    );
+    assert_eq!(ConvertConfig::default().apply_to_text(text, None), text);
+}
+
+#[test]
+fn test_subdirectory_config_overrides_indent() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let text = "- a\n    - b\n      tags:: Fitness";
+    let files = [
+        (ConvertConfig::FILE_NAME, "lowercase_tags = true\n"),
+        ("sub/pkmt-convert.toml", "indent_width = 2\n"),
+        ("a.md", text),
+        ("sub/b.md", text),
+    ];
+    let options = TreeOptions::new(TextMode::LogSeq, TextMode::LogSeq);
+
+    let out = convert_fixture(&files, &options, &ConvertConfig::default());
+    assert_eq!(
+        out[Path::new("a.md")],
+        "- a\n    - b\n      tags:: #fitness\n"
+    );
+    assert_eq!(
+        out[Path::new("sub/b.md")],
+        "- a\n  - b\n    tags:: #fitness\n"
+    );
+
+    // options given on the command line take precedence over the config files
+    let config = ConvertConfig {
+        indent_width: Some(4),
+        ..Default::default()
+    };
+    let out = convert_fixture(&files, &options, &config);
+    assert_eq!(
+        out[Path::new("sub/b.md")],
+        "- a\n    - b\n      tags:: #fitness\n"
+    );
+}
+
+#[test]
+fn test_substitutions() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let convert = |config_file: &str| {
+        let files = [
+            (ConvertConfig::FILE_NAME, config_file),
+            ("a.md", "it’s a → b"),
+        ];
+        let options = TreeOptions::new(TextMode::Zk, TextMode::Zk);
+        convert_fixture(&files, &options, &ConvertConfig::default())[Path::new("a.md")].clone()
+    };
+    // the configured entries are added to the built-in ones
+    assert_eq!(
+        convert("substitutions = [[\"→\", \"->\"]]\n"),
+        "it's a -> b\n"
+    );
+    assert_eq!(
+        convert("substitutions = [[\"→\", \"->\"]]\nreplace_default_substitutions = true\n"),
+        "it’s a -> b\n"
+    );
+}
+
+#[test]
+fn test_property_map() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let map_file = dir_tmp.path().join("map.toml");
+    std::fs::write(&map_file, "channel = \"authors\"\n").unwrap();
+
+    let config = ConvertConfig::from_property_map_file(&map_file, false).unwrap();
+    let out = convert_fixture(
+        &[("a.md", "# A\nchannel ::= x\n")],
+        &TreeOptions::new(TextMode::Zk, TextMode::LogSeq),
+        &config,
+    );
+    let text = &out[Path::new("a.md")];
+    assert!(text.contains("authors:: x"), "{text:?}");
+    assert!(!text.contains("channel"), "{text:?}");
+
+    let reversed = ConvertConfig::from_property_map_file(&map_file, true).unwrap();
+    assert_eq!(
+        reversed.property_map,
+        Some(BTreeMap::from([(
+            "authors".to_string(),
+            "channel".to_string()
+        )]))
+    );
+}
+
+#[test]
+fn test_flatten_outline() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let config = ConvertConfig {
+        flatten_outline: Some(true),
+        ..Default::default()
+    };
+    let out = convert_fixture(
+        &[
+            ("a.md", "- A longer paragraph of prose with a [[link]]."),
+            ("b.md", "- a\n- b\n    - c"),
+        ],
+        &TreeOptions::new(TextMode::LogSeq, TextMode::Zk),
+        &config,
+    );
+    assert_eq!(
+        out[Path::new("a.md")],
+        "A longer paragraph of prose with a [link](link).\n"
+    );
+    assert_eq!(out[Path::new("b.md")], "- a\n- b\n    - c\n");
+}
+
+#[test]
+fn test_output_substitutions() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let convert = |inmode: TextMode| {
+        let files = [
+            (
+                ConvertConfig::FILE_NAME,
+                "[[output_substitutions]]\nfrom = \"zk\"\nto = \"logseq\"\nfind = '\\{'\nreplace = '\\\\{'\n",
+            ),
+            ("a.md", "the set $\\{x\\}$"),
+        ];
+        let options = TreeOptions::new(inmode, TextMode::LogSeq);
+        convert_fixture(&files, &options, &ConvertConfig::default())[Path::new("a.md")].clone()
+    };
+    assert_eq!(convert(TextMode::Zk), "- the set $\\\\{x\\}$\n");
+    assert_eq!(convert(TextMode::LogSeq), "- the set $\\{x\\}$\n");
+}
+
+#[test]
+fn test_trailing_newline() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let convert = |outmode: TextMode, trailing_newline: Option<bool>| {
+        let config = ConvertConfig {
+            trailing_newline,
+            ..Default::default()
+        };
+        let options = TreeOptions::new(TextMode::LogSeq, outmode);
+        convert_fixture(&[("a.md", "- a\n- b\n\n\n")], &options, &config)[Path::new("a.md")].clone()
+    };
+    for outmode in [TextMode::LogSeq, TextMode::Zk] {
+        assert_eq!(convert(outmode.clone(), None), "- a\n- b\n");
+        assert_eq!(convert(outmode, Some(false)), "- a\n- b");
+    }
+}
+
+#[test]
+fn test_line_ending() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let convert = |line_ending: Option<LineEnding>| {
+        let config = ConvertConfig {
+            line_ending,
+            ..Default::default()
+        };
+        let options = TreeOptions::new(TextMode::Zk, TextMode::Zk);
+        convert_fixture(&[("a.md", "# Note\n- a\n- b\n")], &options, &config)[Path::new("a.md")]
+            .clone()
+    };
+    let lf = convert(None);
+    assert!(!lf.contains('\r'));
+    let crlf = convert(Some(LineEnding::Crlf));
+    assert_eq!(crlf, lf.replace('\n', "\r\n"));
+    assert!(crlf.ends_with("- b\r\n"));
+}
+
+#[test]
+fn test_frontmatter_keys() {
+    use crate::{
+        document_component::{TreeOptions, convert_fixture},
+        parsing::TextMode,
+    };
+    let convert = |config: &ConvertConfig| {
+        let files = [(
+            "a.md",
+            "---\ndate: 2024-01-02\nid: abc\nurl: https://example.com\n---\n\n- text\n",
+        )];
+        let options = TreeOptions::new(TextMode::Zk, TextMode::LogSeq);
+        convert_fixture(&files, &options, config)[Path::new("a.md")].clone()
+    };
+    let text = convert(&ConvertConfig::default());
+    assert_eq!(
+        text,
+        "date:: 2024-01-02\nid:: abc\nurl:: https://example.com\n\n- text\n"
+    );
+
+    let text = convert(&ConvertConfig {
+        drop_frontmatter_keys: Some(vec!["id".to_string()]),
+        ..Default::default()
+    });
+    assert!(!text.contains("id::"), "{text:?}");
+    assert!(text.contains("date:: 2024-01-02"), "{text:?}");
+
+    let text = convert(&ConvertConfig {
+        keep_frontmatter_keys: Some(vec!["url".to_string()]),
+        ..Default::default()
+    });
+    assert_eq!(text, "url:: https://example.com\n\n- text\n");
+}
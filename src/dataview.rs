@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::{
+    document_component::{DocumentComponent, ListElem, MentionedFile, ParsedDocument},
+    inspect::TreeIndex,
+    tags::tags_in_document,
+};
+
+/// evaluates a dataview query against the parsed notes of a tree. Only `LIST FROM #tag` is
+/// supported, it returns the notes with the tag or one of its nested tags (`#tag/sub`).
+/// Returns None for unsupported queries.
+pub fn evaluate_dataview(query: &str, index: &TreeIndex) -> Option<Vec<PathBuf>> {
+    let list_re = Regex::new(r"(?i)^\s*LIST\s+FROM\s+#([^\s#]+)\s*$").unwrap();
+    let tag = list_re.captures(query)?[1].to_string();
+    let nested = format!("{tag}/");
+    Some(
+        index
+            .iter()
+            .filter_map(|(f, pd)| pd.as_ref().ok().map(|pd| (f, pd)))
+            .filter(|(_, pd)| {
+                tags_in_document(pd)
+                    .iter()
+                    .any(|t| *t == tag || t.starts_with(&nested))
+            })
+            .map(|(f, _)| f.clone())
+            .collect(),
+    )
+}
+
+/// replaces `dataview` code blocks by a list of links to the notes matching the query.
+/// Unsupported queries are kept as code blocks and a warning is printed.
+pub fn materialize_dataview(pd: &mut ParsedDocument, index: &TreeIndex) {
+    materialize_components(pd.components_mut(), index);
+}
+
+fn materialize_components(components: &mut [DocumentComponent], index: &TreeIndex) {
+    use DocumentComponent::*;
+    components.iter_mut().for_each(|c| match c {
+        CodeBlock(query, Some(code_type)) if code_type == "dataview" => {
+            match evaluate_dataview(query, index) {
+                Some(notes) => *c = link_list(notes),
+                None => eprintln!("Warning: unsupported dataview query kept as code: {query:?}"),
+            }
+        }
+        Admonition(comps, _) => materialize_components(comps, index),
+        List(list_elements, _) => list_elements
+            .iter_mut()
+            .for_each(|le| materialize_list_elem(le, index)),
+        _ => {}
+    });
+}
+
+fn materialize_list_elem(le: &mut ListElem, index: &TreeIndex) {
+    materialize_components(le.contents.components_mut(), index);
+    le.children
+        .iter_mut()
+        .for_each(|c| materialize_list_elem(c, index));
+}
+
+fn link_list(notes: Vec<PathBuf>) -> DocumentComponent {
+    let list_elements = notes
+        .into_iter()
+        .map(|note| {
+            ListElem::new(ParsedDocument::ParsedText(vec![
+                DocumentComponent::FileLink(MentionedFile::FilePath(note), None, None),
+            ]))
+        })
+        .collect();
+    DocumentComponent::List(list_elements, false)
+}
+
+#[test]
+fn test_materialize_dataview() {
+    use crate::{inspect::parse_tree, parsing::TextMode};
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.md"), "first #project").unwrap();
+    std::fs::write(dir.join("b.md"), "nested #project/sub").unwrap();
+    std::fs::write(dir.join("c.md"), "other #projects").unwrap();
+    std::fs::write(
+        dir.join("index.md"),
+        "# Projects\n```dataview\nLIST FROM #project\n```\n```dataview\nTABLE file.ctime FROM #project\n```",
+    )
+    .unwrap();
+    let dir = dir.canonicalize().unwrap();
+    let index = parse_tree(&dir, &TextMode::Obsidian).unwrap();
+
+    assert_eq!(
+        evaluate_dataview("list from #project", &index),
+        Some(vec![dir.join("a.md"), dir.join("b.md")])
+    );
+    assert_eq!(evaluate_dataview("LIST FROM \"folder\"", &index), None);
+
+    let mut pd = index[&dir.join("index.md")].as_ref().unwrap().clone();
+    materialize_dataview(&mut pd, &index);
+    let links = pd.get_all_document_components(&|dc| matches!(dc, DocumentComponent::FileLink(..)));
+    assert_eq!(
+        links,
+        vec![
+            DocumentComponent::FileLink(MentionedFile::FilePath(dir.join("a.md")), None, None),
+            DocumentComponent::FileLink(MentionedFile::FilePath(dir.join("b.md")), None, None),
+        ]
+    );
+    // the unsupported query is kept
+    assert!(matches!(
+        pd.components().last(),
+        Some(DocumentComponent::CodeBlock(_, Some(t))) if t == "dataview"
+    ));
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use clap::{ValueEnum, builder::PossibleValue};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::inspect::{
+    dangling_links_in, empty_files, missing_frontmatter_fields_in, orphan_notes_in, parse_tree,
+    syntax_issues,
+};
+use crate::parsing::TextMode;
+use crate::util::files_in_tree;
+
+/// how urgent the issues of a check are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl ValueEnum for Severity {
+    fn value_variants<'a>() -> &'a [Self] {
+        use Severity::*;
+        &[Info, Warning, Critical]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use Severity::*;
+        Some(match self {
+            Info => PossibleValue::new("info"),
+            Warning => PossibleValue::new("warning"),
+            Critical => PossibleValue::new("critical"),
+        })
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().unwrap();
+        write!(f, "{}", value.get_name())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DoctorOptions {
+    /// frontmatter fields every note must have, not checked if empty
+    pub required_frontmatter: Vec<String>,
+}
+
+impl DoctorOptions {
+    pub fn with_required_frontmatter(mut self, required: Vec<String>) -> Self {
+        self.required_frontmatter = required;
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub severity: Severity,
+    /// affected file and a description of the issue (empty if the name says it all)
+    pub issues: Vec<(PathBuf, String)>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DoctorReport {
+    /// most severe checks first
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    fn add(&mut self, name: &str, severity: Severity, issues: Vec<(PathBuf, String)>) {
+        self.checks.push(DoctorCheck {
+            name: name.to_string(),
+            severity,
+            issues,
+        });
+    }
+
+    /// number of issues with exactly this severity
+    pub fn count(&self, severity: Severity) -> usize {
+        self.checks
+            .iter()
+            .filter(|c| c.severity == severity)
+            .map(|c| c.issues.len())
+            .sum()
+    }
+
+    /// true if there are issues with at least this severity
+    pub fn has_issues(&self, min_severity: Severity) -> bool {
+        self.checks
+            .iter()
+            .any(|c| c.severity >= min_severity && !c.issues.is_empty())
+    }
+
+    pub fn print(&self) {
+        for check in self.checks.iter().filter(|c| !c.issues.is_empty()) {
+            println!(
+                "[{}] {} ({})",
+                check.severity,
+                check.name,
+                check.issues.len()
+            );
+            check.issues.iter().for_each(|(f, issue)| {
+                if issue.is_empty() {
+                    println!("\t{f:?}");
+                } else {
+                    println!("\t{f:?}: {issue}");
+                }
+            });
+        }
+        println!(
+            "{} critical, {} warnings, {} info",
+            self.count(Severity::Critical),
+            self.count(Severity::Warning),
+            self.count(Severity::Info)
+        );
+    }
+}
+
+/// runs all inspections on the tree. Notes that cannot be read or parsed are reported as
+/// critical and left out of the other checks.
+pub fn doctor<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    opts: &DoctorOptions,
+) -> Result<DoctorReport> {
+    use Severity::*;
+    let root_dir = root_dir.as_ref();
+    let index = parse_tree(root_dir, mode)?;
+    let mut report = DoctorReport::default();
+
+    let unparsable = index
+        .iter()
+        .filter_map(|(f, pd)| pd.as_ref().err().map(|e| (f.clone(), format!("{e:#}"))))
+        .collect();
+    report.add("unreadable notes", Critical, unparsable);
+
+    let mut syntax = vec![];
+    for f in files_in_tree(root_dir, &Some(vec!["md"]), None)? {
+        if let Ok(text) = std::fs::read_to_string(&f) {
+            syntax_issues(&text, mode)
+                .into_iter()
+                .for_each(|issue| syntax.push((f.clone(), issue)));
+        }
+    }
+    report.add("unbalanced syntax", Critical, syntax);
+
+    report.add("dangling links", Warning, dangling_links_in(&index));
+    if !opts.required_frontmatter.is_empty() {
+        let missing = missing_frontmatter_fields_in(&index, &opts.required_frontmatter)
+            .into_iter()
+            .map(|(f, missing)| (f, format!("missing {}", missing.join(", "))))
+            .collect();
+        report.add("missing frontmatter", Warning, missing);
+    }
+
+    let (empty, _) = empty_files(root_dir, true)?;
+    report.add(
+        "empty notes",
+        Info,
+        empty.into_iter().map(|f| (f, String::new())).collect(),
+    );
+    report.add(
+        "orphan notes",
+        Info,
+        orphan_notes_in(&index)
+            .into_iter()
+            .map(|f| (f, String::new()))
+            .collect(),
+    );
+
+    report.checks.sort_by_key(|c| std::cmp::Reverse(c.severity));
+    Ok(report)
+}
+
+#[test]
+fn test_doctor() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("empty.md"), "-").unwrap();
+    std::fs::write(
+        dir.join("index.md"),
+        "see [the note](note.md) and [gone](missing.md)",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("note.md"),
+        "back to [index](index.md)\n```\nunclosed",
+    )
+    .unwrap();
+
+    let report = doctor(&dir, &TextMode::Zk, &DoctorOptions::default()).unwrap();
+    let issues = |name: &str| {
+        report
+            .checks
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.issues.clone())
+            .unwrap()
+    };
+    assert_eq!(
+        issues("dangling links"),
+        vec![(dir.join("index.md"), "missing.md".to_string())]
+    );
+    assert_eq!(
+        issues("empty notes"),
+        vec![(dir.join("empty.md"), String::new())]
+    );
+    assert_eq!(issues("unbalanced syntax").len(), 1);
+    assert_eq!(
+        issues("orphan notes"),
+        vec![(dir.join("empty.md"), String::new())]
+    );
+    assert_eq!(report.checks[0].severity, Severity::Critical);
+    assert_eq!(report.count(Severity::Critical), 1);
+    assert!(report.has_issues(Severity::Critical));
+    assert!(
+        !report
+            .checks
+            .iter()
+            .any(|c| c.name == "missing frontmatter")
+    );
+}
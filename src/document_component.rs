@@ -1,25 +1,64 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{Debug, Display, Formatter},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use anyhow::{Context, Result, bail};
+use chrono::NaiveDate;
+use clap::{ValueEnum, builder::PossibleValue};
+use serde::Serialize;
 use tracing::{debug, instrument};
 
 use crate::{
-    parsing::{self, TextMode, parse_file},
+    convert_config::ConvertConfig,
+    dataview::materialize_dataview,
+    embeds::{MAX_EMBED_DEPTH, inline_embeds, resolve_note},
+    error::PkmtError,
+    inspect::{TreeIndex, parse_tree},
+    parsing::{
+        self, CalloutTitleStyle, FrontmatterFormat, FrontmatterListStyle, LinkStyle,
+        NestedTagStyle, TagStyle, TextMode, md_parsing::DefinitionEntries, parse_file_with,
+    },
     util::{
-        self, SPACES_PER_INDENT, ends_with_blank_line, files_in_tree, indent_spaces,
-        starts_with_blank_line, trim_like_first_line_plus,
+        self, IndentUnit, SPACES_PER_INDENT, dirs_in_tree, ends_with_blank_line, files_in_tree,
+        indent_spaces, starts_with_blank_line, trim_like_first_line_plus,
     },
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct FileInfo {
     original_file: PathBuf,
     destination_file: Option<PathBuf>,
     image_dirs: Option<(PathBuf, PathBuf)>,
+    frontmatter_format: FrontmatterFormat,
+    /// rendering of multi-value entries of yaml frontmatter
+    frontmatter_list_style: FrontmatterListStyle,
+    /// overrides the link syntax of the output format
+    link_style: Option<LinkStyle>,
+    /// overrides the tag style of the output format
+    tag_style: Option<TagStyle>,
+    /// keep runs of blank lines between blocks instead of removing them in logseq output
+    preserve_blank_lines: bool,
+    /// extension replacing `.md` in link targets
+    link_extension: Option<String>,
+    /// drop comments instead of writing them as html comments
+    strip_comments: bool,
+    /// deeper headings are rendered with this level
+    max_heading_level: Option<u16>,
+    /// root of the converted tree, links are made relative to it if canonical links are enabled
+    vault_root: Option<PathBuf>,
+    /// render embeds of notes as links
+    embeds_as_links: bool,
+    /// logseq blocks with children nested at least this deep get `collapsed:: true`
+    collapse_depth: Option<usize>,
+    /// rendering of admonition titles in logseq output
+    callout_title_style: CalloutTitleStyle,
+    /// rendering of nested tags in tags properties
+    nested_tag_style: NestedTagStyle,
+    /// indentation of the lists in the source note, the output is indented with it
+    indent_unit: Option<IndentUnit>,
 }
 
 impl FileInfo {
@@ -44,25 +83,202 @@ impl FileInfo {
         image_in_dir: Option<PathBuf>,
         image_out_dir: Option<PathBuf>,
     ) -> Result<Self> {
-        match (image_in_dir, image_out_dir) {
-            (Some(image_in), Some(image_out)) => Ok(FileInfo {
-                original_file,
-                destination_file,
-                image_dirs: Some((image_in, image_out)),
-            }),
-            (None, None) => Ok(FileInfo {
-                original_file,
-                destination_file,
-                image_dirs: None,
-            }),
+        let image_dirs = match (image_in_dir, image_out_dir) {
+            (Some(image_in), Some(image_out)) => Some((image_in, image_out)),
+            (None, None) => None,
             _ => bail!(
                 "Image input directory and image output directory need to be either both set or unset, but got mixture!"
             ),
+        };
+        Ok(FileInfo {
+            original_file,
+            destination_file,
+            image_dirs,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_frontmatter_format(mut self, frontmatter_format: FrontmatterFormat) -> Self {
+        self.frontmatter_format = frontmatter_format;
+        self
+    }
+
+    pub fn with_frontmatter_list_style(mut self, style: FrontmatterListStyle) -> Self {
+        self.frontmatter_list_style = style;
+        self
+    }
+
+    pub fn with_link_style(mut self, link_style: Option<LinkStyle>) -> Self {
+        self.link_style = link_style;
+        self
+    }
+
+    pub fn with_tag_style(mut self, tag_style: Option<TagStyle>) -> Self {
+        self.tag_style = tag_style;
+        self
+    }
+
+    pub fn with_nested_tag_style(mut self, nested_tag_style: NestedTagStyle) -> Self {
+        self.nested_tag_style = nested_tag_style;
+        self
+    }
+
+    pub fn with_preserve_blank_lines(mut self, preserve_blank_lines: bool) -> Self {
+        self.preserve_blank_lines = preserve_blank_lines;
+        self
+    }
+
+    pub fn with_link_extension(mut self, link_extension: Option<String>) -> Self {
+        self.link_extension = link_extension;
+        self
+    }
+
+    pub fn with_strip_comments(mut self, strip_comments: bool) -> Self {
+        self.strip_comments = strip_comments;
+        self
+    }
+
+    pub fn with_max_heading_level(mut self, max_heading_level: Option<u16>) -> Self {
+        self.max_heading_level = max_heading_level;
+        self
+    }
+
+    pub fn with_vault_root(mut self, vault_root: Option<PathBuf>) -> Self {
+        self.vault_root = vault_root;
+        self
+    }
+
+    pub fn with_embeds_as_links(mut self, embeds_as_links: bool) -> Self {
+        self.embeds_as_links = embeds_as_links;
+        self
+    }
+
+    pub fn with_indent_unit(mut self, indent_unit: Option<IndentUnit>) -> Self {
+        self.indent_unit = indent_unit;
+        self
+    }
+
+    pub fn with_collapse_depth(mut self, collapse_depth: Option<usize>) -> Self {
+        self.collapse_depth = collapse_depth;
+        self
+    }
+
+    pub fn with_callout_title_style(mut self, callout_title_style: CalloutTitleStyle) -> Self {
+        self.callout_title_style = callout_title_style;
+        self
+    }
+
+    /// returns path relative to the directory of the destination file if it is known
+    fn relative_to_destination(&self, path: &Path) -> PathBuf {
+        if let Some(dest) = &self.destination_file
+            && let Some(parent) = dest.parent()
+            && let Some(rel) = pathdiff::diff_paths(path, parent)
+        {
+            debug!("determined relative path {rel:?}");
+            rel
+        } else {
+            path.to_path_buf()
+        }
+    }
+}
+
+fn link_style(file_info: &Option<FileInfo>) -> Option<LinkStyle> {
+    file_info.as_ref().and_then(|fi| fi.link_style)
+}
+
+/// replaces the `.md` extension of a link target if file_info sets another link extension
+fn link_target(target: &str, file_info: &Option<FileInfo>) -> String {
+    match (
+        target.strip_suffix(".md"),
+        file_info.as_ref().and_then(|fi| fi.link_extension.as_ref()),
+    ) {
+        (Some(stem), Some(ext)) => format!("{stem}.{ext}"),
+        _ => target.to_string(),
+    }
+}
+
+/// renders a logseq drawer, the same in all formats
+fn drawer(name: &str, contents: &str) -> String {
+    if contents.is_empty() {
+        format!(":{name}:\n:END:")
+    } else {
+        format!(":{name}:\n{contents}\n:END:")
+    }
+}
+
+/// renders a dataview-style inline field, the same in all formats
+fn inline_field(key: &str, value: &str) -> String {
+    if value.is_empty() {
+        format!("({key}::)")
+    } else {
+        format!("({key}:: {value})")
+    }
+}
+
+/// renders a comment as html comment, or as empty string if file_info strips comments
+fn html_comment(comment: &str, file_info: &Option<FileInfo>) -> String {
+    if file_info.as_ref().is_some_and(|fi| fi.strip_comments) {
+        String::new()
+    } else {
+        format!("<!--{comment}-->")
+    }
+}
+
+/// renders a heading, clamping its level to the maximum heading level of file_info
+fn heading(level: u16, title: &str, file_info: &Option<FileInfo>) -> String {
+    let level = file_info
+        .as_ref()
+        .and_then(|fi| fi.max_heading_level)
+        .map_or(level, |max| level.min(max.max(1)));
+    format!("{} {}", "#".repeat(level as usize), title.trim())
+}
+
+fn tag_style(file_info: &Option<FileInfo>, mode: &TextMode) -> TagStyle {
+    file_info
+        .as_ref()
+        .and_then(|fi| fi.tag_style)
+        .unwrap_or_else(|| TagStyle::of_mode(mode))
+}
+
+/// renders a link in the link style of file_info, falling back to markdown links
+fn styled_file_link(
+    file: &MentionedFile,
+    rename: &Option<String>,
+    file_info: &Option<FileInfo>,
+) -> String {
+    let target = match (file, file_info) {
+        (MentionedFile::FilePath(p), Some(file_info)) => file_info.relative_to_destination(p),
+        (MentionedFile::FilePath(p), None) => p.clone(),
+        (MentionedFile::FileName(name), _) => PathBuf::from(name),
+    };
+    let target = target.to_string_lossy();
+    match link_style(file_info).unwrap_or(LinkStyle::Markdown) {
+        LinkStyle::Wiki => {
+            let target = target.strip_suffix(".md").unwrap_or(&target);
+            if let Some(name) = rename {
+                format!("[[{target}|{name}]]")
+            } else {
+                format!("[[{target}]]")
+            }
+        }
+        LinkStyle::Markdown => {
+            let name = rename
+                .as_ref()
+                .map(|n| n.replace(['[', ']'], ""))
+                .unwrap_or_else(|| target.to_string());
+            if Path::new(target.as_ref()).extension().is_none() {
+                format!(
+                    "[{name}]({})",
+                    link_target(&format!("{target}.md"), file_info)
+                )
+            } else {
+                format!("[{name}]({})", link_target(&target, file_info))
+            }
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ParsedDocument {
     ParsedFile(Vec<DocumentComponent>, PathBuf),
     ParsedText(Vec<DocumentComponent>),
@@ -73,7 +289,8 @@ impl ParsedDocument {
     pub fn to_string(&self, outmode: TextMode, file_info: &Option<FileInfo>) -> String {
         use TextMode::*;
         match outmode {
-            Obsidian => todo!("Conversion to Obsidian is not implemented yet!"),
+            // obsidian notes are markdown like zk notes
+            Obsidian => self.to_zk_text(file_info),
             LogSeq => {
                 // TODO transform the parsed document
                 // A heading owns all subsequent parts until a heading of a lower level
@@ -104,6 +321,14 @@ impl ParsedDocument {
         }
     }
 
+    pub fn components_mut(&mut self) -> &mut Vec<DocumentComponent> {
+        use ParsedDocument::*;
+        match self {
+            ParsedFile(comps, _) => comps,
+            ParsedText(comps) => comps,
+        }
+    }
+
     pub fn into_components(self) -> Vec<DocumentComponent> {
         use ParsedDocument::*;
         match self {
@@ -119,6 +344,13 @@ impl ParsedDocument {
         }
     }
 
+    pub fn insert_component(&mut self, index: usize, component: DocumentComponent) {
+        match self {
+            ParsedDocument::ParsedFile(comps, _) => comps.insert(index, component),
+            ParsedDocument::ParsedText(comps) => comps.insert(index, component),
+        }
+    }
+
     pub fn get_document_component(
         &self,
         selector: &dyn Fn(&DocumentComponent) -> bool,
@@ -171,15 +403,43 @@ impl ParsedDocument {
         selector: &dyn Fn(&DocumentComponent) -> bool,
     ) -> Vec<DocumentComponent> {
         let mut res = vec![];
-        for comp in self.components() {
+        self.for_each_component(&mut |comp| {
             if selector(comp) {
                 res.push(comp.clone());
             }
-            let mut rec = comp.get_all_document_components(selector);
-            res.append(&mut rec);
+        });
+        res
+    }
+
+    /// whether a property, frontmatter entry or inline field called name has the given value
+    pub fn has_field(&self, name: &str, value: &str) -> bool {
+        let typed = PropValue::parse_typed(value);
+        let mut found = false;
+        self.for_each_component(&mut |c| match c {
+            DocumentComponent::Properties(props, _) | DocumentComponent::Frontmatter(props) => {
+                found |= props
+                    .iter()
+                    .any(|p| p.has_name(name) && p.has_value(&typed));
+            }
+            DocumentComponent::InlineField(key, val) => found |= key == name && val == value,
+            _ => {}
+        });
+        found
+    }
+
+    /// calls f on the components get_all_document_components selects from, without cloning them
+    pub fn for_each_component<'a>(&'a self, f: &mut dyn FnMut(&'a DocumentComponent)) {
+        for comp in self.components() {
+            f(comp);
+            comp.for_each_nested_component(f);
         }
+    }
 
-        res
+    /// calls f on all properties and frontmatter properties, including the ones in nested lists
+    pub fn for_each_property_mut(&mut self, f: &mut dyn FnMut(&mut Property)) {
+        self.components_mut()
+            .iter_mut()
+            .for_each(|c| c.for_each_property_mut(f));
     }
 
     pub fn with_components(&self, components: Vec<DocumentComponent>) -> ParsedDocument {
@@ -226,11 +486,26 @@ impl ParsedDocument {
             .flat_map(|c| c.mentioned_files().into_iter())
             .collect()
     }
+    /// returns the text of the document without markup, frontmatter and properties
+    pub fn to_plain_text(&self) -> String {
+        let mut res = String::new();
+        self.components().iter().for_each(|c| {
+            let text = c.to_plain_text();
+            if c.should_have_own_block() && !res.is_empty() && !res.ends_with('\n') {
+                res.push('\n');
+            }
+            res.push_str(&text);
+        });
+        res
+    }
+
     #[instrument]
     pub fn to_zk_text(&self, file_info: &Option<FileInfo>) -> String {
         let mut res = String::new();
+        let mut follows_heading = false;
         self.components().iter().for_each(|c| {
-            let cblock = c.should_have_own_block();
+            // text directly below a heading starts on its own line
+            let cblock = c.should_have_own_block() || follows_heading;
             let text = c.to_zk_text(file_info);
             if !res.is_empty()
                 && cblock
@@ -240,6 +515,7 @@ impl ParsedDocument {
                 res.push('\n');
             }
             res.push_str(&text);
+            follows_heading = matches!(c, DocumentComponent::Heading(..));
         });
         debug!("result: {res:?}");
 
@@ -248,9 +524,13 @@ impl ParsedDocument {
 
     #[instrument]
     pub fn to_logseq_text(&self, file_info: &Option<FileInfo>) -> String {
+        let preserve_blank_lines = file_info.as_ref().is_some_and(|fi| fi.preserve_blank_lines);
+        // blank lines preceding the next block if they are preserved
+        let mut blank_lines = 0;
         let mut res = String::new();
         let mut new_block = true;
         let mut heading_level_stack = vec![];
+        let mut follows_page_properties = false;
         self.components().iter().for_each(|c| {
             let is_heading = if let DocumentComponent::Heading(level, _) = c {
                 if heading_level_stack.is_empty() {
@@ -279,16 +559,50 @@ impl ParsedDocument {
                 false
             };
 
-            let text = c.to_logseq_text(file_info);
+            let mut text = c.to_logseq_text(file_info);
+            // page properties are not part of a block and separated from the first block
+            let is_page_properties = matches!(
+                c,
+                DocumentComponent::Properties(_, PropertyScope::Page)
+                    | DocumentComponent::Frontmatter(_)
+            );
+            let mut trailing_blank_lines = if is_page_properties { 1 } else { 0 };
+            if preserve_blank_lines {
+                match c {
+                    DocumentComponent::Text(_) if !text.trim().is_empty() => {
+                        let trimmed_len = text.trim_end_matches('\n').len();
+                        trailing_blank_lines = (text.len() - trimmed_len).saturating_sub(1);
+                        text.truncate(trimmed_len);
+                    }
+                    DocumentComponent::Text(_) => blank_lines += text.matches('\n').count(),
+                    DocumentComponent::List(_, true) => trailing_blank_lines = 1,
+                    _ => {}
+                }
+            }
+            // block properties and code blocks starting on a new line may follow the first lines
+            // of a block
+            let continues_block = !new_block
+                && !res.is_empty()
+                && match c {
+                    DocumentComponent::Properties(..) => true,
+                    DocumentComponent::CodeBlock(_, _) | DocumentComponent::Drawer(_, _) => {
+                        res.trim_end_matches(' ').ends_with('\n')
+                    }
+                    _ => false,
+                };
             if text.trim().is_empty() || c.is_empty_lines() {
                 // do nothing
-            } else if new_block || c.should_have_own_block() {
+            } else if !continues_block && (new_block || c.should_have_own_block()) {
                 let hl = if is_heading {
                     heading_level_stack.len().saturating_sub(1)
                 } else {
                     heading_level_stack.len()
                 };
                 let indent = " ".repeat(hl * util::SPACES_PER_INDENT);
+                if !res.is_empty() {
+                    res.push_str(&"\n".repeat(blank_lines));
+                }
+                blank_lines = 0;
                 if !res.is_empty() && !text.starts_with('\n') {
                     if let Some((_, rest)) = res.rsplit_once("")
                         && !rest.trim().is_empty()
@@ -307,7 +621,8 @@ impl ParsedDocument {
                         }
                         let t = text.trim();
                         // TODO: refactor with regex
-                        if !(t.starts_with("- ")
+                        if !(is_page_properties
+                            || t.starts_with("- ")
                             || t.starts_with("-\n")
                             || t.starts_with("-\n\r")
                             || t == "-"
@@ -327,7 +642,9 @@ impl ParsedDocument {
                     }
                     debug!("removing trailing blank lines from {res:?}");
                     // remove empty lines at the end of a block
-                    if line.trim().starts_with("- ") {
+                    let keep_blank_lines =
+                        preserve_blank_lines || (follows_page_properties && index == 0);
+                    if !keep_blank_lines && line.trim().starts_with("- ") {
                         let mut first_removed = None;
                         while let Some((start, rest)) = res.rsplit_once('\n') {
                             if rest.trim().is_empty() {
@@ -347,10 +664,41 @@ impl ParsedDocument {
                     }
                     res.push_str(&line);
                 });
+            } else if continues_block {
+                let trimmed_len = res.trim_end_matches(' ').len();
+                let indent = " ".repeat(res.len() - trimmed_len);
+                res.truncate(trimmed_len);
+                if !res.ends_with('\n') {
+                    res.push('\n');
+                }
+                if matches!(
+                    c,
+                    DocumentComponent::CodeBlock(_, _) | DocumentComponent::Drawer(_, _)
+                ) {
+                    // keep the code block aligned with the lines before it
+                    let text = text
+                        .lines()
+                        .map(|l| {
+                            if l.is_empty() {
+                                l.to_string()
+                            } else {
+                                format!("{indent}{l}")
+                            }
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n");
+                    res.push_str(&text);
+                } else {
+                    res.push_str(&text);
+                }
             } else {
                 res.push_str(&text);
             }
             new_block = c.should_have_own_block();
+            blank_lines += trailing_blank_lines;
+            if !text.trim().is_empty() {
+                follows_page_properties = is_page_properties;
+            }
         });
         res.trim_end().to_string()
     }
@@ -415,7 +763,7 @@ impl Display for MentionedFile {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Property {
     name: String,
     is_single: bool,
@@ -425,11 +773,7 @@ pub struct Property {
 impl Property {
     pub fn to_mode_text(&self, mode: &TextMode, file_info: &Option<FileInfo>) -> String {
         use TextMode::*;
-        let vals: Vec<String> = self
-            .values
-            .iter()
-            .map(|v| v.to_mode_text(mode, file_info))
-            .collect();
+        let vals = self.value_texts(mode, file_info);
         match mode {
             LogSeq => {
                 let value = vals.join(", ");
@@ -454,20 +798,83 @@ impl Property {
         }
     }
 
-    fn to_zk_frontmatter_prop(&self, file_info: &Option<FileInfo>) -> String {
-        let vals: Vec<String> = self
-            .values
-            .iter()
-            .map(|v| v.to_mode_text(&TextMode::Zk, file_info))
-            .collect();
-        let value = vals.join(", ");
-        if self.is_single {
-            format!("{}: {value}", self.name)
+    fn to_zk_frontmatter_prop(
+        &self,
+        file_info: &Option<FileInfo>,
+        format: FrontmatterFormat,
+    ) -> String {
+        let vals = self.value_texts(&TextMode::Zk, file_info);
+        let quote = |v: String| match format {
+            FrontmatterFormat::Yaml => v,
+            FrontmatterFormat::Toml => toml::Value::String(v).to_string(),
+            FrontmatterFormat::Json => serde_json::Value::String(v).to_string(),
+        };
+        let list_style = file_info
+            .as_ref()
+            .map(|fi| fi.frontmatter_list_style)
+            .unwrap_or_default();
+        let value = if self.is_single {
+            quote(vals.join(", "))
+        } else if format == FrontmatterFormat::Yaml
+            && list_style == FrontmatterListStyle::Block
+            && !vals.is_empty()
+        {
+            vals.iter().map(|v| format!("\n  - {v}")).collect()
         } else {
-            format!("{}: [{value}]", self.name)
+            let vals: Vec<String> = vals.into_iter().map(quote).collect();
+            format!("[{}]", vals.join(", "))
+        };
+        match format {
+            FrontmatterFormat::Yaml if value.starts_with('\n') => format!("{}:{value}", self.name),
+            FrontmatterFormat::Yaml => format!("{}: {value}", self.name),
+            FrontmatterFormat::Toml => format!("{} = {value}", self.name),
+            FrontmatterFormat::Json => {
+                let name = serde_json::Value::String(self.name.clone());
+                format!("  {name}: {value}")
+            }
         }
     }
 
+    /// renders the values, tags are written in the tag style of the output. Exploded nested tags
+    /// are only written once per property
+    fn value_texts(&self, mode: &TextMode, file_info: &Option<FileInfo>) -> Vec<String> {
+        let style = tag_style(file_info, mode);
+        let nested = file_info
+            .as_ref()
+            .map(|fi| fi.nested_tag_style)
+            .unwrap_or_default();
+        let mut seen: Vec<String> = vec![];
+        self.values
+            .iter()
+            .map(|v| match v {
+                // logseq keeps all tags in a single comma separated value
+                PropValue::String(_) | PropValue::Number(_) | PropValue::Date(_)
+                    if self.has_name("tags") =>
+                {
+                    let text = v.to_mode_text(mode, file_info);
+                    if text.trim().is_empty() {
+                        text
+                    } else {
+                        text.split(',')
+                            .flat_map(|t| nested.expand(t.trim()))
+                            .map(|t| style.render(&t))
+                            .filter(|t| {
+                                if nested == NestedTagStyle::Keep || !seen.contains(t) {
+                                    seen.push(t.clone());
+                                    true
+                                } else {
+                                    false
+                                }
+                            })
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    }
+                }
+                v => v.to_mode_text(mode, file_info),
+            })
+            .collect()
+    }
+
     pub fn new(name: String, is_single: bool, values: Vec<PropValue>) -> Self {
         Self {
             name,
@@ -500,13 +907,28 @@ impl Property {
                 return PropValue::FileLink(mf.clone(), sec.clone(), rename.clone());
             }
         }
-        PropValue::String(val.to_string())
+        PropValue::parse_typed(val)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
     }
 
     pub fn has_name(&self, name: &str) -> bool {
         self.name == name
     }
 
+    /// true if the property has no values or only blank ones
+    pub fn is_empty(&self) -> bool {
+        self.values
+            .iter()
+            .all(|v| matches!(v, PropValue::String(s) if s.trim().is_empty()))
+    }
+
     pub fn has_value(&self, value: &PropValue) -> bool {
         self.values.iter().any(|v| v == value)
     }
@@ -518,6 +940,24 @@ impl Property {
             }
         });
     }
+    /// removes repeated values, keeping the first occurrence. Surrounding whitespace of string
+    /// values is ignored, e.g. in `[a, b, a]` the second `a` is parsed as ` a`.
+    pub fn dedupe_values(&mut self) {
+        let mut seen = vec![];
+        self.values.retain(|v| {
+            let key = match v {
+                PropValue::String(s) => PropValue::String(s.trim().to_string()),
+                other => other.clone(),
+            };
+            if seen.contains(&key) {
+                false
+            } else {
+                seen.push(key);
+                true
+            }
+        });
+    }
+
     pub fn add_values_parse(
         &mut self,
         values: &[String],
@@ -533,19 +973,45 @@ impl Property {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum PropValue {
     String(String),
+    /// finite number whose canonical form is the value text
+    Number(f64),
+    /// date of the form `YYYY-MM-DD`
+    Date(NaiveDate),
     /// mentioned_file, optional section, optional rename
     FileLink(MentionedFile, Option<String>, Option<String>),
 }
 
 impl PropValue {
+    /// parses numbers and dates into the typed variants if rendering them gives back val,
+    /// other values are kept as strings
+    pub fn parse_typed(val: &str) -> PropValue {
+        if let Ok(n) = val.parse::<f64>()
+            && n.is_finite()
+            && n.to_string() == val
+        {
+            PropValue::Number(n)
+        } else if let Ok(date) = NaiveDate::parse_from_str(val, "%Y-%m-%d")
+            && date.format("%Y-%m-%d").to_string() == val
+        {
+            PropValue::Date(date)
+        } else {
+            PropValue::String(val.to_string())
+        }
+    }
+
     pub fn to_mode_text(&self, mode: &TextMode, file_info: &Option<FileInfo>) -> String {
         use PropValue::*;
         use TextMode::*;
         match self {
             String(s) => s.to_string(),
+            Number(n) => n.to_string(),
+            Date(date) => date.format("%Y-%m-%d").to_string(),
+            FileLink(mf, _, rename) if link_style(file_info).is_some() => {
+                styled_file_link(mf, rename, file_info)
+            }
             FileLink(mf, _section, rename) => match mode {
                 LogSeq => {
                     // TODO: use section
@@ -566,17 +1032,19 @@ impl PropValue {
                         }
                         let p = p.as_os_str();
                         let p = p.to_string_lossy();
+                        let target = link_target(&p, file_info);
                         if let Some(name) = rename {
-                            format!("[{name}]({p})")
+                            format!("[{name}]({target})")
                         } else {
-                            format!("[{p}]({p})")
+                            format!("[{p}]({target})")
                         }
                     }
                     MentionedFile::FileName(mentioned_name) => {
+                        let target = link_target(mentioned_name, file_info);
                         if let Some(name) = rename {
-                            format!("[{name}]({mentioned_name})")
+                            format!("[{name}]({target})")
                         } else {
-                            format!("[{mentioned_name}]({mentioned_name})")
+                            format!("[{mentioned_name}]({target})")
                         }
                     }
                 },
@@ -588,7 +1056,7 @@ impl PropValue {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ListElem {
     pub contents: ParsedDocument,
     pub children: Vec<ListElem>,
@@ -609,12 +1077,14 @@ impl ListElem {
     ) -> String {
         let contents = match mode {
             TextMode::LogSeq => self.contents.to_logseq_text(file_info),
-            TextMode::Zk => self.contents.to_zk_text(file_info),
-            _ => todo!(),
+            TextMode::Zk | TextMode::Obsidian => self.contents.to_zk_text(file_info),
         };
         let contents = trim_like_first_line_plus(&contents, 2);
         let mut res = String::new();
         let mut list_start_added = false;
+        // shift applied to the lines of a fenced code block so that they stay aligned with its
+        // opening fence
+        let mut fence_shift: Option<isize> = None;
         contents.lines().enumerate().for_each(|(i, l)| {
             if i > 0 {
                 res.push('\n');
@@ -637,11 +1107,26 @@ impl ListElem {
                 }
             } else {
                 let ind_spaces = indent_spaces(l);
+                let is_fence = l.trim_start().starts_with("```");
+                if let Some(shift) = fence_shift {
+                    if !l.trim().is_empty() {
+                        let indent = (ind_spaces as isize + shift).max(0) as usize;
+                        res.push_str(&" ".repeat(indent));
+                        res.push_str(l.trim_start());
+                    }
+                    if is_fence && l.trim() == "```" {
+                        fence_shift = None;
+                    }
+                    return;
+                }
                 // indent to compensate for '- ' prefix of first line of this list element
                 let required_indents = indent_level * SPACES_PER_INDENT + 2;
                 let missing_indents = required_indents.saturating_sub(ind_spaces);
                 debug!("missing indents: {missing_indents} for line {l:?}");
                 (0..missing_indents).for_each(|_| res.push(' '));
+                if is_fence {
+                    fence_shift = Some(missing_indents as isize);
+                }
             }
             res.push_str(l);
         });
@@ -649,6 +1134,34 @@ impl ListElem {
             (0..indent_level).for_each(|_| res.push_str("    "));
             res.push('-');
         }
+        if *mode == TextMode::LogSeq
+            && !self.children.is_empty()
+            && file_info
+                .as_ref()
+                .and_then(|fi| fi.collapse_depth)
+                .is_some_and(|depth| indent_level >= depth)
+            && !self.contents.components().iter().any(|c| {
+                matches!(c, DocumentComponent::Properties(props, _) if props.iter().any(|p| p.has_name("collapsed")))
+            })
+        {
+            // the property goes below the first line and the properties following it
+            let is_property = |l: &str| {
+                l.trim_start().split_once("::").is_some_and(|(key, _)| {
+                    !key.is_empty()
+                        && key
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+                })
+            };
+            let mut lines: Vec<&str> = res.lines().collect();
+            let pos = 1 + lines[1..].iter().take_while(|l| is_property(l)).count();
+            let property = format!(
+                "{}collapsed:: true",
+                " ".repeat(indent_level * SPACES_PER_INDENT + 2)
+            );
+            lines.insert(pos, &property);
+            res = lines.join("\n");
+        }
         self.children.iter().for_each(|c| {
             let text = c.to_mode_text(mode, file_info, indent_level + 1);
             res.push('\n');
@@ -657,6 +1170,15 @@ impl ListElem {
         res
     }
 
+    pub fn to_plain_text(&self) -> String {
+        let mut res = self.contents.to_plain_text();
+        self.children.iter().for_each(|c| {
+            res.push('\n');
+            res.push_str(&c.to_plain_text());
+        });
+        res
+    }
+
     pub fn get_document_component(
         &self,
         selector: &dyn Fn(&DocumentComponent) -> bool,
@@ -698,16 +1220,27 @@ impl ListElem {
         &self,
         selector: &dyn Fn(&DocumentComponent) -> bool,
     ) -> Vec<DocumentComponent> {
-        let mut res = self.contents.get_all_document_components(selector);
-        let mut rec = self
-            .children
-            .iter()
-            .flat_map(|c| c.get_all_document_components(selector).into_iter())
-            .collect();
-        res.append(&mut rec);
+        let mut res = vec![];
+        self.for_each_component(&mut |comp| {
+            if selector(comp) {
+                res.push(comp.clone());
+            }
+        });
         res
     }
 
+    pub fn for_each_component<'a>(&'a self, f: &mut dyn FnMut(&'a DocumentComponent)) {
+        self.contents.for_each_component(f);
+        self.children.iter().for_each(|c| c.for_each_component(f));
+    }
+
+    pub fn for_each_property_mut(&mut self, f: &mut dyn FnMut(&mut Property)) {
+        self.contents.for_each_property_mut(f);
+        self.children
+            .iter_mut()
+            .for_each(|c| c.for_each_property_mut(f));
+    }
+
     pub fn get_document_component_mut(
         &mut self,
         selector: &dyn Fn(&DocumentComponent) -> bool,
@@ -732,25 +1265,48 @@ impl ListElem {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// logseq page properties are the first lines of a page and not part of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyScope {
+    Page,
+    Block,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum DocumentComponent {
     Heading(u16, String),
     /// file, optional section, optional rename
     FileLink(MentionedFile, Option<String>, Option<String>),
     FileEmbed(MentionedFile, Option<String>),
     Text(String),
+    /// inline `#tag`, stored without the `#`
+    Tag(String),
+    /// link to an external url, optional link text
+    Url(String, Option<String>),
+    /// embedded media like logseq's `{{video url}}`, by url
+    Media(String),
+    /// comment like obsidian's `%%comment%%`, stored without the delimiters
+    Comment(String),
+    /// dataview-style inline field `(key:: value)`, key and value
+    InlineField(String, String),
     /// text, map storing additional properties
     Admonition(Vec<DocumentComponent>, HashMap<String, String>),
     /// inner text, type string
     CodeBlock(String, Option<String>),
+    /// logseq drawer like `:LOGBOOK:` ... `:END:`, name and the lines in between, kept verbatim
+    Drawer(String, String),
 
     /// list item, map stores additional properties
     //ListElement(ParsedDocument, Vec<(String, String)>),
     /// list_elems, terminated by blank line
     List(Vec<ListElem>, bool),
 
-    Properties(Vec<Property>),
+    Properties(Vec<Property>, PropertyScope),
     Frontmatter(Vec<Property>),
+    /// thematic break with its marker as written, e.g. `***`
+    ThematicBreak(String),
+    /// terms with their definitions
+    DefinitionList(DefinitionEntries),
 }
 
 impl DocumentComponent {
@@ -762,10 +1318,8 @@ impl DocumentComponent {
         let mut tmp = self.clone();
         tmp.cleanup();
         match self {
-            Frontmatter(_props) => {
-                todo!("frontmatter to logseq")
-            }
-            Properties(props) => {
+            // frontmatter becomes the page properties
+            Frontmatter(props) | Properties(props, _) => {
                 let mut res = String::new();
                 props.iter().for_each(|p| {
                     let p_text = p.to_mode_text(&TextMode::LogSeq, file_info);
@@ -776,10 +1330,9 @@ impl DocumentComponent {
                 });
                 res
             }
-            Heading(level, title) => {
-                let title = title.trim();
-                let hashes = "#".repeat(*level as usize).to_string();
-                format!("{hashes} {title}")
+            Heading(level, title) => heading(*level, title, file_info),
+            FileLink(file, _, name) if link_style(file_info).is_some() => {
+                styled_file_link(file, name, file_info)
             }
             // TODO: use other parsed properties
             FileLink(file, _, _) => format!("[[{file}]]"),
@@ -812,16 +1365,32 @@ impl DocumentComponent {
                     }
                 }
 
+                if file_info.as_ref().is_some_and(|fi| fi.embeds_as_links) {
+                    return format!("[[{file}]]");
+                }
                 format!("{{{{embed [[{file}]]}}}}")
             }
             Text(text) => text.to_string(),
+            Tag(tag) => TagStyle::Hash.render(tag),
+            Url(url, name) => format!("[{}]({url})", name.as_deref().unwrap_or(url)),
+            Media(url) => format!("{{{{video {url}}}}}"),
+            InlineField(key, value) => inline_field(key, value),
+            Comment(comment) => html_comment(comment, file_info),
+            ThematicBreak(marker) => marker.to_string(),
             Admonition(s, props) => {
-                let mut res = "#+BEGIN_QUOTE".to_string();
-                if let Some(title) = props.get("title") {
-                    res.push('\n');
-                    res.push_str("**");
-                    res.push_str(title);
-                    res.push_str("**");
+                let title_style = file_info
+                    .as_ref()
+                    .map(|fi| fi.callout_title_style)
+                    .unwrap_or_default();
+                let mut res = String::new();
+                match (props.get("title"), title_style) {
+                    (Some(title), CalloutTitleStyle::Heading) => {
+                        res.push_str(&format!("### {title}\n#+BEGIN_QUOTE"));
+                    }
+                    (Some(title), CalloutTitleStyle::Bold) => {
+                        res.push_str(&format!("#+BEGIN_QUOTE\n**{title}**"));
+                    }
+                    (None, _) => res.push_str("#+BEGIN_QUOTE"),
                 }
                 let body = s
                     .iter()
@@ -846,6 +1415,10 @@ impl DocumentComponent {
                 res.push_str("```");
                 res
             }
+            Drawer(name, contents) => drawer(name, contents),
+            DefinitionList(entries) => {
+                DocumentComponent::definition_list_as_list(entries).to_logseq_text(file_info)
+            }
             List(list_elems, _) => list_elems
                 .iter()
                 .map(|le| le.to_mode_text(&TextMode::LogSeq, file_info, 0))
@@ -890,16 +1463,28 @@ impl DocumentComponent {
         tmp.cleanup();
         let res = match self {
             Frontmatter(props) => {
-                let mut res = String::from("---");
-                props.iter().for_each(|p| {
-                    let p_text = p.to_zk_frontmatter_prop(file_info);
+                let format = file_info
+                    .as_ref()
+                    .map(|fi| fi.frontmatter_format)
+                    .unwrap_or_default();
+                let (start, end, separator) = match format {
+                    FrontmatterFormat::Yaml => ("---", "\n---", ""),
+                    FrontmatterFormat::Toml => ("+++", "\n+++", ""),
+                    FrontmatterFormat::Json => ("{", "\n}", ","),
+                };
+                let mut res = String::from(start);
+                props.iter().enumerate().for_each(|(i, p)| {
+                    let p_text = p.to_zk_frontmatter_prop(file_info, format);
+                    if i > 0 {
+                        res.push_str(separator);
+                    }
                     res.push('\n');
                     res.push_str(&p_text);
                 });
-                res.push_str("\n---");
+                res.push_str(end);
                 res
             }
-            Properties(props) => {
+            Properties(props, _) => {
                 let mut res = String::from("");
                 props.iter().for_each(|p| {
                     if !res.is_empty() {
@@ -910,18 +1495,18 @@ impl DocumentComponent {
                 });
                 res
             }
-            Heading(level, title) => {
-                let title = title.trim();
-                let hashes = "#".repeat(*level as usize).to_string();
-                format!("{hashes} {title}")
+            Heading(level, title) => heading(*level, title, file_info),
+            FileLink(file, _, name) if link_style(file_info).is_some() => {
+                styled_file_link(file, name, file_info)
             }
             //TODO: use other parsed properties
             FileLink(file, _, name) => match file {
                 MentionedFile::FileName(mentioned_name) => {
+                    let target = link_target(mentioned_name, file_info);
                     if let Some(name) = name {
-                        format!("[{name}]({mentioned_name})")
+                        format!("[{name}]({target})")
                     } else {
-                        format!("[{mentioned_name}]({mentioned_name})")
+                        format!("[{mentioned_name}]({target})")
                     }
                 }
                 MentionedFile::FilePath(p) => {
@@ -939,11 +1524,12 @@ impl DocumentComponent {
                     }
                     let p = p.as_os_str();
                     let p = p.to_string_lossy();
+                    let target = link_target(&p, file_info);
                     if let Some(name) = name {
                         let sanitized_name = name.replace(['[', ']'], "");
-                        format!("[{sanitized_name}]({p})")
+                        format!("[{sanitized_name}]({target})")
                     } else {
-                        format!("[{p}]({p})")
+                        format!("[{p}]({target})")
                     }
                 }
             },
@@ -976,28 +1562,44 @@ impl DocumentComponent {
                     }
                 }
 
-                format!("{{{{embed [[{file}]]}}}}")
+                // zk has no embeds, link to the embedded note instead
+                match file {
+                    MentionedFile::FileName(name) if Path::new(name).extension().is_none() => {
+                        let target = link_target(&format!("{name}.md"), file_info);
+                        if target.contains(' ') {
+                            format!("[{name}](<{target}>)")
+                        } else {
+                            format!("[{name}]({target})")
+                        }
+                    }
+                    _ => FileLink(file.clone(), None, None).to_zk_text(file_info),
+                }
             }
             Text(text) => text.to_string(),
-            Admonition(s, props) => {
-                // TODO: proper implementation, how should admonitions be represented?
-                let mut res = "- #+BEGIN_QUOTE".to_string();
-                if let Some(title) = props.get("title") {
+            Tag(tag) => TagStyle::Hash.render(tag),
+            Url(url, name) => format!("[{}]({url})", name.as_deref().unwrap_or(url)),
+            Media(url) => url.to_string(),
+            InlineField(key, value) => inline_field(key, value),
+            Comment(comment) => {
+                let mut res = html_comment(comment, file_info);
+                // an html block only ends with its line, keep following text out of the comment
+                if comment.contains('\n') && !res.is_empty() {
                     res.push('\n');
-                    res.push_str("**");
-                    res.push_str(title);
-                    res.push_str("**");
                 }
-                let body = s
-                    .iter()
-                    .map(|c| c.to_logseq_text(file_info))
-                    .collect::<Vec<String>>()
-                    .join("");
-                let body = body.trim();
-                res.push('\n');
-                res.push_str(body);
+                res
+            }
+            ThematicBreak(marker) => marker.to_string(),
+            Admonition(s, props) => {
+                let mut res = "```ad-note".to_string();
+                ["title", "color"].iter().for_each(|name| {
+                    if let Some(value) = props.get(*name) {
+                        res.push_str(&format!("\n{name}: {value}"));
+                    }
+                });
+                let body = ParsedDocument::ParsedText(s.clone()).to_zk_text(file_info);
                 res.push('\n');
-                res.push_str("#+END_QUOTE");
+                res.push_str(body.trim());
+                res.push_str("\n```");
                 res
             }
             CodeBlock(text, code_type) => {
@@ -1011,6 +1613,16 @@ impl DocumentComponent {
                 res.push_str("```");
                 res
             }
+            Drawer(name, contents) => drawer(name, contents),
+            DefinitionList(entries) => entries
+                .iter()
+                .map(|(term, definitions)| {
+                    let definitions: Vec<String> =
+                        definitions.iter().map(|d| format!(": {d}")).collect();
+                    format!("{term}\n{}", definitions.join("\n"))
+                })
+                .collect::<Vec<String>>()
+                .join("\n\n"),
             List(list_elems, terminated_by_blank_line) => {
                 let mut res = list_elems
                     .iter()
@@ -1059,15 +1671,34 @@ impl DocumentComponent {
         &self,
         selector: &dyn Fn(&DocumentComponent) -> bool,
     ) -> Vec<DocumentComponent> {
+        let mut res = vec![];
+        self.for_each_nested_component(&mut |comp| {
+            if selector(comp) {
+                res.push(comp.clone());
+            }
+        });
+        res
+    }
+
+    /// calls f on the direct children of admonitions and on all components of lists
+    pub fn for_each_nested_component<'a>(&'a self, f: &mut dyn FnMut(&'a DocumentComponent)) {
         use DocumentComponent::*;
         match self {
-            Admonition(comps, _) => comps.iter().filter(|c| selector(c)).cloned().collect(),
-            List(list_elements, _) => list_elements
-                .iter()
-                .flat_map(|le| le.get_all_document_components(selector).into_iter())
-                .collect(),
+            Admonition(comps, _) => comps.iter().for_each(&mut *f),
+            List(list_elements, _) => list_elements.iter().for_each(|le| le.for_each_component(f)),
+            _ => {}
+        }
+    }
 
-            _ => vec![],
+    fn for_each_property_mut(&mut self, f: &mut dyn FnMut(&mut Property)) {
+        use DocumentComponent::*;
+        match self {
+            Properties(props, _) | Frontmatter(props) => props.iter_mut().for_each(&mut *f),
+            Admonition(comps, _) => comps.iter_mut().for_each(|c| c.for_each_property_mut(f)),
+            List(list_elements, _) => list_elements
+                .iter_mut()
+                .for_each(|le| le.for_each_property_mut(f)),
+            _ => {}
         }
     }
 
@@ -1088,6 +1719,63 @@ impl DocumentComponent {
         }
     }
 
+    fn to_plain_text(&self) -> String {
+        use DocumentComponent::*;
+        match self {
+            Heading(_, title) => title.trim().to_string(),
+            FileLink(_, _, Some(rename)) => rename.to_string(),
+            FileLink(MentionedFile::FileName(name), _, None) => {
+                name.trim_end_matches(".md").to_string()
+            }
+            FileLink(MentionedFile::FilePath(path), _, None) => path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            Text(text) => text.to_string(),
+            Tag(tag) => TagStyle::Hash.render(tag),
+            Url(url, name) => name.as_ref().unwrap_or(url).to_string(),
+            Media(url) => url.to_string(),
+            InlineField(key, value) => format!("{key}: {value}"),
+            Admonition(components, _) => {
+                ParsedDocument::ParsedText(components.clone()).to_plain_text()
+            }
+            CodeBlock(code, _) => code.to_string(),
+            Drawer(_, _) => String::new(),
+            List(list_elements, _) => list_elements
+                .iter()
+                .map(|le| le.to_plain_text())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            DefinitionList(entries) => entries
+                .iter()
+                .map(|(term, definitions)| format!("{term}\n{}", definitions.join("\n")))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            FileEmbed(_, _) | Comment(_) | Properties(..) | Frontmatter(_) | ThematicBreak(_) => {
+                String::new()
+            }
+        }
+    }
+
+    /// bullet list with bold terms and their definitions as children, used for logseq which has no
+    /// definition lists
+    fn definition_list_as_list(entries: &DefinitionEntries) -> DocumentComponent {
+        let text_elem = |text: String| {
+            ListElem::new(ParsedDocument::ParsedText(vec![DocumentComponent::Text(
+                text,
+            )]))
+        };
+        let list_elements = entries
+            .iter()
+            .map(|(term, definitions)| {
+                let mut le = text_elem(format!("**{term}**"));
+                le.children = definitions.iter().cloned().map(text_elem).collect();
+                le
+            })
+            .collect();
+        DocumentComponent::List(list_elements, false)
+    }
+
     pub fn should_have_own_block(&self) -> bool {
         use DocumentComponent::*;
         match self {
@@ -1096,10 +1784,14 @@ impl DocumentComponent {
             Heading(_, _) => true,
             Admonition(_, _) => true,
             FileEmbed(_, _) => true,
-            FileLink(_, _, _) => false,
+            FileLink(_, _, _) | Tag(_) | Url(_, _) | Media(_) | InlineField(_, _) => false,
+            Comment(comment) => comment.contains('\n'),
             CodeBlock(_, _) => true,
-            Properties(_) => true,
+            Drawer(_, _) => true,
+            Properties(..) => true,
             List(_, _) => true,
+            ThematicBreak(_) => true,
+            DefinitionList(_) => true,
         }
     }
 
@@ -1159,69 +1851,715 @@ impl DocumentComponent {
     }
 }
 
-pub fn convert_tree(
-    root_dir: PathBuf,
-    target_dir: PathBuf,
-    inmode: TextMode,
+/// result of converting a single file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConversionEntry {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub success: bool,
+    pub error: Option<String>,
+    pub mentioned_files: Vec<String>,
+    /// time spent converting the file
+    #[serde(skip)]
+    pub duration: Duration,
+}
+
+impl ConversionEntry {
+    pub fn new(source: PathBuf, destination: PathBuf, res: Result<Vec<String>>) -> Self {
+        match res {
+            Ok(mentioned_files) => Self {
+                source,
+                destination,
+                success: true,
+                error: None,
+                mentioned_files,
+                duration: Duration::ZERO,
+            },
+            Err(e) => Self {
+                source,
+                destination,
+                success: false,
+                error: Some(format!("{e:?}")),
+                mentioned_files: vec![],
+                duration: Duration::ZERO,
+            },
+        }
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConversionReport {
+    pub entries: Vec<ConversionEntry>,
+}
+
+impl ConversionReport {
+    pub fn mentioned_files(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .flat_map(|e| e.mentioned_files.iter().cloned())
+            .collect()
+    }
+
+    pub fn failures(&self) -> Vec<&ConversionEntry> {
+        self.entries.iter().filter(|e| !e.success).collect()
+    }
+
+    /// returns the n entries that took the longest to convert, slowest first
+    pub fn slowest(&self, n: usize) -> Vec<&ConversionEntry> {
+        let mut entries: Vec<&ConversionEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.duration));
+        entries.truncate(n);
+        entries
+    }
+
+    pub fn print_timing(&self, n: usize) {
+        println!("Slowest conversions:");
+        self.slowest(n).iter().for_each(|e| {
+            println!(
+                "{:>10.3} ms  {:?}",
+                e.duration.as_secs_f64() * 1000.0,
+                e.source
+            );
+        });
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self).context("Failed to serialize report")?;
+        std::fs::write(path, text).context(format!("Failed to write report to {path:?}"))
+    }
+}
+
+/// restricts the files of a tree to the ones matching any include pattern (all if there are none)
+/// and none of the exclude patterns. Patterns are matched against paths relative to the root.
+#[derive(Debug, Clone, Default)]
+pub struct TreeFilter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    max_depth: Option<usize>,
+}
+
+impl TreeFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| glob::Pattern::new(p).context(format!("Invalid glob pattern {p:?}")))
+                .collect::<Result<Vec<glob::Pattern>>>()
+        };
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+            max_depth: None,
+        })
+    }
+
+    /// only files in at most max_depth levels of subdirectories are included
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn matches(&self, rel_path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(rel_path)))
+            && !self.exclude.iter().any(|p| p.matches_path(rel_path))
+    }
+
+    /// whether files directly in the directory at rel_path may match
+    pub fn matches_dir(&self, rel_path: &Path) -> bool {
+        self.matches(&rel_path.join("*"))
+    }
+}
+
+/// what happens if a converted file would overwrite a file converted from another input tree
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum OnCollision {
+    /// the conversion of the file fails
+    #[default]
+    Error,
+    /// the name of the input tree is prepended to the file name, e.g. `vault_note.md`
+    Prefix,
+}
+
+impl ValueEnum for OnCollision {
+    fn value_variants<'a>() -> &'a [Self] {
+        use OnCollision::*;
+        &[Error, Prefix]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use OnCollision::*;
+        Some(match self {
+            Error => PossibleValue::new("error"),
+            Prefix => PossibleValue::new("prefix"),
+        })
+    }
+}
+
+/// tracks the files written by convert_tree so that several input trees can be converted into
+/// the same target directory
+#[derive(Debug, Default)]
+pub struct Collisions {
+    policy: OnCollision,
+    written: HashSet<PathBuf>,
+    /// prefix of each input tree, unique even if the trees have the same name
+    prefixes: HashMap<PathBuf, String>,
+}
+
+impl Collisions {
+    pub fn new(policy: OnCollision) -> Self {
+        Self {
+            policy,
+            written: HashSet::new(),
+            prefixes: HashMap::new(),
+        }
+    }
+
+    /// the name of root_dir, numbered if another input tree has the same name
+    fn prefix(&mut self, root_dir: &Path) -> String {
+        if let Some(prefix) = self.prefixes.get(root_dir) {
+            return prefix.clone();
+        }
+        let name = root_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut prefix = name.clone();
+        let mut i = 1;
+        while self.prefixes.values().any(|p| *p == prefix) {
+            i += 1;
+            prefix = format!("{name}_{i}");
+        }
+        self.prefixes.insert(root_dir.to_path_buf(), prefix.clone());
+        prefix
+    }
+
+    /// returns the file to write instead of target, root_dir is the input tree of the file
+    fn claim(&mut self, target: &Path, root_dir: &Path) -> Result<PathBuf> {
+        let root_name = self.prefix(root_dir);
+        if self.written.insert(target.to_path_buf()) {
+            return Ok(target.to_path_buf());
+        }
+        match self.policy {
+            OnCollision::Error => bail!("{target:?} was already converted from another input"),
+            OnCollision::Prefix => {
+                let file_name = target
+                    .file_name()
+                    .context(format!("{target:?} has no file name"))?
+                    .to_string_lossy();
+                let prefixed = target.with_file_name(format!("{root_name}_{file_name}"));
+                if !self.written.insert(prefixed.clone()) {
+                    bail!("{target:?} and {prefixed:?} were already converted from other inputs");
+                }
+                Ok(prefixed)
+            }
+        }
+    }
+}
+
+/// options of a tree conversion that do not depend on the directory of a note
+#[derive(Debug, Clone)]
+pub struct TreeOptions {
+    inmode: TextMode,
     outmode: TextMode,
-    image_dir: &Option<PathBuf>,
-    image_out_dir: &Option<PathBuf>,
-) -> Result<Vec<String>> {
+    image_dir: Option<PathBuf>,
+    image_out_dir: Option<PathBuf>,
+    frontmatter_format: FrontmatterFormat,
+    filter: TreeFilter,
+}
+
+impl TreeOptions {
+    pub fn new(inmode: TextMode, outmode: TextMode) -> Self {
+        Self {
+            inmode,
+            outmode,
+            image_dir: None,
+            image_out_dir: None,
+            frontmatter_format: FrontmatterFormat::default(),
+            filter: TreeFilter::default(),
+        }
+    }
+
+    /// images are looked up in image_dir and copied to image_out_dir
+    pub fn with_image_dirs(
+        mut self,
+        image_dir: Option<PathBuf>,
+        image_out_dir: Option<PathBuf>,
+    ) -> Self {
+        self.image_dir = image_dir;
+        self.image_out_dir = image_out_dir;
+        self
+    }
+
+    pub fn with_frontmatter_format(mut self, frontmatter_format: FrontmatterFormat) -> Self {
+        self.frontmatter_format = frontmatter_format;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: TreeFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+}
+
+/// state shared by the conversions of the files of a tree
+#[derive(Debug, Default)]
+struct ConversionContext {
+    /// parsed notes of the tree for embeds and dataview queries, parsed when the first note needs
+    /// them
+    tree_index: Option<TreeIndex>,
+}
+
+/// converts all md files in root_dir into target_dir. Conversion continues after a file failed;
+/// the failure is recorded in the report.
+/// progress is called with (converted files, total files) after each file. Files that collide
+/// with ones converted before are handled according to collisions.
+pub fn convert_tree(
+    root_dir: PathBuf,
+    target_dir: PathBuf,
+    options: &TreeOptions,
+    base_config: &ConvertConfig,
+    collisions: &mut Collisions,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<ConversionReport> {
+    let TreeOptions {
+        inmode,
+        outmode,
+        image_dir,
+        image_out_dir,
+        frontmatter_format,
+        filter,
+    } = options;
     let root_dir = root_dir.canonicalize()?;
-    let files = files_in_tree(&root_dir, &Some(vec!["md"]))?;
+    let files: Vec<PathBuf> = files_in_tree(&root_dir, &Some(vec!["md"]), filter.max_depth)?
+        .into_iter()
+        .filter(|f| filter.matches(f.strip_prefix(&root_dir).unwrap_or(f)))
+        .collect();
     if !target_dir.exists() {
         std::fs::create_dir_all(&target_dir)?;
     }
     let target_dir = target_dir.canonicalize()?;
+    // subdirectories can opt out, but only the top-level config enables preserving directories
+    let root_config = base_config
+        .clone()
+        .merge(ConvertConfig::resolve(&root_dir, &root_dir)?);
+    if root_config.preserve_empty_dirs == Some(true) {
+        for dir in dirs_in_tree(&root_dir, filter.max_depth)? {
+            let rel = dir.strip_prefix(&root_dir)?;
+            let config = base_config
+                .clone()
+                .merge(ConvertConfig::resolve(&root_dir, &dir)?);
+            if filter.matches_dir(rel) && config.preserve_empty_dirs == Some(true) {
+                let target = target_dir.join(rel);
+                std::fs::create_dir_all(&target).context(format!("Could not create {target:?}"))?;
+            }
+        }
+    }
 
-    let mentioned_files = files
+    let mut context = ConversionContext::default();
+    let total = files.len();
+    let entries = files
         .iter()
-        .map(|f| {
+        .enumerate()
+        .map(|(i, f)| {
             let rel = pathdiff::diff_paths(f, &root_dir).unwrap();
-            let target = target_dir.join(&rel);
-            let file_info = FileInfo::try_new(
-                f.clone(),
-                Some(target),
-                image_dir.clone(),
-                image_out_dir.clone(),
-            )?;
-            convert_file(file_info, inmode.clone(), outmode.clone())
+            let config = ConvertConfig::resolve(&root_dir, f.parent().unwrap_or(&root_dir))
+                .map(|c| base_config.clone().merge(c));
+            let mut target = target_dir.join(&rel);
+            if let Ok(config) = &config
+                && let Some(ext) = config.output_extension()
+            {
+                target.set_extension(ext);
+            }
+            let claimed = collisions.claim(&target, &root_dir);
+            if let Ok(claimed) = &claimed {
+                target = claimed.clone();
+            }
+            let span = tracing::debug_span!("convert_file", file = ?rel);
+            let _guard = span.enter();
+            let start = Instant::now();
+            let res = (|| {
+                claimed?;
+                if let Some(parent) = target.parent()
+                    && !parent.exists()
+                {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let file_info = FileInfo::try_new(
+                    f.clone(),
+                    Some(target.clone()),
+                    image_dir.clone(),
+                    image_out_dir.clone(),
+                )?
+                .with_frontmatter_format(*frontmatter_format)
+                .with_vault_root(Some(root_dir.clone()));
+                Ok(convert_file_in(
+                    file_info,
+                    inmode.clone(),
+                    outmode.clone(),
+                    &config?,
+                    &mut context,
+                )?)
+            })();
+            let duration = start.elapsed();
+            debug!(?duration, "converted {rel:?}");
+            progress(i + 1, total);
+            ConversionEntry::new(f.clone(), target, res).with_duration(duration)
         })
-        .collect::<Result<Vec<Vec<String>>>>();
-    match mentioned_files {
-        Ok(v) => Ok(v.into_iter().flat_map(|v| v.into_iter()).collect()),
-        Err(e) => Err(e),
+        .collect();
+    Ok(ConversionReport { entries })
+}
+
+/// removes files with the output extension in target_dir that have no corresponding md file at
+/// the same relative path in root_dir. If dry_run is set, nothing is removed.
+/// Returns the removed files.
+pub fn mirror_deletions(
+    root_dir: PathBuf,
+    target_dir: PathBuf,
+    extension: &str,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let root_dir = root_dir.canonicalize()?;
+    let target_dir = target_dir.canonicalize()?;
+    if root_dir == target_dir {
+        bail!("Source and target directory are the same: {root_dir:?}");
     }
+    let mut removed = vec![];
+    for f in files_in_tree(&target_dir, &Some(vec![extension]), None)? {
+        let Some(rel) = pathdiff::diff_paths(&f, &target_dir) else {
+            continue;
+        };
+        if rel.starts_with("..") || root_dir.join(rel.with_extension("md")).exists() {
+            continue;
+        }
+        if !dry_run {
+            std::fs::remove_file(&f).context(format!("Failed to remove {f:?}"))?;
+        }
+        removed.push(f);
+    }
+    Ok(removed)
+}
+
+/// applies the rendering options of config to file_info
+fn with_render_options(file_info: FileInfo, config: &ConvertConfig) -> FileInfo {
+    let link_style = config.link_style.or(file_info.link_style);
+    let tag_style = config.tag_style.or(file_info.tag_style);
+    file_info
+        .with_link_style(link_style)
+        .with_tag_style(tag_style)
+        .with_nested_tag_style(config.nested_tag_style.unwrap_or_default())
+        .with_frontmatter_list_style(config.frontmatter_list_style.unwrap_or_default())
+        .with_preserve_blank_lines(config.preserve_blank_lines == Some(true))
+        .with_link_extension(
+            config
+                .output_extension()
+                .filter(|_| config.rewrite_link_extensions == Some(true)),
+        )
+        .with_strip_comments(config.strip_comments == Some(true))
+        .with_max_heading_level(config.max_heading_level)
+        .with_embeds_as_links(config.embeds_as_links == Some(true))
+        .with_collapse_depth(config.collapse_depth)
+        .with_callout_title_style(config.callout_title_style.unwrap_or_default())
+}
+
+/// renders the converted document and applies the text options of config
+fn render_converted(
+    pd: &ParsedDocument,
+    file_info: &FileInfo,
+    inmode: &TextMode,
+    outmode: &TextMode,
+    config: &ConvertConfig,
+) -> String {
+    let text = pd.to_string(outmode.clone(), &Some(file_info.clone()));
+    let text = config.apply_to_text(&text, file_info.indent_unit);
+    let text = config.apply_output_substitutions(&text, inmode, outmode);
+    let text = config.apply_trailing_newline(&text);
+    config.apply_line_ending(&text)
 }
 
 pub fn convert_file(
     file_info: FileInfo,
     inmode: TextMode,
     outmode: TextMode,
-) -> Result<Vec<String>> {
-    let file = &file_info.original_file;
-    let pd = parse_file(file, &inmode);
+    config: &ConvertConfig,
+) -> Result<Vec<String>, PkmtError> {
+    convert_file_in(
+        file_info,
+        inmode,
+        outmode,
+        config,
+        &mut ConversionContext::default(),
+    )
+}
 
-    if let Ok(pd) = pd {
-        let mentioned_files = pd.mentioned_files();
+/// converts a single file, the notes of the vault are parsed once per context
+fn convert_file_in(
+    file_info: FileInfo,
+    inmode: TextMode,
+    outmode: TextMode,
+    config: &ConvertConfig,
+    context: &mut ConversionContext,
+) -> Result<Vec<String>, PkmtError> {
+    let mut file_info = with_render_options(file_info, config);
+    let encoding = config.input_encoding.unwrap_or_default();
+    // notes converted to their own format keep the indentation of their lists
+    if inmode == outmode {
+        let unit = encoding
+            .read(&file_info.original_file)
+            .ok()
+            .map(|text| IndentUnit::detect(&text));
+        file_info = file_info.with_indent_unit(unit);
+    }
+    let file = &file_info.original_file;
+    let strict = config.strict == Some(true);
+    let best_effort = config.best_effort == Some(true);
+    let substitutions = config.substitutions();
+    let mut pd = parse_file_with(
+        file,
+        &inmode,
+        strict,
+        best_effort,
+        &encoding,
+        &substitutions,
+    )?;
+    if config.follow_embeds == Some(true) {
+        let file_dir = file.parent().unwrap_or(Path::new("."));
+        let vault_root = file_info.vault_root.as_deref().unwrap_or(file_dir);
+        pd = inline_embeds(
+            pd,
+            &mut |mf| {
+                let note =
+                    resolve_note(mf, file_dir, vault_root, &inmode, &mut context.tree_index)?;
+                parse_file_with(
+                    &note,
+                    &inmode,
+                    strict,
+                    best_effort,
+                    &encoding,
+                    &substitutions,
+                )
+                .ok()
+            },
+            MAX_EMBED_DEPTH,
+        );
+    }
+    if config.materialize_dataview == Some(true)
+        && !pd
+            .get_all_document_components(
+                &|dc| matches!(dc, DocumentComponent::CodeBlock(_, Some(t)) if t == "dataview"),
+            )
+            .is_empty()
+    {
+        let file_dir = file.parent().unwrap_or(Path::new("."));
+        let vault_root = file_info.vault_root.as_deref().unwrap_or(file_dir);
+        let index = match &mut context.tree_index {
+            Some(index) => index,
+            None => context.tree_index.insert(
+                parse_tree(vault_root, &inmode)
+                    .map_err(|e| PkmtError::from_parse_error(file, e))?,
+            ),
+        };
+        materialize_dataview(&mut pd, index);
+    }
+    let mentioned_files = pd.mentioned_files();
+
+    config.apply_to_document(&mut pd);
+    config.explode_inline_tags(&mut pd, &outmode);
+    if config.emit_source_path == Some(true) {
+        let file_dir = file.parent().unwrap_or(Path::new("."));
+        let vault_root = file_info.vault_root.as_deref().unwrap_or(file_dir);
+        add_source_path(&mut pd, file, vault_root);
+    }
+    if config.canonical_links == Some(true) {
+        let file_dir = file.parent().unwrap_or(Path::new("."));
+        let vault_root = file_info.vault_root.as_deref().unwrap_or(file_dir);
+        canonicalize_links(pd.components_mut(), file_dir, vault_root);
+    }
+    let text = render_converted(&pd, &file_info, &inmode, &outmode, config);
+    let dest_file = file_info
+        .destination_file
+        .clone()
+        .ok_or_else(|| PkmtError::Config(format!("No destination file for {file:?}")))?;
+
+    std::fs::write(&dest_file, text).map_err(|e| PkmtError::io(&dest_file, e))?;
+    Ok(mentioned_files)
+}
 
-        let text = pd.to_string(outmode, &Some(file_info.clone()));
-        let dest_file = file_info
-            .destination_file
-            .clone()
-            .context(format!("No destination file: {file_info:?}"))?;
+/// converts the text read from input, e.g. stdin, and writes it to dest_file. Without a file
+/// context, links are not resolved, images are not copied and the options that need the other
+/// notes (following embeds, dataview, canonical links and the source path) are ignored
+pub fn convert_reader<R: std::io::Read>(
+    mut input: R,
+    dest_file: PathBuf,
+    inmode: TextMode,
+    outmode: TextMode,
+    frontmatter_format: FrontmatterFormat,
+    config: &ConvertConfig,
+) -> Result<Vec<String>, PkmtError> {
+    let source = PathBuf::from("-");
+    let mut text = String::new();
+    input
+        .read_to_string(&mut text)
+        .map_err(|e| PkmtError::io(&source, e))?;
+    let file_info = FileInfo::try_new(source.clone(), Some(dest_file.clone()), None, None)
+        .map_err(|e| PkmtError::Config(format!("{e:#}")))?
+        .with_frontmatter_format(frontmatter_format);
+    let mut file_info = with_render_options(file_info, config);
+    if inmode == outmode {
+        file_info = file_info.with_indent_unit(Some(IndentUnit::detect(&text)));
+    }
+    let strict = config.strict == Some(true);
+    let best_effort = config.best_effort == Some(true);
+    let substitutions = config.substitutions();
+    let mut pd =
+        parsing::parse_text_with(&text, &inmode, &None, strict, best_effort, &substitutions)
+            .map_err(|e| PkmtError::from_parse_error(&source, e))?;
+    let mentioned_files = pd.mentioned_files();
+    config.apply_to_document(&mut pd);
+    config.explode_inline_tags(&mut pd, &outmode);
+    let text = render_converted(&pd, &file_info, &inmode, &outmode, config);
+    std::fs::write(&dest_file, text).map_err(|e| PkmtError::io(&dest_file, e))?;
+    Ok(mentioned_files)
+}
 
-        let res =
-            std::fs::write(&dest_file, text).context(format!("Failed to write to {dest_file:?}"));
-        if res.is_err() {
-            bail!("Encountered: {res:?}!");
+/// adds an `original_path` page property with the path of file relative to vault_root. It is
+/// added to the frontmatter or page properties, a frontmatter is created if there are none
+fn add_source_path(pd: &mut ParsedDocument, file: &Path, vault_root: &Path) {
+    use DocumentComponent::*;
+    let rel = pathdiff::diff_paths(file, vault_root).unwrap_or_else(|| file.to_path_buf());
+    let property = Property::new(
+        "original_path".to_string(),
+        true,
+        vec![PropValue::String(rel.to_string_lossy().replace("\\", "/"))],
+    );
+    let components = pd.components_mut();
+    match components.first_mut() {
+        Some(Frontmatter(props) | Properties(props, _)) => {
+            props.retain(|p| !p.has_name("original_path"));
+            props.push(property);
         }
-        Ok(mentioned_files)
-    } else {
-        bail!("Could not convert the file {file:?} to obsidian: {pd:?}")
+        _ => components.insert(0, Frontmatter(vec![property])),
     }
 }
 
+/// replaces the targets of file links and embeds by their paths relative to vault_root. Bare names
+/// are resolved relative to file_dir and vault_root, targets that cannot be resolved are kept.
+fn canonicalize_links(components: &mut [DocumentComponent], file_dir: &Path, vault_root: &Path) {
+    use DocumentComponent::*;
+    components.iter_mut().for_each(|c| match c {
+        FileLink(file, _, _) | FileEmbed(file, _) => {
+            if let Some(canonical) = canonical_link(file, file_dir, vault_root) {
+                *file = canonical;
+            }
+        }
+        Admonition(comps, _) => canonicalize_links(comps, file_dir, vault_root),
+        List(list_elements, _) => list_elements
+            .iter_mut()
+            .for_each(|le| canonicalize_list_elem_links(le, file_dir, vault_root)),
+        _ => {}
+    });
+}
+
+fn canonicalize_list_elem_links(le: &mut ListElem, file_dir: &Path, vault_root: &Path) {
+    canonicalize_links(le.contents.components_mut(), file_dir, vault_root);
+    le.children
+        .iter_mut()
+        .for_each(|c| canonicalize_list_elem_links(c, file_dir, vault_root));
+}
+
+fn canonical_link(
+    file: &MentionedFile,
+    file_dir: &Path,
+    vault_root: &Path,
+) -> Option<MentionedFile> {
+    let path = match file {
+        MentionedFile::FilePath(p) => p.clone(),
+        MentionedFile::FileName(name) => [file_dir, vault_root]
+            .iter()
+            .flat_map(|dir| [dir.join(name), dir.join(format!("{name}.md"))])
+            .find(|p| p.is_file())?
+            .canonicalize()
+            .ok()?,
+    };
+    let rel = path.strip_prefix(vault_root).ok()?;
+    Some(MentionedFile::FileName(
+        rel.to_string_lossy().replace('\\', "/"),
+    ))
+}
+
+/// sorts the entries of all properties and frontmatter blocks. Names contained in priority come
+/// first in that order, all others follow alphabetically. Values are not changed.
+pub fn sort_properties(pd: &mut ParsedDocument, priority: &[String]) {
+    sort_component_properties(pd.components_mut(), priority);
+}
+
+fn sort_component_properties(components: &mut [DocumentComponent], priority: &[String]) {
+    use DocumentComponent::*;
+    components.iter_mut().for_each(|c| match c {
+        Properties(props, _) | Frontmatter(props) => props.sort_by_cached_key(|p| {
+            let rank = priority
+                .iter()
+                .position(|name| p.has_name(name))
+                .unwrap_or(priority.len());
+            (rank, p.name().to_string())
+        }),
+        Admonition(comps, _) => sort_component_properties(comps, priority),
+        List(list_elements, _) => list_elements
+            .iter_mut()
+            .for_each(|le| sort_list_elem_properties(le, priority)),
+        _ => {}
+    });
+}
+
+fn sort_list_elem_properties(le: &mut ListElem, priority: &[String]) {
+    sort_component_properties(le.contents.components_mut(), priority);
+    le.children
+        .iter_mut()
+        .for_each(|c| sort_list_elem_properties(c, priority));
+}
+
+/// renumbers headings so that their levels never skip one, e.g. `#`, `###`, `###` become `#`,
+/// `##`, `##`. The first heading gets level 1.
+pub fn normalize_heading_levels(pd: &mut ParsedDocument) {
+    normalize_component_headings(pd.components_mut(), &mut vec![]);
+}
+
+/// stack holds the (original, new) levels of the enclosing headings
+fn normalize_component_headings(components: &mut [DocumentComponent], stack: &mut Vec<(u16, u16)>) {
+    use DocumentComponent::*;
+    components.iter_mut().for_each(|c| match c {
+        Heading(level, _) => {
+            while stack.last().is_some_and(|(original, _)| original >= level) {
+                stack.pop();
+            }
+            let new_level = stack.last().map(|(_, new)| new + 1).unwrap_or(1);
+            stack.push((*level, new_level));
+            *level = new_level;
+        }
+        Admonition(comps, _) => normalize_component_headings(comps, stack),
+        List(list_elements, _) => list_elements
+            .iter_mut()
+            .for_each(|le| normalize_list_elem_headings(le, stack)),
+        _ => {}
+    });
+}
+
+fn normalize_list_elem_headings(le: &mut ListElem, stack: &mut Vec<(u16, u16)>) {
+    normalize_component_headings(le.contents.components_mut(), stack);
+    le.children
+        .iter_mut()
+        .for_each(|c| normalize_list_elem_headings(c, stack));
+}
+
+/// removes repeated values of all properties and frontmatter entries, keeping the order
+pub fn dedupe_property_values(pd: &mut ParsedDocument) {
+    pd.for_each_property_mut(&mut |p| p.dedupe_values());
+}
+
 pub fn collapse_text(components: &[DocumentComponent]) -> Vec<DocumentComponent> {
     use DocumentComponent::*;
     let mut text = String::new();
@@ -1231,31 +2569,39 @@ pub fn collapse_text(components: &[DocumentComponent]) -> Vec<DocumentComponent>
             text.push_str(s);
         }
         Admonition(components, properties) => {
-            if !text.is_empty() {
-                res.push(DocumentComponent::Text(text.clone()));
-                text.clear();
-            }
+            flush_text(&mut text, &mut res, true);
             let collapsed = collapse_text(components);
             res.push(DocumentComponent::Admonition(collapsed, properties.clone()));
         }
         List(list_elements, blank_line_after) => {
+            flush_text(&mut text, &mut res, true);
             let elems = list_elements.iter().map(|le| le.collapse_text()).collect();
             res.push(DocumentComponent::List(elems, *blank_line_after));
         }
         _ => {
-            if !text.is_empty() {
-                res.push(DocumentComponent::Text(text.clone()));
-                text = String::new();
-            }
+            flush_text(&mut text, &mut res, c.should_have_own_block());
             res.push(c.clone());
         }
     });
-    if !text.is_empty() {
-        res.push(DocumentComponent::Text(text));
-    }
+    flush_text(&mut text, &mut res, false);
     res
 }
 
+/// moves the collected text into res. Whitespace-only text next to a block element is dropped
+/// unless it spans several lines (see `is_empty_lines`)
+fn flush_text(text: &mut String, res: &mut Vec<DocumentComponent>, before_block: bool) {
+    if text.is_empty() {
+        return;
+    }
+    let comp = DocumentComponent::Text(std::mem::take(text));
+    let after_block = res.last().is_some_and(|c| c.should_have_own_block());
+    let stray_whitespace = matches!(&comp, DocumentComponent::Text(t) if t.trim().is_empty())
+        && !comp.is_empty_lines();
+    if !(stray_whitespace && (before_block || after_block)) {
+        res.push(comp);
+    }
+}
+
 #[test]
 fn test_text_elem_to_logseq() {
     let text = "line 1\n\t  line 2".to_string();
@@ -1303,18 +2649,21 @@ fn test_list_element_to_logseq() {
 
     let list = DocumentComponent::List(
         vec![ListElem {
-            contents: ParsedDocument::ParsedText(vec![DocumentComponent::Properties(vec![
-                Property::new(
-                    "template".to_string(),
-                    true,
-                    vec![PropValue::String("blog".to_string())],
-                ),
-                Property::new(
-                    "tags".to_string(),
-                    true,
-                    vec![PropValue::String("[[blog]]".to_string())],
-                ),
-            ])]),
+            contents: ParsedDocument::ParsedText(vec![DocumentComponent::Properties(
+                vec![
+                    Property::new(
+                        "template".to_string(),
+                        true,
+                        vec![PropValue::String("blog".to_string())],
+                    ),
+                    Property::new(
+                        "tags".to_string(),
+                        true,
+                        vec![PropValue::String("[[blog]]".to_string())],
+                    ),
+                ],
+                PropertyScope::Block,
+            )]),
             children: vec![],
         }],
         false,
@@ -1348,3 +2697,930 @@ fn test_almost_empty_pd_to_logseq() {
     let expected = "-";
     assert_eq!(pd.to_logseq_text(&None), expected);
 }
+
+#[test]
+fn test_collapse_text_around_lists() {
+    use crate::parsing::{logseq_parsing::parse_logseq_text, zk_parsing::parse_zk_text};
+    use DocumentComponent::*;
+    let text = "text\n\n- a\n- b\n\nmore";
+    let pd = parse_logseq_text(text, &None).unwrap();
+    assert_eq!(pd.to_logseq_text(&None), "- text\n- a\n- b\n- more");
+
+    let pd = parse_zk_text(text, &None).unwrap();
+    assert_eq!(pd.to_zk_text(&None), text);
+    assert_eq!(pd.to_logseq_text(&None), "- text\n- a\n- b\n- more");
+
+    let list = List(
+        vec![ListElem::new(ParsedDocument::ParsedText(vec![Text(
+            "a".to_string(),
+        )]))],
+        false,
+    );
+    let components = vec![
+        Text("text".to_string()),
+        Text("\n".to_string()),
+        list.clone(),
+        Text("\n".to_string()),
+        Heading(1, "heading".to_string()),
+    ];
+    // the newline between the list and the heading is dropped
+    let expected = vec![
+        Text("text\n".to_string()),
+        list,
+        Heading(1, "heading".to_string()),
+    ];
+    assert_eq!(collapse_text(&components), expected);
+}
+
+/// converts a tree of the given files (path relative to the tree, content) and returns the
+/// converted files by their path relative to the output tree
+#[cfg(test)]
+pub(crate) fn convert_fixture(
+    files: &[(&str, &str)],
+    options: &TreeOptions,
+    config: &ConvertConfig,
+) -> HashMap<PathBuf, String> {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (src, target) = (dir.join("src"), dir.join("target"));
+    std::fs::create_dir_all(&src).unwrap();
+    for (path, content) in files {
+        let file = src.join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, content).unwrap();
+    }
+    let report = convert_tree(
+        src,
+        target.clone(),
+        options,
+        config,
+        &mut Collisions::default(),
+        &mut |_, _| {},
+    )
+    .unwrap();
+    assert!(report.failures().is_empty(), "{:?}", report.failures());
+    let target = target.canonicalize().unwrap();
+    files_in_tree(&target, &None, None)
+        .unwrap()
+        .into_iter()
+        .map(|f| {
+            let text = std::fs::read_to_string(&f).unwrap();
+            (f.strip_prefix(&target).unwrap().to_path_buf(), text)
+        })
+        .collect()
+}
+
+#[test]
+fn test_mirror_deletions() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (src, target) = (dir.join("src"), dir.join("target"));
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("a.md"), "a").unwrap();
+    std::fs::write(src.join("sub").join("b.md"), "b").unwrap();
+    convert_tree(
+        src.clone(),
+        target.clone(),
+        &TreeOptions::new(TextMode::Zk, TextMode::Zk),
+        &ConvertConfig::default(),
+        &mut Collisions::default(),
+        &mut |_, _| {},
+    )
+    .unwrap();
+    std::fs::write(target.join("keep.txt"), "not a note").unwrap();
+    std::fs::remove_file(src.join("sub").join("b.md")).unwrap();
+
+    let target_b = target.join("sub").join("b.md").canonicalize().unwrap();
+    let removed = mirror_deletions(src.clone(), target.clone(), "md", true).unwrap();
+    assert_eq!(removed, vec![target_b.clone()]);
+    assert!(target_b.exists());
+
+    let removed = mirror_deletions(src.clone(), target.clone(), "md", false).unwrap();
+    assert_eq!(removed, vec![target_b.clone()]);
+    assert!(!target_b.exists());
+    assert!(target.join("a.md").exists());
+    assert!(target.join("keep.txt").exists());
+
+    // files converted with another extension are matched to the md file of the same name
+    std::fs::write(target.join("a.org"), "a").unwrap();
+    std::fs::write(target.join("c.org"), "c").unwrap();
+    let target_c = target.join("c.org").canonicalize().unwrap();
+    let removed = mirror_deletions(src, target.clone(), "org", false).unwrap();
+    assert_eq!(removed, vec![target_c]);
+}
+
+#[test]
+fn test_conversion_report() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (src, target) = (dir.join("src"), dir.join("target"));
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("a.md"), "see [b](sub/b.md)").unwrap();
+    std::fs::write(src.join("sub").join("b.md"), "b").unwrap();
+    let report = convert_tree(
+        src.clone(),
+        target.clone(),
+        &TreeOptions::new(TextMode::Zk, TextMode::Zk),
+        &ConvertConfig::default(),
+        &mut Collisions::default(),
+        &mut |_, _| {},
+    )
+    .unwrap();
+    let (src, target) = (src.canonicalize().unwrap(), target.canonicalize().unwrap());
+    let mut entries: Vec<(PathBuf, PathBuf, bool)> = report
+        .entries
+        .iter()
+        .map(|e| (e.source.clone(), e.destination.clone(), e.success))
+        .collect();
+    entries.sort();
+    assert_eq!(
+        entries,
+        vec![
+            (src.join("a.md"), target.join("a.md"), true),
+            (
+                src.join("sub").join("b.md"),
+                target.join("sub").join("b.md"),
+                true
+            ),
+        ]
+    );
+    let report_file = dir.join("report.json");
+    report.write(&report_file).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&report_file).unwrap()).unwrap();
+    assert_eq!(json["entries"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_convert_tree_filter() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (src, target) = (dir.join("src"), dir.join("target"));
+    std::fs::create_dir_all(src.join("fitness").join("archive")).unwrap();
+    std::fs::create_dir_all(src.join("other")).unwrap();
+    std::fs::write(src.join("fitness").join("a.md"), "see [b](../other/b.md)").unwrap();
+    std::fs::write(src.join("fitness").join("archive").join("old.md"), "old").unwrap();
+    std::fs::write(src.join("other").join("b.md"), "see [c](c.md)").unwrap();
+    std::fs::write(src.join("top.md"), "top").unwrap();
+
+    let filter = TreeFilter::new(
+        &["**/fitness/**".to_string()],
+        &["**/archive/**".to_string()],
+    )
+    .unwrap();
+    let report = convert_tree(
+        src.clone(),
+        target.clone(),
+        &TreeOptions::new(TextMode::Zk, TextMode::Zk).with_filter(filter),
+        &ConvertConfig::default(),
+        &mut Collisions::default(),
+        &mut |_, _| {},
+    )
+    .unwrap();
+    assert_eq!(report.entries.len(), 1);
+    assert_eq!(report.mentioned_files(), vec!["../other/b.md".to_string()]);
+    assert!(target.join("fitness").join("a.md").exists());
+    assert!(
+        !target
+            .join("fitness")
+            .join("archive")
+            .join("old.md")
+            .exists()
+    );
+    assert!(!target.join("other").join("b.md").exists());
+    assert!(!target.join("top.md").exists());
+}
+
+#[test]
+fn test_slowest_conversions() {
+    let entry = |name: &str, ms: u64| {
+        ConversionEntry::new(PathBuf::from(name), PathBuf::from(name), Ok(vec![]))
+            .with_duration(Duration::from_millis(ms))
+    };
+    let report = ConversionReport {
+        entries: vec![entry("a.md", 5), entry("b.md", 20), entry("c.md", 10)],
+    };
+    let slowest: Vec<&Path> = report
+        .slowest(2)
+        .iter()
+        .map(|e| e.source.as_path())
+        .collect();
+    assert_eq!(slowest, vec![Path::new("b.md"), Path::new("c.md")]);
+}
+
+#[test]
+fn test_definition_list_to_logseq() {
+    let text = "# Fruit\nApple\n: a fruit\n: a company\n\nPear\n: another fruit";
+    let pd = parsing::parse_text(text, &TextMode::Zk, &None).unwrap();
+    assert_eq!(
+        pd.to_logseq_text(&None),
+        "- # Fruit\n    - **Apple**\n        - a fruit\n        - a company\n    - **Pear**\n        - another fruit"
+    );
+}
+
+#[test]
+fn test_definition_list_to_markdown() {
+    let text = "# Fruit\nApple\n: a fruit\n: a company\n\nPear\n: another fruit";
+    for mode in [TextMode::Zk, TextMode::Obsidian] {
+        let pd = parsing::parse_text(text, &mode, &None).unwrap();
+        assert_eq!(pd.to_string(mode, &None), text);
+    }
+}
+
+#[test]
+fn test_zk_text_below_heading() {
+    let text = "# A\nsee #x\n\n## B\nmore text";
+    let pd = parsing::parse_text(text, &TextMode::Zk, &None).unwrap();
+    assert_eq!(pd.to_zk_text(&None), text);
+}
+
+#[test]
+fn test_tag_styles() {
+    let pd = parsing::parse_text("- a\n  tags:: #video", &TextMode::LogSeq, &None).unwrap();
+    let props: Vec<Property> = pd
+        .get_all_document_components(&|c| matches!(c, DocumentComponent::Properties(..)))
+        .into_iter()
+        .flat_map(|c| match c {
+            DocumentComponent::Properties(props, _) => props,
+            _ => vec![],
+        })
+        .collect();
+    assert_eq!(pd.to_logseq_text(&None), "- a\n  tags:: #video");
+    let frontmatter = ParsedDocument::ParsedText(vec![DocumentComponent::Frontmatter(props)]);
+    assert_eq!(frontmatter.to_zk_text(&None), "---\ntags: video\n---");
+
+    let pd = parsing::parse_text("---\ntags: [video]\n---\n", &TextMode::Zk, &None).unwrap();
+    assert_eq!(pd.to_zk_text(&None), "---\ntags: [video]\n---");
+    let file_info = FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+        .unwrap()
+        .with_tag_style(Some(TagStyle::Hash));
+    assert_eq!(pd.to_zk_text(&Some(file_info)), "---\ntags: [#video]\n---");
+    // inline tags are not affected by the style
+    let pd = parsing::parse_text("see #video", &TextMode::Zk, &None).unwrap();
+    let file_info = FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+        .unwrap()
+        .with_tag_style(Some(TagStyle::Bare));
+    assert_eq!(pd.to_zk_text(&Some(file_info)), "see #video");
+    assert_eq!(TagStyle::Hash.render("multi word"), "#[[multi word]]");
+    assert_eq!(TagStyle::Bare.render("#[[multi word]]"), "multi word");
+}
+
+#[test]
+fn test_frontmatter_list_style() {
+    let text = "---\ntags: [a, b]\ndate: 2024-01-01\n---\n# Note";
+    let pd = parsing::parse_text(text, &TextMode::Zk, &None).unwrap();
+    let file_info = |style| {
+        Some(
+            FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+                .unwrap()
+                .with_frontmatter_list_style(style),
+        )
+    };
+    assert_eq!(
+        pd.to_zk_text(&file_info(FrontmatterListStyle::Inline)),
+        text
+    );
+    let block = pd.to_zk_text(&file_info(FrontmatterListStyle::Block));
+    assert_eq!(
+        block,
+        "---\ntags:\n  - a\n  - b\ndate: 2024-01-01\n---\n# Note"
+    );
+    // block sequences are parsed back into the same entries
+    let reparsed = parsing::parse_text(&block, &TextMode::Zk, &None).unwrap();
+    assert_eq!(reparsed.to_zk_text(&None), text);
+}
+
+#[test]
+fn test_nested_tag_styles() {
+    let pd = parsing::parse_text(
+        "---\ntags: [a/b, a/c]\n---\n# Note",
+        &TextMode::Obsidian,
+        &None,
+    )
+    .unwrap();
+    let file_info = |style| {
+        Some(
+            FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+                .unwrap()
+                .with_nested_tag_style(style),
+        )
+    };
+    assert_eq!(
+        pd.to_zk_text(&file_info(NestedTagStyle::Keep)),
+        "---\ntags: [a/b, a/c]\n---\n# Note"
+    );
+    assert_eq!(
+        pd.to_zk_text(&file_info(NestedTagStyle::Explode)),
+        "---\ntags: [a, a/b, a/c]\n---\n# Note"
+    );
+
+    let pd =
+        parsing::parse_text("- a\n  tags:: #a/b/c, [[x/y]]", &TextMode::LogSeq, &None).unwrap();
+    assert_eq!(
+        pd.to_logseq_text(&file_info(NestedTagStyle::Explode)),
+        "- a\n  tags:: #a, #a/b, #a/b/c, [[x/y]]"
+    );
+}
+
+#[test]
+fn test_nested_inline_tags() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let out = dir_tmp.path().join("out.md");
+    let convert = |text: &str, style| {
+        let config = ConvertConfig {
+            nested_tag_style: Some(style),
+            ..Default::default()
+        };
+        convert_reader(
+            text.as_bytes(),
+            out.clone(),
+            TextMode::Obsidian,
+            TextMode::Zk,
+            FrontmatterFormat::Yaml,
+            &config,
+        )
+        .unwrap();
+        std::fs::read_to_string(&out).unwrap()
+    };
+    let text = "# Note\nsee #a/b and #c";
+    assert_eq!(convert(text, NestedTagStyle::Keep), format!("{text}\n"));
+    assert_eq!(
+        convert(text, NestedTagStyle::Explode),
+        "---\ntags: [a, a/b]\n---\n# Note\nsee #a/b and #c\n"
+    );
+    // levels already in the frontmatter are not repeated
+    let text = "---\ntags: [a]\n---\n# Note\nsee #a/b";
+    assert_eq!(
+        convert(text, NestedTagStyle::Explode),
+        "---\ntags: [a, a/b]\n---\n# Note\nsee #a/b\n"
+    );
+}
+
+#[test]
+fn test_link_styles() {
+    let file_info = |style| {
+        Some(
+            FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+                .unwrap()
+                .with_link_style(Some(style)),
+        )
+    };
+    let pd = parsing::parse_text("see [the other note](other.md)", &TextMode::Zk, &None).unwrap();
+    assert_eq!(
+        pd.to_zk_text(&file_info(LinkStyle::Wiki)),
+        "see [[other|the other note]]"
+    );
+    assert_eq!(
+        pd.to_zk_text(&file_info(LinkStyle::Markdown)),
+        "see [the other note](other.md)"
+    );
+
+    let pd = parsing::parse_text("- see [[third]]", &TextMode::LogSeq, &None).unwrap();
+    assert_eq!(
+        pd.to_logseq_text(&file_info(LinkStyle::Markdown)),
+        "- see [third](third.md)"
+    );
+    assert_eq!(
+        pd.to_logseq_text(&file_info(LinkStyle::Wiki)),
+        "- see [[third]]"
+    );
+}
+
+#[test]
+fn test_code_block_in_list_element() {
+    let texts = [
+        (
+            "- item\n  ```rust\n  fn main() {\n      x\n  }\n  ```\n- next",
+            TextMode::LogSeq,
+        ),
+        (
+            "- item\n  ```rust\n  fn main() {\n      x\n  }\n  ```\n- next",
+            TextMode::Zk,
+        ),
+        (
+            "- a\n    - item\n      ```rust\n      fn main() {\n          x\n      }\n      ```\n- next",
+            TextMode::LogSeq,
+        ),
+    ];
+    for (text, mode) in texts.iter() {
+        let pd = parsing::parse_text(text, mode, &None).unwrap();
+        assert_eq!(pd.to_string(mode.clone(), &None), *text);
+    }
+    let pd = parsing::parse_text(texts[2].0, &TextMode::LogSeq, &None).unwrap();
+    let code = pd.get_all_document_components(&|dc| matches!(dc, DocumentComponent::CodeBlock(..)));
+    assert_eq!(
+        code,
+        vec![DocumentComponent::CodeBlock(
+            "fn main() {\n    x\n}".to_string(),
+            Some("rust".to_string())
+        )]
+    );
+}
+
+#[test]
+fn test_preserve_blank_lines() {
+    let file_info = |preserve| {
+        Some(
+            FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+                .unwrap()
+                .with_preserve_blank_lines(preserve),
+        )
+    };
+    let pd = parsing::parse_text("para one\n\n\n\n- item", &TextMode::Zk, &None).unwrap();
+    assert_eq!(pd.to_logseq_text(&file_info(false)), "- para one\n- item");
+    // zk output keeps blank lines without the option
+    assert_eq!(pd.to_zk_text(&file_info(false)), "para one\n\n\n\n- item");
+    assert_eq!(
+        pd.to_logseq_text(&file_info(true)),
+        "- para one\n\n\n\n- item"
+    );
+    let pd = parsing::parse_text("- a\n\n\n\n- b", &TextMode::Zk, &None).unwrap();
+    assert_eq!(pd.to_logseq_text(&file_info(true)), "- a\n\n\n\n- b");
+}
+
+#[test]
+fn test_output_extension() {
+    let config = ConvertConfig {
+        output_extension: Some(".org".to_string()),
+        rewrite_link_extensions: Some(true),
+        ..Default::default()
+    };
+    let out = convert_fixture(
+        &[("a.md", "# A\nsee [B](b.md)"), ("b.md", "# B")],
+        &TreeOptions::new(TextMode::Zk, TextMode::Zk),
+        &config,
+    );
+    assert!(out.contains_key(Path::new("b.org")));
+    assert!(!out.contains_key(Path::new("a.md")));
+    let text = &out[Path::new("a.org")];
+    assert!(text.contains("[B](b.org)"), "{text:?}");
+}
+
+#[test]
+fn test_page_and_block_properties() {
+    let text = "title:: A\ntags:: #x\n\n- block\n  id:: 1";
+    let pd = parsing::parse_text(text, &TextMode::LogSeq, &None).unwrap();
+    assert!(matches!(
+        pd.components()[0],
+        DocumentComponent::Properties(_, PropertyScope::Page)
+    ));
+    assert_eq!(pd.to_logseq_text(&None), text);
+
+    let property = |name: &str| {
+        Property::new(
+            name.to_string(),
+            true,
+            vec![PropValue::String("x".to_string())],
+        )
+    };
+    let pd = ParsedDocument::ParsedText(vec![
+        DocumentComponent::Properties(vec![property("page")], PropertyScope::Page),
+        DocumentComponent::Properties(vec![property("block")], PropertyScope::Block),
+    ]);
+    assert_eq!(pd.to_logseq_text(&None), "page:: x\n\n- block:: x");
+}
+
+#[test]
+fn test_convert_tree_progress() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (src, target) = (dir.join("src"), dir.join("target"));
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    std::fs::write(src.join("a.md"), "# A").unwrap();
+    std::fs::write(src.join("b.md"), "# B").unwrap();
+    std::fs::write(src.join("sub").join("c.md"), "# C").unwrap();
+
+    let mut calls = vec![];
+    convert_tree(
+        src,
+        target,
+        &TreeOptions::new(TextMode::Zk, TextMode::Zk),
+        &ConvertConfig::default(),
+        &mut Collisions::default(),
+        &mut |done, total| calls.push((done, total)),
+    )
+    .unwrap();
+    assert_eq!(calls, vec![(1, 3), (2, 3), (3, 3)]);
+}
+
+#[test]
+fn test_preserve_empty_dirs() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (src, target) = (dir.join("src"), dir.join("target"));
+    std::fs::create_dir_all(src.join("placeholder").join("nested")).unwrap();
+    std::fs::write(src.join("a.md"), "# A").unwrap();
+
+    let convert = |config: &ConvertConfig| {
+        convert_tree(
+            src.clone(),
+            target.clone(),
+            &TreeOptions::new(TextMode::Zk, TextMode::Zk),
+            config,
+            &mut Collisions::default(),
+            &mut |_, _| {},
+        )
+        .unwrap()
+    };
+    convert(&ConvertConfig::default());
+    assert!(target.join("a.md").exists());
+    assert!(!target.join("placeholder").exists());
+
+    convert(&ConvertConfig {
+        preserve_empty_dirs: Some(true),
+        ..Default::default()
+    });
+    assert!(target.join("placeholder").join("nested").is_dir());
+
+    // the filter applies to directories as well, hidden directories are skipped
+    std::fs::remove_dir_all(&target).unwrap();
+    std::fs::create_dir_all(src.join("archive")).unwrap();
+    std::fs::create_dir_all(src.join(".hidden")).unwrap();
+    convert_tree(
+        src.clone(),
+        target.clone(),
+        &TreeOptions::new(TextMode::Zk, TextMode::Zk).with_filter(
+            TreeFilter::new(&[], &["archive/*".to_string()])
+                .unwrap()
+                .with_max_depth(Some(1)),
+        ),
+        &ConvertConfig {
+            preserve_empty_dirs: Some(true),
+            ..Default::default()
+        },
+        &mut Collisions::default(),
+        &mut |_, _| {},
+    )
+    .unwrap();
+    assert!(target.join("placeholder").is_dir());
+    assert!(!target.join("placeholder").join("nested").exists());
+    assert!(!target.join("archive").exists());
+    assert!(!target.join(".hidden").exists());
+}
+
+#[test]
+fn test_merge_trees() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (vault_a, vault_b, target) = (dir.join("vault_a"), dir.join("vault_b"), dir.join("target"));
+    for vault in [&vault_a, &vault_b] {
+        std::fs::create_dir_all(vault).unwrap();
+    }
+    std::fs::write(vault_a.join("note.md"), "from a").unwrap();
+    std::fs::write(vault_b.join("note.md"), "from b").unwrap();
+    std::fs::write(vault_b.join("other.md"), "other").unwrap();
+
+    let convert = |root: &PathBuf, collisions: &mut Collisions| {
+        convert_tree(
+            root.clone(),
+            target.clone(),
+            &TreeOptions::new(TextMode::Zk, TextMode::Zk),
+            &ConvertConfig::default(),
+            collisions,
+            &mut |_, _| {},
+        )
+        .unwrap()
+    };
+    let mut collisions = Collisions::new(OnCollision::Prefix);
+    convert(&vault_a, &mut collisions);
+    let report = convert(&vault_b, &mut collisions);
+    assert!(report.failures().is_empty());
+    let read = |name: &str| std::fs::read_to_string(target.join(name)).unwrap();
+    assert_eq!(read("note.md"), "from a\n");
+    assert_eq!(read("vault_b_note.md"), "from b\n");
+    assert_eq!(read("other.md"), "other\n");
+
+    let mut collisions = Collisions::new(OnCollision::Error);
+    convert(&vault_a, &mut collisions);
+    let report = convert(&vault_b, &mut collisions);
+    assert_eq!(report.failures().len(), 1);
+    assert!(report.failures()[0].source.ends_with("note.md"));
+
+    // input trees with the same name get different prefixes
+    let vault_c = dir.join("c").join("vault_b");
+    std::fs::create_dir_all(&vault_c).unwrap();
+    std::fs::write(vault_c.join("note.md"), "from c").unwrap();
+    let mut collisions = Collisions::new(OnCollision::Prefix);
+    for vault in [&vault_a, &vault_b, &vault_c] {
+        assert!(convert(vault, &mut collisions).failures().is_empty());
+    }
+    assert_eq!(read("vault_b_note.md"), "from b\n");
+    assert_eq!(read("vault_b_2_note.md"), "from c\n");
+}
+
+#[test]
+fn test_max_heading_level() {
+    let file_info = Some(
+        FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+            .unwrap()
+            .with_max_heading_level(Some(4)),
+    );
+    let pd = parsing::parse_text("## Two\n\n###### Six", &TextMode::Zk, &None).unwrap();
+    assert_eq!(pd.to_zk_text(&file_info), "## Two\n\n#### Six");
+    assert_eq!(pd.to_zk_text(&None), "## Two\n\n###### Six");
+}
+
+#[test]
+fn test_collapse_depth() {
+    let file_info = Some(
+        FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+            .unwrap()
+            .with_collapse_depth(Some(2)),
+    );
+    let pd = parsing::parse_text(
+        "- a\n    - b\n        - c\n            - d",
+        &TextMode::Zk,
+        &None,
+    )
+    .unwrap();
+    assert_eq!(
+        pd.to_logseq_text(&file_info),
+        "- a\n    - b\n        - c\n          collapsed:: true\n            - d"
+    );
+    assert!(!pd.to_logseq_text(&None).contains("collapsed::"));
+
+    let pd = parsing::parse_text(
+        "- a\n    - b\n      more text\n        - c\n            - d",
+        &TextMode::Zk,
+        &None,
+    )
+    .unwrap();
+    assert_eq!(
+        pd.to_logseq_text(&file_info),
+        "- a\n    - b\n      more text\n        - c\n          collapsed:: true\n            - d"
+    );
+    let pd = parsing::parse_text(
+        "- a\n    - b\n        - c\n          more text\n            - d",
+        &TextMode::Zk,
+        &None,
+    )
+    .unwrap();
+    assert_eq!(
+        pd.to_logseq_text(&file_info),
+        "- a\n    - b\n        - c\n          collapsed:: true\n          more text\n            - d"
+    );
+    let text = "- a\n    - b\n        - c\n          id:: 1\n            - d";
+    let pd = parsing::parse_text(text, &TextMode::LogSeq, &None).unwrap();
+    assert_eq!(
+        pd.to_logseq_text(&file_info),
+        "- a\n    - b\n        - c\n          id:: 1\n          collapsed:: true\n            - d"
+    );
+}
+
+#[test]
+fn test_callout_title_style() {
+    let pd = parsing::parse_text(
+        "```ad-note\ntitle: Title\nsome text\n```",
+        &TextMode::Zk,
+        &None,
+    )
+    .unwrap();
+    let file_info = |style| {
+        Some(
+            FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+                .unwrap()
+                .with_callout_title_style(style),
+        )
+    };
+    assert_eq!(
+        pd.to_logseq_text(&file_info(CalloutTitleStyle::Bold)),
+        "- #+BEGIN_QUOTE\n  **Title**\n  some text\n  #+END_QUOTE"
+    );
+    assert_eq!(
+        pd.to_logseq_text(&file_info(CalloutTitleStyle::Heading)),
+        "- ### Title\n  #+BEGIN_QUOTE\n  some text\n  #+END_QUOTE"
+    );
+}
+
+#[test]
+fn test_canonical_links() {
+    let config = ConvertConfig {
+        canonical_links: Some(true),
+        ..Default::default()
+    };
+    let out = convert_fixture(
+        &[
+            ("notes/x.md", "# X"),
+            (
+                "a/b/note.md",
+                "# Note\n[one](../../notes/x.md) and [two](notes/x.md)",
+            ),
+        ],
+        &TreeOptions::new(TextMode::Zk, TextMode::Zk),
+        &config,
+    );
+    let text = &out[Path::new("a/b/note.md")];
+    assert!(
+        text.contains("[one](notes/x.md) and [two](notes/x.md)"),
+        "{text:?}"
+    );
+}
+
+#[test]
+fn test_convert_reader() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("out.md");
+    let stdin = "---\ntags: [a]\n---\n# Note\nsee [[other]]".as_bytes();
+    convert_reader(
+        stdin,
+        out.clone(),
+        TextMode::Zk,
+        TextMode::LogSeq,
+        FrontmatterFormat::Yaml,
+        &ConvertConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap(),
+        "tags:: #a\n\n- # Note\n    - see [[other]]\n"
+    );
+}
+
+#[test]
+fn test_emit_source_path() {
+    let config = ConvertConfig {
+        emit_source_path: Some(true),
+        ..Default::default()
+    };
+    let out = convert_fixture(
+        &[
+            ("projects/work/note.md", "---\ntags: [a]\n---\n# Note"),
+            ("top.md", "# Top"),
+        ],
+        &TreeOptions::new(TextMode::Zk, TextMode::LogSeq),
+        &config,
+    );
+    let text = &out[Path::new("projects/work/note.md")];
+    assert!(
+        text.starts_with("tags:: #a\noriginal_path:: projects/work/note.md\n"),
+        "{text:?}"
+    );
+    let text = &out[Path::new("top.md")];
+    assert!(text.starts_with("original_path:: top.md\n"), "{text:?}");
+}
+
+#[test]
+fn test_embeds_as_links() {
+    let file_info = |embeds_as_links| {
+        Some(
+            FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+                .unwrap()
+                .with_embeds_as_links(embeds_as_links),
+        )
+    };
+    let pd = ParsedDocument::ParsedText(vec![DocumentComponent::FileEmbed(
+        MentionedFile::FileName("file".to_string()),
+        None,
+    )]);
+    assert_eq!(pd.to_logseq_text(&file_info(true)), "- [[file]]");
+    assert_eq!(pd.to_logseq_text(&file_info(false)), "- {{embed [[file]]}}");
+}
+
+#[test]
+fn test_zk_embed_links_to_note() {
+    let embed = |name: &str| {
+        ParsedDocument::ParsedText(vec![DocumentComponent::FileEmbed(
+            MentionedFile::FileName(name.to_string()),
+            None,
+        )])
+    };
+    assert_eq!(
+        embed("other note").to_zk_text(&None),
+        "[other note](<other note.md>)"
+    );
+    assert_eq!(embed("file").to_zk_text(&None), "[file](file.md)");
+    let pd =
+        parsing::parse_text(&embed("other note").to_zk_text(&None), &TextMode::Zk, &None).unwrap();
+    assert_eq!(pd.mentioned_files(), vec!["other note.md".to_string()]);
+}
+
+#[test]
+fn test_typed_property_values() {
+    let text =
+        "- a\n  priority:: 3\n  date:: 2024-01-01\n  ratio:: 0.5\n  id:: 007\n  title:: 2024";
+    let pd = parsing::parse_text(text, &TextMode::LogSeq, &None).unwrap();
+    let props = pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Properties(..)));
+    let DocumentComponent::Properties(props, _) = &props[0] else {
+        panic!("no properties: {pd:?}");
+    };
+    let values: Vec<&PropValue> = props.iter().flat_map(|p| p.values.iter()).collect();
+    assert_eq!(
+        values,
+        vec![
+            &PropValue::Number(3.0),
+            &PropValue::Date(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()),
+            &PropValue::Number(0.5),
+            &PropValue::String("007".to_string()),
+            &PropValue::Number(2024.0),
+        ]
+    );
+    assert_eq!(pd.to_logseq_text(&None), text);
+
+    let text = "---\ndate: 2024-01-01\n---\n\n# Note\npriority ::= 3";
+    let pd = parsing::parse_text(text, &TextMode::Zk, &None).unwrap();
+    assert_eq!(pd.to_zk_text(&None), text);
+}
+
+#[test]
+fn test_dedupe_property_values() {
+    use crate::parsing::zk_parsing::parse_zk_text;
+    let mut pd = parse_zk_text("tags ::= [a, b, a]\n\ntext", &None).unwrap();
+    dedupe_property_values(&mut pd);
+    let mut values = vec![];
+    pd.for_each_property_mut(&mut |p| values.push(p.values.clone()));
+    assert_eq!(
+        values,
+        vec![vec![
+            PropValue::String("a".to_string()),
+            PropValue::String(" b".to_string())
+        ]]
+    );
+    assert!(pd.to_zk_text(&None).starts_with("tags ::= [a, b]\n"));
+
+    let mut pd = parse_zk_text("---\ntags: [video, youtube, video]\n---\n\n# Note", &None).unwrap();
+    dedupe_property_values(&mut pd);
+    assert_eq!(
+        pd.to_zk_text(&None),
+        "---\ntags: [video, youtube]\n---\n\n# Note"
+    );
+}
+
+#[test]
+fn test_normalize_heading_levels() {
+    let mut pd = parsing::parse_text("# A\n### B\n### C", &TextMode::Zk, &None).unwrap();
+    normalize_heading_levels(&mut pd);
+    assert_eq!(pd.to_zk_text(&None), "# A\n## B\n## C");
+
+    let mut pd = parsing::parse_text("## A\n#### B\n## C\n###### D", &TextMode::Zk, &None).unwrap();
+    normalize_heading_levels(&mut pd);
+    assert_eq!(pd.to_zk_text(&None), "# A\n## B\n# C\n## D");
+}
+
+#[test]
+fn test_convert_file_uses_tree_index() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.md"), "```dataview\nLIST FROM #project\n```").unwrap();
+    let dir = dir.canonicalize().unwrap();
+    let config = ConvertConfig {
+        materialize_dataview: Some(true),
+        ..Default::default()
+    };
+    let convert = |context: &mut ConversionContext| {
+        let file_info =
+            FileInfo::try_new(dir.join("index.md"), Some(dir.join("out.md")), None, None)
+                .unwrap()
+                .with_vault_root(Some(dir.clone()));
+        convert_file_in(
+            file_info,
+            TextMode::Obsidian,
+            TextMode::Zk,
+            &config,
+            context,
+        )
+        .unwrap();
+        std::fs::read_to_string(dir.join("out.md")).unwrap()
+    };
+
+    // the note only exists in the given index, the tree is not parsed again
+    let cached = dir.join("cached.md");
+    let pd = parsing::parse_text("see #project", &TextMode::Obsidian, &None).unwrap();
+    let mut context = ConversionContext {
+        tree_index: Some(TreeIndex::from([(cached.clone(), Ok(pd))])),
+    };
+    assert!(convert(&mut context).contains("cached.md"));
+
+    let mut context = ConversionContext::default();
+    assert!(!convert(&mut context).contains("cached.md"));
+    assert!(
+        context
+            .tree_index
+            .unwrap()
+            .contains_key(&dir.join("index.md"))
+    );
+}
+
+#[test]
+fn test_convert_file_keeps_indent_unit() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::write(dir.join("note.md"), "- a\n\t- b\n\t\t- c").unwrap();
+    let convert = |outmode: TextMode| {
+        let file_info =
+            FileInfo::try_new(dir.join("note.md"), Some(dir.join("out.md")), None, None).unwrap();
+        convert_file(
+            file_info,
+            TextMode::LogSeq,
+            outmode,
+            &ConvertConfig::default(),
+        )
+        .unwrap();
+        std::fs::read_to_string(dir.join("out.md")).unwrap()
+    };
+    assert_eq!(convert(TextMode::LogSeq), "- a\n\t- b\n\t\t- c\n");
+    // other formats use their default indentation
+    assert!(!convert(TextMode::Zk).contains('\t'));
+}
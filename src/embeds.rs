@@ -0,0 +1,222 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    document_component::{DocumentComponent, ListElem, MentionedFile, ParsedDocument},
+    inspect::{TreeIndex, parse_tree},
+    parsing::TextMode,
+};
+
+/// number of nested embeds that are inlined by `--follow-embeds`
+pub const MAX_EMBED_DEPTH: usize = 8;
+
+/// replaces note embeds by the components of the embedded note, recursively up to max_depth
+/// levels. Embeds the resolver returns None for (e.g. images) and embeds below max_depth are kept.
+/// An embed of a note that is already being inlined is replaced by a comment, notes are compared by
+/// their canonical path.
+pub fn inline_embeds(
+    pd: ParsedDocument,
+    resolver: &mut dyn FnMut(&MentionedFile) -> Option<ParsedDocument>,
+    max_depth: usize,
+) -> ParsedDocument {
+    let mut stack = vec![];
+    if let ParsedDocument::ParsedFile(_, path) = &pd {
+        stack.push(canonical(path));
+    }
+    let components = inline_components(pd.components(), resolver, max_depth, &mut stack);
+    match pd {
+        ParsedDocument::ParsedFile(_, path) => ParsedDocument::ParsedFile(components, path),
+        ParsedDocument::ParsedText(_) => ParsedDocument::ParsedText(components),
+    }
+}
+
+fn inline_components(
+    components: &[DocumentComponent],
+    resolver: &mut dyn FnMut(&MentionedFile) -> Option<ParsedDocument>,
+    depth: usize,
+    stack: &mut Vec<PathBuf>,
+) -> Vec<DocumentComponent> {
+    use DocumentComponent::*;
+    let mut res = vec![];
+    for c in components {
+        match c {
+            FileEmbed(file, _) if depth > 0 => match resolver(file) {
+                Some(ParsedDocument::ParsedFile(_, path)) if stack.contains(&canonical(&path)) => {
+                    res.push(Comment(format!("embed cycle: {}", note_name(file))));
+                }
+                Some(embedded) => {
+                    let is_file = matches!(embedded, ParsedDocument::ParsedFile(..));
+                    if let ParsedDocument::ParsedFile(_, path) = &embedded {
+                        stack.push(canonical(path));
+                    }
+                    res.extend(inline_components(
+                        embedded.components(),
+                        resolver,
+                        depth - 1,
+                        stack,
+                    ));
+                    if is_file {
+                        stack.pop();
+                    }
+                }
+                None => res.push(c.clone()),
+            },
+            Admonition(comps, props) => res.push(Admonition(
+                inline_components(comps, resolver, depth, stack),
+                props.clone(),
+            )),
+            List(list_elements, terminated_by_blank_line) => res.push(List(
+                list_elements
+                    .iter()
+                    .map(|le| inline_list_elem(le, resolver, depth, stack))
+                    .collect(),
+                *terminated_by_blank_line,
+            )),
+            _ => res.push(c.clone()),
+        }
+    }
+    res
+}
+
+fn inline_list_elem(
+    le: &ListElem,
+    resolver: &mut dyn FnMut(&MentionedFile) -> Option<ParsedDocument>,
+    depth: usize,
+    stack: &mut Vec<PathBuf>,
+) -> ListElem {
+    ListElem {
+        contents: ParsedDocument::ParsedText(inline_components(
+            le.contents.components(),
+            resolver,
+            depth,
+            stack,
+        )),
+        children: le
+            .children
+            .iter()
+            .map(|c| inline_list_elem(c, resolver, depth, stack))
+            .collect(),
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// name of the note without directories and `.md`
+fn note_name(file: &MentionedFile) -> String {
+    let name = match file {
+        MentionedFile::FileName(name) => name.rsplit('/').next().unwrap_or(name).to_string(),
+        MentionedFile::FilePath(path) => path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    name.trim_end_matches(".md").to_string()
+}
+
+/// finds the md note an embed refers to. Names are looked up next to the embedding note, in
+/// vault_root and finally among the notes of tree_index, which is only built from vault_root if
+/// it is needed.
+pub fn resolve_note(
+    file: &MentionedFile,
+    file_dir: &Path,
+    vault_root: &Path,
+    mode: &TextMode,
+    tree_index: &mut Option<TreeIndex>,
+) -> Option<PathBuf> {
+    let is_note = |p: &Path| p.is_file() && p.extension().is_some_and(|ext| ext == "md");
+    match file {
+        MentionedFile::FilePath(p) => Some(file_dir.join(p)).filter(|p| is_note(p)),
+        MentionedFile::FileName(name) => [file_dir, vault_root]
+            .iter()
+            .flat_map(|dir| [dir.join(name), dir.join(format!("{name}.md"))])
+            .find(|p| is_note(p))
+            .or_else(|| {
+                let name = note_name(file);
+                let index = match tree_index {
+                    Some(index) => index,
+                    None => tree_index.insert(parse_tree(vault_root, mode).ok()?),
+                };
+                index
+                    .keys()
+                    .find(|f| f.file_stem().is_some_and(|stem| *stem == *name))
+                    .cloned()
+            }),
+    }
+}
+
+#[test]
+fn test_inline_embeds() {
+    use crate::parsing::parse_text;
+    let note = |text: &str| parse_text(text, &TextMode::Zk, &None).unwrap();
+    let mut resolver = |file: &MentionedFile| match note_name(file).as_str() {
+        "embedded" => Some(note("embedded text")),
+        "self" => Some(ParsedDocument::ParsedFile(
+            note("before ![[self]]").into_components(),
+            PathBuf::from("self.md"),
+        )),
+        _ => None,
+    };
+
+    let pd = inline_embeds(note("intro\n![[embedded]]"), &mut resolver, 1);
+    assert_eq!(pd.to_zk_text(&None), "intro\nembedded text");
+
+    // the embed is kept once the maximum depth is reached
+    let pd = inline_embeds(note("![[embedded]]"), &mut resolver, 0);
+    assert!(matches!(
+        pd.components()[..],
+        [DocumentComponent::FileEmbed(..)]
+    ));
+
+    let pd = ParsedDocument::ParsedFile(
+        note("start ![[self]]").into_components(),
+        PathBuf::from("self.md"),
+    );
+    let pd = inline_embeds(pd, &mut resolver, MAX_EMBED_DEPTH);
+    assert_eq!(
+        pd.components().last(),
+        Some(&DocumentComponent::Comment("embed cycle: self".to_string()))
+    );
+}
+
+#[test]
+fn test_embed_of_note_with_same_name() {
+    use crate::parsing::{TextMode, parse_text};
+    let note = |text: &str| parse_text(text, &TextMode::Zk, &None).unwrap();
+    // notes in different directories with the same name are not a cycle
+    let mut resolver = |_: &MentionedFile| {
+        Some(ParsedDocument::ParsedFile(
+            note("other x").into_components(),
+            PathBuf::from("b/x.md"),
+        ))
+    };
+    let pd =
+        ParsedDocument::ParsedFile(note("![[b/x]]").into_components(), PathBuf::from("a/x.md"));
+    let pd = inline_embeds(pd, &mut resolver, 1);
+    assert_eq!(pd.to_zk_text(&None), "other x");
+}
+
+#[test]
+fn test_resolve_note_in_tree() {
+    use crate::parsing::TextMode;
+    let root_tmp = tempfile::tempdir().unwrap();
+    let root = root_tmp.path().canonicalize().unwrap();
+    std::fs::create_dir_all(root.join("sub").join("deep")).unwrap();
+    std::fs::write(root.join("a.md"), "![[note]]").unwrap();
+    std::fs::write(root.join("sub").join("deep").join("note.md"), "text").unwrap();
+
+    let mut tree_index = None;
+    let file = MentionedFile::FileName("a".to_string());
+    assert_eq!(
+        resolve_note(&file, &root, &root, &TextMode::Zk, &mut tree_index),
+        Some(root.join("a.md"))
+    );
+    // the tree is only indexed for notes that are not found directly
+    assert!(tree_index.is_none());
+    let file = MentionedFile::FileName("note".to_string());
+    assert_eq!(
+        resolve_note(&file, &root, &root, &TextMode::Zk, &mut tree_index),
+        Some(root.join("sub").join("deep").join("note.md"))
+    );
+    assert_eq!(tree_index.as_ref().map(|index| index.len()), Some(2));
+}
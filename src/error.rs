@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// errors of the functions that are usable as a library; the binary wraps them in anyhow
+#[derive(Debug, Error)]
+pub enum PkmtError {
+    /// the note was read, but the parser rejected it
+    #[error("Failed to parse {file:?}")]
+    Parse {
+        file: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// reading or writing failed, source is an io error or has one in its chain
+    #[error("Failed to access {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// the request failed or the response was not what was expected
+    #[error("Request to {url} failed")]
+    Network {
+        url: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// invalid options or missing settings
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+}
+
+impl PkmtError {
+    /// io errors anywhere in the chain of e become Io errors, all other ones Parse errors. The
+    /// whole chain is kept as the source
+    pub fn from_parse_error(file: &std::path::Path, e: anyhow::Error) -> Self {
+        if e.chain().any(|c| c.is::<std::io::Error>()) {
+            PkmtError::Io {
+                path: file.to_path_buf(),
+                source: e,
+            }
+        } else {
+            PkmtError::Parse {
+                file: file.to_path_buf(),
+                source: e,
+            }
+        }
+    }
+
+    pub fn io(path: &std::path::Path, e: std::io::Error) -> Self {
+        PkmtError::Io {
+            path: path.to_path_buf(),
+            source: e.into(),
+        }
+    }
+
+    pub fn network<E: Into<anyhow::Error>>(url: &str, e: E) -> Self {
+        PkmtError::Network {
+            url: url.to_string(),
+            source: e.into(),
+        }
+    }
+}
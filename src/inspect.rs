@@ -1,34 +1,420 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
 use edit_distance::edit_distance;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::result::Result::Ok;
+use std::time::Duration;
+use tracing::debug;
 
+use crate::document_component::{DocumentComponent, MentionedFile, ParsedDocument, PropValue};
+use crate::parsing::{TextMode, parse_file};
 use crate::util::files_in_tree;
 
-pub fn list_empty_files(root_dir: PathBuf) -> Result<()> {
-    let empty_files = get_empty_files(root_dir)?;
-    empty_files.iter().for_each(|f| println!("{f:?} is empty!"));
-    Ok(())
+pub const DEFAULT_WPM: usize = 200;
+
+/// (file, word count, estimated reading time)
+pub type ReadingStats = Vec<(PathBuf, usize, Duration)>;
+
+/// files that could not be read together with the reason
+pub type FileErrors = Vec<(PathBuf, anyhow::Error)>;
+
+/// collects the error of a single file if keep_going is set, otherwise it is returned
+fn collect_file_error<T>(
+    file: &Path,
+    res: Result<T>,
+    keep_going: bool,
+    errors: &mut FileErrors,
+) -> Result<Option<T>> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if keep_going => {
+            errors.push((file.to_path_buf(), e));
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
 }
 
-fn get_empty_files(root_dir: PathBuf) -> Result<Vec<PathBuf>> {
-    let files = files_in_tree(root_dir, &Some(vec!["md"]))?;
-    let res = files
+/// parsed md files of a tree by path
+pub type TreeIndex = BTreeMap<PathBuf, Result<ParsedDocument>>;
+
+/// parses all md files in the tree in parallel. The number of threads is that of the current
+/// rayon thread pool (see `RAYON_NUM_THREADS`).
+pub fn parse_tree<T: AsRef<Path>>(root_dir: T, mode: &TextMode) -> Result<TreeIndex> {
+    let files = files_in_tree(root_dir, &Some(vec!["md"]), None)?;
+    Ok(files
+        .into_par_iter()
+        .map(|f| {
+            let pd = parse_file(&f, mode).context(format!("Failed to parse {f:?}"));
+            (f, pd)
+        })
+        .collect())
+}
+
+/// returns the reading stats of all md files in the tree, longest first
+pub fn reading_stats<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    wpm: usize,
+    keep_going: bool,
+) -> Result<(ReadingStats, FileErrors)> {
+    let wpm = wpm.max(1);
+    let mut res = vec![];
+    let mut errors = vec![];
+    for (f, pd) in parse_tree(root_dir, mode)? {
+        if let Some(pd) = collect_file_error(&f, pd, keep_going, &mut errors)? {
+            let words = pd.to_plain_text().split_whitespace().count();
+            let reading_time = Duration::from_secs_f64(words as f64 * 60.0 / wpm as f64);
+            res.push((f, words, reading_time));
+        }
+    }
+    res.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok((res, errors))
+}
+
+/// keeps the notes with more than over and fewer than under words, thresholds that are not set
+/// are ignored. The order is kept
+pub fn filter_by_word_count(
+    stats: ReadingStats,
+    over: Option<usize>,
+    under: Option<usize>,
+) -> ReadingStats {
+    stats
         .into_iter()
-        .filter(|f| {
-            if let Ok(text) = std::fs::read_to_string(f) {
-                text.replace("-", "").is_empty()
-            } else {
-                false
+        .filter(|(_, words, _)| over.is_none_or(|over| *words > over))
+        .filter(|(_, words, _)| under.is_none_or(|under| *words < under))
+        .collect()
+}
+
+/// returns the md files in the tree that contain nothing but dashes. Files that cannot be read
+/// are skipped, they are only reported if keep_going is set
+pub fn empty_files<T: AsRef<Path>>(
+    root_dir: T,
+    keep_going: bool,
+) -> Result<(Vec<PathBuf>, FileErrors)> {
+    let files = files_in_tree(root_dir, &Some(vec!["md"]), None)?;
+    let mut res = vec![];
+    let mut errors = vec![];
+    for f in files {
+        match std::fs::read_to_string(&f).context(format!("Failed to read {f:?}")) {
+            Ok(text) if text.replace("-", "").is_empty() => res.push(f),
+            Ok(_) => {}
+            Err(e) if keep_going => errors.push((f, e)),
+            Err(_) => {}
+        }
+    }
+    Ok((res, errors))
+}
+
+/// returns the zk notes in the tree that lack some of the required frontmatter fields together
+/// with the missing fields. Notes without frontmatter lack all of them.
+pub fn missing_frontmatter_fields<T: AsRef<Path>>(
+    root_dir: T,
+    required: &[String],
+) -> Result<Vec<(PathBuf, Vec<String>)>> {
+    let index = parse_tree(root_dir, &TextMode::Zk)?;
+    check_parsed(&index)?;
+    Ok(missing_frontmatter_fields_in(&index, required))
+}
+
+/// missing_frontmatter_fields of the notes in index that could be parsed
+pub fn missing_frontmatter_fields_in(
+    index: &TreeIndex,
+    required: &[String],
+) -> Vec<(PathBuf, Vec<String>)> {
+    let mut res = vec![];
+    for (f, pd) in index {
+        let Ok(pd) = pd else {
+            continue;
+        };
+        let frontmatter =
+            pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Frontmatter(_)));
+        let missing: Vec<String> = required
+            .iter()
+            .filter(|name| {
+                !frontmatter.iter().any(|c| {
+                    matches!(c, DocumentComponent::Frontmatter(props) if props.iter().any(|p| p.has_name(name)))
+                })
+            })
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            res.push((f.clone(), missing));
+        }
+    }
+    res
+}
+
+/// returns the notes in the tree that contain some of the required properties without a value
+/// (e.g. a template property a `todoi` handler failed to fill) together with these properties
+pub fn notes_with_empty_required_props<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    required: &[String],
+) -> Result<Vec<(PathBuf, Vec<String>)>> {
+    let mut res = vec![];
+    for (f, pd) in parse_tree(root_dir, mode)? {
+        let pd = pd?;
+        let mut empty: Vec<String> = vec![];
+        let mut add = |name: &str| {
+            if required.iter().any(|r| r == name) && !empty.iter().any(|e| e == name) {
+                empty.push(name.to_string());
+            }
+        };
+        pd.for_each_component(&mut |c| match c {
+            DocumentComponent::Properties(props, _) | DocumentComponent::Frontmatter(props) => {
+                props
+                    .iter()
+                    .filter(|p| p.is_empty())
+                    .for_each(|p| add(p.name()))
+            }
+            DocumentComponent::InlineField(key, value) if value.trim().is_empty() => add(key),
+            _ => {}
+        });
+        if !empty.is_empty() {
+            res.push((f, empty));
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+/// returns the notes in which a property, frontmatter entry or inline field `(name:: value)` has
+/// the given value
+pub fn notes_with_field<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    name: &str,
+    value: &str,
+) -> Result<Vec<PathBuf>> {
+    let mut res = vec![];
+    for (f, pd) in parse_tree(root_dir, mode)? {
+        if pd?.has_field(name, value) {
+            res.push(f);
+        }
+    }
+    Ok(res)
+}
+
+/// name of the note a link refers to (lowercase file stem), None for links to other files like
+/// images
+fn linked_note(file: &MentionedFile) -> Option<String> {
+    let path = match file {
+        MentionedFile::FileName(name) => PathBuf::from(name),
+        MentionedFile::FilePath(path) => path.clone(),
+    };
+    let is_asset = path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy();
+        ext != "md" && ext.len() <= 4 && ext.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+    if is_asset {
+        return None;
+    }
+    Some(path.file_stem()?.to_string_lossy().to_lowercase())
+}
+
+/// links and embeds (including the ones in properties) of the notes that could be parsed
+fn note_links(index: &TreeIndex) -> BTreeMap<&PathBuf, Vec<MentionedFile>> {
+    let mut res = BTreeMap::new();
+    for (f, pd) in index {
+        let Ok(pd) = pd else {
+            continue;
+        };
+        let mut links = vec![];
+        pd.for_each_component(&mut |c| match c {
+            DocumentComponent::FileLink(file, _, _) | DocumentComponent::FileEmbed(file, _) => {
+                links.push(file.clone())
+            }
+            DocumentComponent::Properties(props, _) | DocumentComponent::Frontmatter(props) => {
+                props.iter().flat_map(|p| &p.values).for_each(|v| {
+                    if let PropValue::FileLink(file, _, _) = v {
+                        links.push(file.clone());
+                    }
+                })
             }
+            _ => {}
+        });
+        res.insert(f, links);
+    }
+    res
+}
+
+fn note_name(f: &Path) -> String {
+    f.file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// returns the first error of the index
+fn check_parsed(index: &TreeIndex) -> Result<()> {
+    if let Some((_, Err(e))) = index.iter().find(|(_, pd)| pd.is_err()) {
+        bail!("{e:#}");
+    }
+    Ok(())
+}
+
+/// returns the links to notes that do not exist in the tree together with the linking note.
+/// Note names are compared case-insensitively, links to other files are ignored.
+pub fn dangling_links<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+) -> Result<Vec<(PathBuf, String)>> {
+    let index = parse_tree(root_dir, mode)?;
+    check_parsed(&index)?;
+    Ok(dangling_links_in(&index))
+}
+
+/// dangling_links of the notes in index that could be parsed
+pub fn dangling_links_in(index: &TreeIndex) -> Vec<(PathBuf, String)> {
+    let notes: HashSet<String> = index.keys().map(|f| note_name(f)).collect();
+    let mut res = vec![];
+    for (f, links) in note_links(index) {
+        links
+            .iter()
+            .filter(|l| linked_note(l).is_some_and(|name| !notes.contains(&name)))
+            .for_each(|l| res.push((f.clone(), l.to_string())));
+    }
+    res.dedup();
+    res
+}
+
+/// returns the notes no other note links to
+pub fn orphan_notes<T: AsRef<Path>>(root_dir: T, mode: &TextMode) -> Result<Vec<PathBuf>> {
+    let index = parse_tree(root_dir, mode)?;
+    check_parsed(&index)?;
+    Ok(orphan_notes_in(&index))
+}
+
+/// orphan_notes of the notes in index that could be parsed
+pub fn orphan_notes_in(index: &TreeIndex) -> Vec<PathBuf> {
+    let links = note_links(index);
+    let linked: HashSet<String> = links
+        .iter()
+        .flat_map(|(f, links)| {
+            let own_name = note_name(f);
+            links
+                .iter()
+                .filter_map(linked_note)
+                .filter(move |name| *name != own_name)
         })
         .collect();
+    links
+        .into_keys()
+        .filter(|f| !linked.contains(&note_name(f)))
+        .cloned()
+        .collect()
+}
+
+/// reports unbalanced `[[`/`]]`, `$$` and unterminated code fences (and `#+BEGIN_`/`#+END_` blocks
+/// in logseq notes) of all notes in the tree. The notes are not parsed, so this also works for
+/// notes that fail to parse.
+pub fn inspect_syntax<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+) -> Result<Vec<(PathBuf, String)>> {
+    let mut res = vec![];
+    for f in files_in_tree(root_dir, &Some(vec!["md"]), None)? {
+        let text = std::fs::read_to_string(&f).context(format!("Failed to read {f:?}"))?;
+        syntax_issues(&text, mode)
+            .into_iter()
+            .for_each(|issue| res.push((f.clone(), issue)));
+    }
+    res.sort_by(|a, b| a.0.cmp(&b.0));
     Ok(res)
 }
 
-pub fn similar_file_names(root_dir: PathBuf, threshold: usize) {
-    let files = files_in_tree(root_dir, &Some(vec!["md"])).unwrap();
+pub(crate) fn syntax_issues(text: &str, mode: &TextMode) -> Vec<String> {
+    let inline_code = Regex::new("`[^`]*`").unwrap();
+    let mut res = vec![];
+    let mut open_fence = None;
+    let mut open_math = None;
+    let mut open_blocks: Vec<(String, usize)> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line_nr = i + 1;
+        let trimmed = line.trim_start();
+        let trimmed = trimmed.strip_prefix("- ").unwrap_or(trimmed).trim_start();
+        if trimmed.starts_with("```") {
+            open_fence = match open_fence {
+                Some(_) => None,
+                None => Some(line_nr),
+            };
+            continue;
+        }
+        if open_fence.is_some() {
+            continue;
+        }
+        if *mode == TextMode::LogSeq {
+            if let Some(kind) = trimmed.strip_prefix("#+BEGIN_") {
+                open_blocks.push((kind.trim().to_string(), line_nr));
+            } else if let Some(kind) = trimmed.strip_prefix("#+END_") {
+                match open_blocks.pop() {
+                    Some((open, _)) if open == kind.trim() => {}
+                    Some((open, open_line)) => res.push(format!(
+                        "`#+END_{}` on line {line_nr} closes `#+BEGIN_{open}` of line {open_line}",
+                        kind.trim()
+                    )),
+                    None => res.push(format!(
+                        "`#+END_{}` on line {line_nr} without `#+BEGIN_`",
+                        kind.trim()
+                    )),
+                }
+            }
+        }
+        let line = inline_code.replace_all(line, "");
+        for _ in line.matches("$$") {
+            open_math = match open_math {
+                Some(_) => None,
+                None => Some(line_nr),
+            };
+        }
+        // links cannot span multiple lines
+        let mut open_links: usize = 0;
+        let mut rest = line.as_ref();
+        while let Some(pos) = rest.find(['[', ']']) {
+            if rest[pos..].starts_with("[[") {
+                open_links += 1;
+                rest = &rest[pos + 2..];
+            } else if rest[pos..].starts_with("]]") {
+                if open_links == 0 {
+                    res.push(format!("unmatched `]]` on line {line_nr}"));
+                } else {
+                    open_links -= 1;
+                }
+                rest = &rest[pos + 2..];
+            } else {
+                rest = &rest[pos + 1..];
+            }
+        }
+        if open_links > 0 {
+            res.push(format!("unclosed `[[` on line {line_nr}"));
+        }
+    }
+    if let Some(line_nr) = open_fence {
+        res.push(format!("unterminated code fence opened on line {line_nr}"));
+    }
+    if let Some(line_nr) = open_math {
+        res.push(format!("unbalanced `$$` on line {line_nr}"));
+    }
+    open_blocks.iter().for_each(|(kind, line_nr)| {
+        res.push(format!(
+            "`#+BEGIN_{kind}` on line {line_nr} is never closed"
+        ))
+    });
+    res
+}
+
+/// groups of files (outside of `bak` directories) whose names differ by at most threshold edits.
+/// If since is set, only pairs containing a file modified since then are compared.
+pub fn similar_file_names<T: AsRef<Path>>(
+    root_dir: T,
+    threshold: usize,
+    since: Option<DateTime<Local>>,
+) -> Result<Vec<Vec<PathBuf>>> {
+    let files = files_in_tree(root_dir, &Some(vec!["md"]), None)?;
     let file_names: Vec<(String, PathBuf)> = files
         .iter()
         .filter(|f| {
@@ -42,28 +428,36 @@ pub fn similar_file_names(root_dir: PathBuf, threshold: usize) {
             )
         })
         .collect();
-    println!("Found {} files!", file_names.len());
+    debug!("Found {} files!", file_names.len());
+    let changed: Vec<bool> = match since {
+        Some(since) => file_names
+            .iter()
+            .map(|(_, f)| {
+                let modified = std::fs::metadata(f)
+                    .and_then(|m| m.modified())
+                    .context(format!("Could not get the modification time of {f:?}"))?;
+                Ok(DateTime::<Local>::from(modified) >= since)
+            })
+            .collect::<Result<_>>()?,
+        None => vec![true; file_names.len()],
+    };
     let mut clustering: Vec<usize> = (0..file_names.len()).collect();
-    println!("Building initial clustering");
     (0..file_names.len().saturating_sub(1)).for_each(|a| {
-        println!("{a}");
         let (first, _) = &file_names[a];
         ((a + 1)..file_names.len()).for_each(|b| {
             let (second, _) = &file_names[b];
-            if edit_distance(first, second) <= threshold {
+            if (changed[a] || changed[b]) && edit_distance(first, second) <= threshold {
                 clustering[a] = b;
             }
         })
     });
 
-    println!("Shortcutting clustering");
     // shortcut clustering
     (0..file_names.len()).rev().for_each(|i| {
         let next = clustering[i];
         clustering[i] = clustering[next]
     });
 
-    println!("Building final clusters");
     let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
     clustering.iter().enumerate().for_each(|(id, cluster_id)| {
         if let Some(v) = clusters.get_mut(cluster_id) {
@@ -73,18 +467,418 @@ pub fn similar_file_names(root_dir: PathBuf, threshold: usize) {
         }
     });
 
-    let clusters = clusters;
-    clusters.iter().for_each(|(_, components)| {
-        if components.len() > 1 {
-            let files: Vec<&PathBuf> = file_names
+    let mut res: Vec<Vec<PathBuf>> = clusters
+        .values()
+        .filter(|components| components.len() > 1)
+        .map(|components| {
+            let mut files: Vec<PathBuf> = components
                 .iter()
-                .enumerate()
-                .filter(|(index, _)| components.contains(index))
-                .map(|(_, (_, f))| f)
+                .map(|index| file_names[*index].1.clone())
                 .collect();
+            files.sort();
+            files
+        })
+        .collect();
+    res.sort();
+    Ok(res)
+}
+
+/// word count and reading time of a file in the json output of `pkmt inspect`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReadingEntry {
+    pub file: PathBuf,
+    pub words: usize,
+    pub minutes: u64,
+}
+
+/// results of `pkmt inspect`, categories that were not inspected are None and are not serialized
+#[derive(Debug, Default, Serialize)]
+pub struct InspectReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub empty_files: Option<Vec<PathBuf>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similar_names: Option<Vec<Vec<PathBuf>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reading_stats: Option<Vec<ReadingEntry>>,
+    /// file -> missing frontmatter fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing_frontmatter: Option<BTreeMap<PathBuf, Vec<String>>>,
+    /// notes in which a property or inline field has the value given by `--field`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matching_notes: Option<Vec<PathBuf>>,
+    /// file -> required properties without a value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub empty_props: Option<BTreeMap<PathBuf, Vec<String>>>,
+    /// file -> syntax issues
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub syntax_issues: Option<BTreeMap<PathBuf, Vec<String>>>,
+    /// file -> reason it could not be read
+    pub unreadable_files: BTreeMap<PathBuf, String>,
+}
+
+impl InspectReport {
+    pub fn with_empty_files(mut self, empty_files: Vec<PathBuf>) -> Self {
+        self.empty_files = Some(empty_files);
+        self
+    }
 
-            println!("The following files have very similar names:");
-            files.iter().for_each(|f| println!("\t{f:?}"));
+    pub fn with_similar_names(mut self, similar_names: Vec<Vec<PathBuf>>) -> Self {
+        self.similar_names = Some(similar_names);
+        self
+    }
+
+    pub fn with_reading_stats(mut self, stats: ReadingStats) -> Self {
+        self.reading_stats = Some(
+            stats
+                .into_iter()
+                .map(|(file, words, time)| ReadingEntry {
+                    file,
+                    words,
+                    minutes: time.as_secs().div_ceil(60),
+                })
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_missing_frontmatter(mut self, missing: Vec<(PathBuf, Vec<String>)>) -> Self {
+        self.missing_frontmatter = Some(missing.into_iter().collect());
+        self
+    }
+
+    pub fn with_matching_notes(mut self, notes: Vec<PathBuf>) -> Self {
+        self.matching_notes = Some(notes);
+        self
+    }
+
+    pub fn with_empty_props(mut self, empty: Vec<(PathBuf, Vec<String>)>) -> Self {
+        self.empty_props = Some(empty.into_iter().collect());
+        self
+    }
+
+    pub fn with_syntax_issues(mut self, issues: Vec<(PathBuf, String)>) -> Self {
+        let mut res: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+        issues
+            .into_iter()
+            .for_each(|(f, issue)| res.entry(f).or_default().push(issue));
+        self.syntax_issues = Some(res);
+        self
+    }
+
+    pub fn with_file_errors(mut self, errors: FileErrors) -> Self {
+        self.unreadable_files
+            .extend(errors.into_iter().map(|(f, e)| (f, format!("{e:#}"))));
+        self
+    }
+
+    pub fn print(&self) {
+        if let Some(empty_files) = &self.empty_files {
+            empty_files.iter().for_each(|f| println!("{f:?} is empty!"));
         }
-    });
+        if let Some(similar_names) = &self.similar_names {
+            similar_names.iter().for_each(|files| {
+                println!("The following files have very similar names:");
+                files.iter().for_each(|f| println!("\t{f:?}"));
+            });
+        }
+        if let Some(stats) = &self.reading_stats {
+            stats.iter().for_each(|e| {
+                println!("{:>7} words {:>5} min  {:?}", e.words, e.minutes, e.file);
+            });
+        }
+        if let Some(missing) = &self.missing_frontmatter {
+            missing
+                .iter()
+                .for_each(|(f, missing)| println!("{f:?} is missing {}", missing.join(", ")));
+        }
+        if let Some(notes) = &self.matching_notes {
+            notes.iter().for_each(|f| println!("{f:?}"));
+        }
+        if let Some(empty) = &self.empty_props {
+            empty
+                .iter()
+                .for_each(|(f, props)| println!("{f:?} has empty {}", props.join(", ")));
+        }
+        if let Some(issues) = &self.syntax_issues {
+            issues
+                .iter()
+                .for_each(|(f, issues)| issues.iter().for_each(|issue| println!("{f:?}: {issue}")));
+        }
+        if !self.unreadable_files.is_empty() {
+            self.unreadable_files
+                .iter()
+                .for_each(|(f, e)| println!("{f:?}: {e}"));
+            println!("{} files could not be read", self.unreadable_files.len());
+        }
+    }
+}
+
+/// reports empty files and files with similar names, see similar_file_names for since
+pub fn inspect_tree<T: AsRef<Path>>(
+    root_dir: T,
+    keep_going: bool,
+    since: Option<DateTime<Local>>,
+) -> Result<InspectReport> {
+    let root_dir = root_dir.as_ref();
+    let (empty_files, errors) = empty_files(root_dir, keep_going)?;
+    Ok(InspectReport::default()
+        .with_empty_files(empty_files)
+        .with_similar_names(similar_file_names(root_dir, 4, since)?)
+        .with_file_errors(errors))
+}
+
+#[test]
+fn test_reading_stats() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("long.md"),
+        "---\ntags: [a, b, c]\n---\n\n# Long note\n- one two [three](short.md)\n- four",
+    )
+    .unwrap();
+    std::fs::write(dir.join("short.md"), "just two").unwrap();
+
+    let (stats, _) = reading_stats(&dir, &TextMode::Zk, 60, false).unwrap();
+    let stats: Vec<(String, usize, Duration)> = stats
+        .into_iter()
+        .map(|(f, w, d)| (f.file_name().unwrap().to_string_lossy().to_string(), w, d))
+        .collect();
+    assert_eq!(
+        stats,
+        vec![
+            ("long.md".to_string(), 6, Duration::from_secs(6)),
+            ("short.md".to_string(), 2, Duration::from_secs(2)),
+        ]
+    );
+    let (slower, _) = reading_stats(&dir, &TextMode::Zk, 30, false).unwrap();
+    assert_eq!(slower[0].2, Duration::from_secs(12));
+}
+
+#[test]
+fn test_filter_by_word_count() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("long.md"), "one two three four five six").unwrap();
+    std::fs::write(dir.join("medium.md"), "one two three four").unwrap();
+    std::fs::write(dir.join("stub.md"), "one").unwrap();
+    let (stats, _) = reading_stats(&dir, &TextMode::Zk, DEFAULT_WPM, false).unwrap();
+    let sizes = |stats: ReadingStats| -> Vec<(PathBuf, usize)> {
+        stats.into_iter().map(|(f, words, _)| (f, words)).collect()
+    };
+
+    assert_eq!(
+        sizes(filter_by_word_count(stats.clone(), Some(3), None)),
+        vec![(dir.join("long.md"), 6), (dir.join("medium.md"), 4)]
+    );
+    assert_eq!(
+        sizes(filter_by_word_count(stats.clone(), None, Some(4))),
+        vec![(dir.join("stub.md"), 1)]
+    );
+    assert_eq!(
+        sizes(filter_by_word_count(stats.clone(), Some(1), Some(6))),
+        vec![(dir.join("medium.md"), 4)]
+    );
+    assert_eq!(filter_by_word_count(stats.clone(), None, None), stats);
+}
+
+#[test]
+fn test_keep_going_on_unreadable_file() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("empty.md"), "-").unwrap();
+    std::fs::write(dir.join("note.md"), "some words").unwrap();
+    // invalid utf-8 cannot be read as text
+    std::fs::write(dir.join("unreadable.md"), [0xff, 0xfe, 0x00]).unwrap();
+
+    // unreadable files are skipped when checking for empty files
+    let (empty, errors) = empty_files(&dir, false).unwrap();
+    assert_eq!(empty, vec![dir.join("empty.md")]);
+    assert!(errors.is_empty());
+    let (empty_files, errors) = empty_files(&dir, true).unwrap();
+    assert_eq!(empty_files, vec![dir.join("empty.md")]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, dir.join("unreadable.md"));
+
+    assert!(reading_stats(&dir, &TextMode::Zk, 60, false).is_err());
+    let (stats, errors) = reading_stats(&dir, &TextMode::Zk, 60, true).unwrap();
+    assert_eq!(stats.len(), 2);
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_missing_frontmatter_fields() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("complete.md"),
+        "---\ndate: 2025-01-02\ntags: [a]\nurl: https://example.com\n---\n\n# Complete",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("no_url.md"),
+        "---\ndate: 2025-01-02\ntags: [a]\n---\n\n# No url",
+    )
+    .unwrap();
+
+    let required = ["date", "tags", "url"].map(|s| s.to_string());
+    let missing = missing_frontmatter_fields(&dir, &required).unwrap();
+    assert_eq!(
+        missing,
+        vec![(dir.join("no_url.md"), vec!["url".to_string()])]
+    );
+}
+
+#[test]
+fn test_notes_with_empty_required_props() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("filled.md"),
+        "- url:: https://example.com\n  source:: youtube\n- notes",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("unfilled.md"),
+        "- url::\n  source:: youtube\n  comment::\n- notes",
+    )
+    .unwrap();
+
+    let required = ["url", "source"].map(|s| s.to_string());
+    let empty = notes_with_empty_required_props(&dir, &TextMode::LogSeq, &required).unwrap();
+    assert_eq!(
+        empty,
+        vec![(dir.join("unfilled.md"), vec!["url".to_string()])]
+    );
+}
+
+#[test]
+fn test_inspect_syntax() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("fine.md"),
+        "# Fine\nsee [[other]] and `[[`\n$$\nx\n$$\n```\n[[ in code\n```",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("broken.md"),
+        "# Broken\nsee [[other\n```rust\nfn main() {}\n",
+    )
+    .unwrap();
+
+    let issues = inspect_syntax(&dir, &TextMode::Zk).unwrap();
+    assert_eq!(
+        issues,
+        vec![
+            (dir.join("broken.md"), "unclosed `[[` on line 2".to_string()),
+            (
+                dir.join("broken.md"),
+                "unterminated code fence opened on line 3".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_inspect_report_json() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("empty.md"), "").unwrap();
+    std::fs::write(dir.join("note_one.md"), "text").unwrap();
+    std::fs::write(dir.join("note_two.md"), "text").unwrap();
+
+    let report = inspect_tree(&dir, false, None).unwrap();
+    let json = serde_json::to_value(&report).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "empty_files": [path("empty.md")],
+            "similar_names": [[path("note_one.md"), path("note_two.md")]],
+            "unreadable_files": {},
+        })
+    );
+}
+
+#[test]
+fn test_similar_file_names_since() {
+    use std::time::{Duration as StdDuration, SystemTime};
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    let old = SystemTime::now() - StdDuration::from_secs(30 * 24 * 60 * 60);
+    for name in [
+        "old_note_a.md",
+        "old_note_b.md",
+        "project.md",
+        "projects.md",
+    ] {
+        let file = std::fs::File::create(dir.join(name)).unwrap();
+        if name != "projects.md" {
+            file.set_modified(old).unwrap();
+        }
+    }
+
+    let all = similar_file_names(&dir, 1, None).unwrap();
+    assert_eq!(all.len(), 2);
+
+    let since = Local::now() - chrono::Duration::days(7);
+    let recent = similar_file_names(&dir, 1, Some(since)).unwrap();
+    assert_eq!(
+        recent,
+        vec![vec![dir.join("project.md"), dir.join("projects.md")]]
+    );
+}
+
+#[test]
+fn test_parallel_inspection_is_deterministic() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    for i in 0..24 {
+        let sub = dir.join(format!("dir{}", i % 4));
+        std::fs::create_dir_all(&sub).unwrap();
+        let url = if i % 3 == 0 {
+            ""
+        } else {
+            "https://example.com"
+        };
+        std::fs::write(
+            sub.join(format!("note{i}.md")),
+            format!("---\nurl: {url}\n---\n\n{}", "word ".repeat(i * 7 % 11)),
+        )
+        .unwrap();
+    }
+
+    let run = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let index: Vec<PathBuf> = parse_tree(&dir, &TextMode::Zk)
+                .unwrap()
+                .into_iter()
+                .map(|(f, pd)| {
+                    assert!(pd.is_ok(), "{pd:?}");
+                    f
+                })
+                .collect();
+            let (stats, errors) = reading_stats(&dir, &TextMode::Zk, DEFAULT_WPM, false).unwrap();
+            assert!(errors.is_empty());
+            let required = ["url".to_string()];
+            let empty = notes_with_empty_required_props(&dir, &TextMode::Zk, &required).unwrap();
+            (index, stats, empty)
+        })
+    };
+    let sequential = run(1);
+    assert_eq!(sequential.0.len(), 24);
+    assert_eq!(sequential.2.len(), 8);
+    assert_eq!(run(4), sequential);
 }
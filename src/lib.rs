@@ -0,0 +1,15 @@
+pub mod convert_config;
+pub mod dataview;
+pub mod doctor;
+pub mod document_component;
+pub mod embeds;
+pub mod error;
+pub mod file_checklist;
+pub mod inspect;
+pub mod move_note;
+pub mod parsing;
+pub mod tags;
+pub mod tangle;
+pub mod tidy;
+pub mod todoi;
+pub mod util;
@@ -1,26 +1,50 @@
 use anyhow::{Context, Result, bail};
+use chrono::{Duration, Local, NaiveDate, Utc};
 use clap::{Parser, Subcommand};
 
-use todoi::handlers::zk_handler::{get_zk_creator_file, set_zk_creator_file};
+use todoi::handlers::zk_handler::{
+    ZkHandler, discover_zk_root, instantiate_template, set_zk_creator_file,
+};
 use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 extern crate tracing;
 
-mod file_checklist;
-use document_component::{FileInfo, convert_file, convert_tree};
+use pkmt::{
+    convert_config, doctor, document_component, file_checklist, inspect, move_note, parsing, tags,
+    tangle, tidy, todoi, util,
+};
+
+use convert_config::ConvertConfig;
+use doctor::{DoctorOptions, Severity, doctor};
+use document_component::{
+    Collisions, ConversionEntry, ConversionReport, FileInfo, OnCollision, TreeFilter, TreeOptions,
+    convert_file, convert_reader, convert_tree,
+};
 use file_checklist::checklist_for_tree;
-use inspect::{list_empty_files, similar_file_names};
+use inspect::{
+    DEFAULT_WPM, InspectReport, filter_by_word_count, inspect_syntax, inspect_tree,
+    missing_frontmatter_fields, notes_with_empty_required_props, notes_with_field, reading_stats,
+};
+use move_note::move_note;
+use tags::{TagRename, print_tags, retag_tree};
+use tangle::tangle;
+use tidy::tidy_tree;
 use util::files_in_tree;
 
-use std::{collections::HashSet, fmt::Debug, path::PathBuf};
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
-use crate::todoi::config::Tags;
-mod document_component;
-mod inspect;
+use crate::todoi::{TodoiOptions, audit_log::AuditLog, config::Tags};
 
-use parsing::TextMode;
-mod parsing;
-mod todoi;
-mod util;
+use parsing::{
+    CalloutTitleStyle, FrontmatterFormat, FrontmatterListStyle, InputEncoding, LineEnding,
+    LinkStyle, NestedTagStyle, TextMode,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -34,6 +58,7 @@ enum Commands {
     /// convert between different formats
     Convert {
         /// path to parse. If this is a directory, the out_path must also be a directory. Missing directories in out_path will be created.
+        /// `-` reads a single note from stdin
         #[arg(required = true)]
         in_path: PathBuf,
 
@@ -49,6 +74,10 @@ enum Commands {
         #[arg(value_enum)]
         outmode: TextMode,
 
+        /// further directories converted into the same out_path, e.g.
+        /// `pkmt convert vault_a out zk logseq vault_b vault_c`
+        extra_in_paths: Vec<PathBuf>,
+
         /// image directory for the input files. If this is set, found image files will be copied to the output image dir `imout` (required in this case)
         #[arg(long)]
         imdir: Option<PathBuf>,
@@ -56,6 +85,57 @@ enum Commands {
         /// image output directory
         #[arg(long)]
         imout: Option<PathBuf>,
+
+        /// remove converted files in out_path whose source file no longer exists in in_path
+        #[arg(long, default_value_t = false)]
+        mirror_deletions: bool,
+
+        /// what to do if files of several input trees are converted to the same file
+        #[arg(long, value_enum, default_value_t = OnCollision::Error)]
+        on_collision: OnCollision,
+
+        /// only print the files that would be removed by --mirror-deletions
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+
+        /// write a json report of the converted files to this path
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// format of the frontmatter in the output files
+        #[arg(long, value_enum, default_value_t = FrontmatterFormat::Yaml)]
+        frontmatter_format: FrontmatterFormat,
+
+        /// only convert files whose path relative to in_path matches one of these globs
+        #[arg(long)]
+        include: Vec<String>,
+
+        /// skip files whose path relative to in_path matches one of these globs
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// only convert files in at most this many levels of subdirectories, 0 for in_path only
+        #[arg(long)]
+        max_depth: Option<usize>,
+
+        /// toml file mapping property names to the names used in the output
+        #[arg(long)]
+        prop_map: Option<PathBuf>,
+
+        /// apply the property map from new to old names
+        #[arg(long, default_value_t = false, requires = "prop_map")]
+        reverse_prop_map: bool,
+
+        #[command(flatten)]
+        config: Box<ConvertArgs>,
+
+        /// show a progress bar while converting a directory (only if stdout is a terminal)
+        #[arg(long, default_value_t = false)]
+        progress: bool,
+
+        /// print the N files that took the longest to convert
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10")]
+        timing: Option<usize>,
     },
     /// generate a file checklist
     Checklist {
@@ -75,6 +155,165 @@ enum Commands {
         /// root directory to inspect
         #[arg(required = true)]
         root_dir: PathBuf,
+        /// list the word count and estimated reading time per file instead
+        #[arg(long, default_value_t = false)]
+        reading: bool,
+        /// reading speed in words per minute
+        #[arg(long, default_value_t = DEFAULT_WPM)]
+        wpm: usize,
+        #[arg(short, long, required = false)]
+        mode: Option<TextMode>,
+        /// report files that cannot be read instead of aborting (or skipping them when checking for
+        /// empty files)
+        #[arg(long, default_value_t = false)]
+        keep_going: bool,
+        /// list the zk notes missing any of these frontmatter fields instead
+        #[arg(long, value_delimiter = ',')]
+        require: Vec<String>,
+        /// report unbalanced `[[`, `$$` and code fences without parsing the notes instead
+        #[arg(long, default_value_t = false)]
+        syntax: bool,
+        /// print the results as a single json object
+        #[arg(long, default_value_t = false)]
+        json: bool,
+        /// list the notes in which a property or inline field `(name:: value)` has this value instead,
+        /// inline fields are only found in zk and obsidian notes
+        #[arg(long, value_parser = parse_template_var, requires = "mode")]
+        field: Option<(String, String)>,
+        /// date (YYYY-MM-DD) from which on modified files are compared to all others for similar names
+        #[arg(long)]
+        since: Option<NaiveDate>,
+    },
+    /// list the notes with more or fewer words than a threshold, longest first
+    BigNotes {
+        /// root directory of the notes
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// list notes with more words, e.g. to split them
+        #[arg(long, required_unless_present = "under")]
+        over: Option<usize>,
+        /// list notes with fewer words, e.g. stubs
+        #[arg(long)]
+        under: Option<usize>,
+        #[arg(short, long, required = false)]
+        mode: Option<TextMode>,
+        /// report files that cannot be read instead of aborting
+        #[arg(long, default_value_t = false)]
+        keep_going: bool,
+        /// print the results as a single json object
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// list the notes in which template properties were left empty
+    CheckTemplates {
+        /// root directory of the notes
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// parsing mode
+        #[arg(value_enum)]
+        mode: TextMode,
+        /// properties that must have a value if they are present
+        #[arg(long, value_delimiter = ',', default_values_t = ["url".to_string(), "source".to_string()])]
+        require: Vec<String>,
+        /// print the results as a single json object
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// run all health checks on the notes, exits with 1 if there are severe issues
+    Doctor {
+        /// root directory of the notes
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// parsing mode
+        #[arg(value_enum)]
+        mode: TextMode,
+        /// frontmatter fields every note must have
+        #[arg(long, value_delimiter = ',')]
+        require: Vec<String>,
+        /// print the report as a single json object
+        #[arg(long, default_value_t = false)]
+        json: bool,
+        /// exit with 1 if there are issues of at least this severity
+        #[arg(long, value_enum, default_value_t = Severity::Critical)]
+        fail_on: Severity,
+    },
+    /// list all tags used in the notes with their number of occurrences
+    Tags {
+        /// root directory of the notes
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// parsing mode
+        #[arg(value_enum)]
+        mode: TextMode,
+        /// only include notes in at most this many levels of subdirectories, 0 for root_dir only
+        #[arg(long)]
+        max_depth: Option<usize>,
+        /// only list tags starting with this prefix, e.g. `project/`
+        #[arg(long)]
+        prefix: Option<String>,
+        /// print the counts as json
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// rename a tag in all notes in place, changed notes are re-rendered as a whole
+    Retag {
+        /// root directory of the notes
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// parsing mode
+        #[arg(value_enum)]
+        mode: TextMode,
+        /// tag to rename, or the prefix to replace with --prefix
+        #[arg(required = true)]
+        from: String,
+        /// new name of the tag or new prefix
+        #[arg(required = true)]
+        to: String,
+        /// rename all tags starting with `from`, e.g. `project/ area/` renames `project/x` to `area/x`
+        #[arg(long, default_value_t = false)]
+        prefix: bool,
+        /// only include notes in at most this many levels of subdirectories, 0 for root_dir only
+        #[arg(long)]
+        max_depth: Option<usize>,
+    },
+    /// sort the properties of all notes in place (see `property_order` in pkmt-convert.toml)
+    Tidy {
+        /// root directory of the notes
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// parsing mode
+        #[arg(value_enum)]
+        mode: TextMode,
+        /// only include notes in at most this many levels of subdirectories, 0 for root_dir only
+        #[arg(long)]
+        max_depth: Option<usize>,
+    },
+    /// move a note into another directory and update the links in it and pointing to it
+    Move {
+        /// root directory of the notes whose links should be updated
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// parsing mode
+        #[arg(value_enum)]
+        mode: TextMode,
+        /// note to move
+        #[arg(required = true)]
+        file: PathBuf,
+        /// directory to move the note to. It is created if it does not exist.
+        #[arg(required = true)]
+        dest_dir: PathBuf,
+    },
+    /// write the code blocks with a language tag of a note to files
+    Tangle {
+        /// note to extract the code blocks from
+        #[arg(required = true)]
+        file: PathBuf,
+        /// parsing mode
+        #[arg(value_enum)]
+        mode: TextMode,
+        /// directory the files are written to
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
     },
     /// todoist import
     Todoi {
@@ -82,14 +321,72 @@ enum Commands {
         graph_root: Option<PathBuf>,
         #[arg(short, long, default_value_t = false, required = false)]
         complete_tasks: bool,
+        /// only print the tasks that would be marked as completed
+        #[arg(long, default_value_t = false, required = false)]
+        preview_completions: bool,
+        /// do not run the configured post-creation hooks
+        #[arg(long, default_value_t = false, required = false)]
+        no_hooks: bool,
+        /// directory of the zk templates (default: `zk_template_dir` of the settings or `.zk/templates`)
+        #[arg(long, required = false)]
+        template_dir: Option<PathBuf>,
         #[arg(short, long, required = false)]
         mode: Option<TextMode>,
+        /// also import inbox tasks that were already completed. They are not closed again.
+        #[arg(long, default_value_t = false, required = false)]
+        include_completed: bool,
+        /// date (YYYY-MM-DD) from which on completed tasks are included (default: 7 days ago)
+        #[arg(long, required = false, requires = "include_completed")]
+        since: Option<NaiveDate>,
+        /// skip tasks that were handled in a previous run (runs with --preview-completions are not
+        /// recorded)
+        #[arg(long, default_value_t = false, required = false)]
+        only_new: bool,
+        /// also import inbox tasks that have subtasks or a parent task
+        #[arg(long, default_value_t = false, required = false)]
+        all_tasks: bool,
+        /// only import tasks whose content matches this regex
+        #[arg(long, required = false)]
+        filter: Option<String>,
+        /// list the tasks that could not be classified without asking at the end
+        #[arg(long, default_value_t = false, required = false)]
+        report_unhandled: bool,
+        /// show the note an interactive task would create on stderr and ask for confirmation
+        #[arg(long, default_value_t = false, required = false)]
+        preview: bool,
+        /// print the tasks that were (or with --preview-completions would be) marked as completed
+        /// as a single json object
+        #[arg(long, default_value_t = false, required = false)]
+        json: bool,
     },
     /// config for todoist import
     TodoiConfig {
         #[clap(subcommand)]
         tcfg_command: TCfgCommand,
     },
+    /// log of the notes created by the todoist import
+    TodoiLog {
+        #[clap(subcommand)]
+        log_command: LogCommand,
+    },
+    /// create a note from a zk template
+    New {
+        /// root directory of the zk notebook
+        #[arg(required = true)]
+        root_dir: PathBuf,
+        /// name of the template in the template directory
+        #[arg(long)]
+        template: String,
+        /// directory of the templates (default: `zk_template_dir` of the settings or `.zk/templates`)
+        #[arg(long)]
+        template_dir: Option<PathBuf>,
+        /// title of the note
+        #[arg(long)]
+        title: String,
+        /// additional values in the form name=value
+        #[arg(long = "set", value_parser = parse_template_var)]
+        vars: Vec<(String, String)>,
+    },
     /// todoi creator manipulation
     Creator {
         #[arg(required = true)]
@@ -103,6 +400,16 @@ enum Commands {
     },
 }
 
+#[derive(Clone, Subcommand)]
+enum LogCommand {
+    /// print the most recent entries
+    Show {
+        /// number of entries
+        #[arg(short = 'n', long, default_value_t = 20)]
+        count: usize,
+    },
+}
+
 #[derive(Clone, Subcommand)]
 enum TCfgCommand {
     /// show config paths
@@ -154,10 +461,170 @@ enum CreatorCommand {
     },
 }
 
+/// options of `convert` that can also be set in pkmt-convert.toml
+#[derive(clap::Args)]
+struct ConvertArgs {
+    /// write multi-value yaml frontmatter entries inline (`[a, b]`) or as one `- value` line per value
+    #[arg(long, value_enum)]
+    frontmatter_list_style: Option<FrontmatterListStyle>,
+
+    /// render top-level lists with a single prose item as paragraphs
+    #[arg(long, default_value_t = false)]
+    flatten_outline: bool,
+
+    /// syntax of links in the output, independent of the output format
+    #[arg(long, value_enum)]
+    link_style: Option<LinkStyle>,
+
+    /// write nested tags in tags properties as they are or with all their parents, e.g. `a, a/b`.
+    /// Exploded nested inline tags are added to the frontmatter of zk and obsidian output
+    #[arg(long, value_enum)]
+    nested_tag_style: Option<NestedTagStyle>,
+
+    /// sort properties and frontmatter entries (see `property_order` in pkmt-convert.toml)
+    #[arg(long, default_value_t = false)]
+    sort_props: bool,
+
+    /// remove repeated values of properties and frontmatter entries, e.g. duplicate tags
+    #[arg(long, default_value_t = false)]
+    dedupe_tags: bool,
+
+    /// fail on constructs the zk parser does not support instead of keeping them as text. Only
+    /// affects zk input
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// keep blocks that fail to parse as text and print a warning instead of failing the note
+    #[arg(long, default_value_t = false)]
+    best_effort: bool,
+
+    /// replace `LIST FROM #tag` dataview queries by lists of links to the matching notes
+    #[arg(long, default_value_t = false)]
+    materialize_dataview: bool,
+
+    /// keep runs of blank lines between blocks in logseq output instead of removing them, zk
+    /// output always keeps them
+    #[arg(long, default_value_t = false)]
+    preserve_blank_lines: bool,
+
+    /// drop comments like `%%comment%%` instead of converting them to html comments
+    #[arg(long, default_value_t = false)]
+    strip_comments: bool,
+
+    /// encoding of the notes, a leading byte order mark is removed (default: utf8)
+    #[arg(long, value_enum)]
+    input_encoding: Option<InputEncoding>,
+
+    /// extension of the converted files, e.g. `org`
+    #[arg(long)]
+    output_extension: Option<String>,
+
+    /// replace the `.md` extension of link targets by the output extension
+    #[arg(long, default_value_t = false, requires = "output_extension")]
+    rewrite_link_extensions: bool,
+
+    /// convert deeper headings to headings of this level
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..=6))]
+    max_heading_level: Option<u16>,
+
+    /// renumber headings so that their levels never skip one
+    #[arg(long, default_value_t = false)]
+    normalize_headings: bool,
+
+    /// start logseq blocks with children nested at least this deep collapsed, 0 for all blocks
+    #[arg(long)]
+    collapse_depth: Option<usize>,
+
+    /// render admonition titles in logseq output in bold or as a heading above the quote
+    #[arg(long, value_enum)]
+    callout_title_style: Option<CalloutTitleStyle>,
+
+    /// rewrite file links to paths relative to in_path
+    #[arg(long, default_value_t = false)]
+    canonical_links: bool,
+
+    /// add an `original_path` property with the path of each note relative to in_path
+    #[arg(long, default_value_t = false)]
+    emit_source_path: bool,
+
+    /// render embeds of notes as `[[file]]` links instead of `{{embed [[file]]}}` in logseq output
+    #[arg(long, default_value_t = false)]
+    no_embed_rewrite: bool,
+
+    /// end converted files with a single newline (`true`, default) or none (`false`)
+    #[arg(long)]
+    trailing_newline: Option<bool>,
+
+    /// line terminator of the converted files (default: lf)
+    #[arg(long, value_enum)]
+    line_ending: Option<LineEnding>,
+
+    /// replace embeds of notes by the content of the embedded notes
+    #[arg(long, default_value_t = false)]
+    follow_embeds: bool,
+
+    /// recreate directories of in_path that contain no notes
+    #[arg(long, default_value_t = false)]
+    preserve_empty_dirs: bool,
+
+    /// remove these frontmatter entries before converting
+    #[arg(long, value_delimiter = ',')]
+    drop_frontmatter_keys: Vec<String>,
+
+    /// remove all frontmatter entries except these before converting
+    #[arg(long, value_delimiter = ',')]
+    keep_frontmatter_keys: Vec<String>,
+}
+
+impl From<ConvertArgs> for ConvertConfig {
+    fn from(args: ConvertArgs) -> Self {
+        // flags that are not passed leave the option unset, so that it can be configured
+        let flag = |set: bool| set.then_some(true);
+        let keys = |keys: Vec<String>| Some(keys).filter(|keys| !keys.is_empty());
+        ConvertConfig {
+            frontmatter_list_style: args.frontmatter_list_style,
+            flatten_outline: flag(args.flatten_outline),
+            link_style: args.link_style,
+            nested_tag_style: args.nested_tag_style,
+            sort_properties: flag(args.sort_props),
+            dedupe_property_values: flag(args.dedupe_tags),
+            strict: flag(args.strict),
+            best_effort: flag(args.best_effort),
+            materialize_dataview: flag(args.materialize_dataview),
+            preserve_blank_lines: flag(args.preserve_blank_lines),
+            strip_comments: flag(args.strip_comments),
+            input_encoding: args.input_encoding,
+            output_extension: args.output_extension,
+            rewrite_link_extensions: flag(args.rewrite_link_extensions),
+            max_heading_level: args.max_heading_level,
+            normalize_headings: flag(args.normalize_headings),
+            collapse_depth: args.collapse_depth,
+            callout_title_style: args.callout_title_style,
+            canonical_links: flag(args.canonical_links),
+            emit_source_path: flag(args.emit_source_path),
+            embeds_as_links: flag(args.no_embed_rewrite),
+            trailing_newline: args.trailing_newline,
+            line_ending: args.line_ending,
+            follow_embeds: flag(args.follow_embeds),
+            preserve_empty_dirs: flag(args.preserve_empty_dirs),
+            drop_frontmatter_keys: keys(args.drop_frontmatter_keys),
+            keep_frontmatter_keys: keys(args.keep_frontmatter_keys),
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {}
 
+fn parse_template_var(s: &str) -> Result<(String, String)> {
+    let (name, value) = s
+        .split_once('=')
+        .context(format!("Expected name=value, got {s:?}"))?;
+    Ok((name.trim().to_string(), value.to_string()))
+}
+
 fn main() {
     let res = run();
     if res.is_err() {
@@ -177,23 +644,62 @@ fn run() -> Result<()> {
         Some(Commands::Todoi {
             graph_root,
             complete_tasks,
+            preview_completions,
+            no_hooks,
+            template_dir,
             mode,
+            include_completed,
+            since,
+            only_new,
+            all_tasks,
+            filter,
+            report_unhandled,
+            preview,
+            json,
         }) => {
+            let filter = filter
+                .map(|f| regex::Regex::new(&f).context(format!("Invalid filter {f:?}")))
+                .transpose()?;
             let mode = mode.unwrap_or(TextMode::LogSeq);
+            let completed_since = if include_completed {
+                let since = since.unwrap_or((Local::now() - Duration::days(7)).date_naive());
+                let since = since
+                    .and_hms_opt(0, 0, 0)
+                    .and_then(|d| d.and_local_timezone(Local).single())
+                    .context(format!("Invalid date {since}"))?;
+                Some(since.with_timezone(&Utc))
+            } else {
+                None
+            };
             let graph_root = if let Some(graph_root) = graph_root {
                 graph_root
             } else if mode == TextMode::Zk {
                 if let Ok(notebook_dir) = std::env::var("ZK_NOTEBOOK_DIR") {
                     PathBuf::from(notebook_dir)
+                } else if let Some(notebook_dir) = discover_zk_root(&std::env::current_dir()?) {
+                    notebook_dir
                 } else {
                     bail!(
-                        "Could not determine zk notebook dir. Either specify it via the environment variable 'ZK_NOTEBOOK_DIR' or specify it directly!"
+                        "Could not determine zk notebook dir. Either specify it via the environment variable 'ZK_NOTEBOOK_DIR', run pkmt inside the notebook or specify it directly!"
                     );
                 }
             } else {
                 bail!("Could not determine graph root!");
             };
-            todoi::main(graph_root, complete_tasks, mode)?;
+            let options = TodoiOptions {
+                complete_tasks,
+                preview_completions,
+                no_hooks,
+                completed_since,
+                only_new,
+                all_tasks,
+                filter,
+                template_dir,
+                report_unhandled,
+                preview,
+                json,
+            };
+            todoi::main(graph_root, mode, options)?;
             Ok(())
         }
         Some(Commands::TodoiConfig { tcfg_command }) => match tcfg_command {
@@ -218,6 +724,15 @@ fn run() -> Result<()> {
                 all_tags.add_url_sources(url, sources)
             }
         },
+        Some(Commands::TodoiLog { log_command }) => match log_command {
+            LogCommand::Show { count } => {
+                let log = AuditLog::new(AuditLog::default_path());
+                log.recent(count)?
+                    .iter()
+                    .for_each(|entry| println!("{entry}"));
+                Ok(())
+            }
+        },
         Some(Commands::Checklist {
             root_dir,
             out_file,
@@ -228,19 +743,193 @@ fn run() -> Result<()> {
                 .context(format!("Could not write checklist to {out_file:?}!"))?;
             Ok(())
         }
-        Some(Commands::Inspect { root_dir }) => {
-            list_empty_files(root_dir.clone())?;
-            similar_file_names(root_dir, 4);
+        Some(Commands::Inspect {
+            root_dir,
+            reading,
+            wpm,
+            mode,
+            keep_going,
+            require,
+            syntax,
+            json,
+            field,
+            since,
+        }) => {
+            let report = if let Some((name, value)) = field {
+                let mode = mode.context("--field requires --mode")?;
+                InspectReport::default()
+                    .with_matching_notes(notes_with_field(root_dir, &mode, &name, &value)?)
+            } else if syntax {
+                let mode = mode.unwrap_or(TextMode::LogSeq);
+                InspectReport::default().with_syntax_issues(inspect_syntax(root_dir, &mode)?)
+            } else if !require.is_empty() {
+                InspectReport::default()
+                    .with_missing_frontmatter(missing_frontmatter_fields(root_dir, &require)?)
+            } else if reading {
+                let mode = mode.unwrap_or(TextMode::LogSeq);
+                let (stats, errors) = reading_stats(root_dir, &mode, wpm, keep_going)?;
+                InspectReport::default()
+                    .with_reading_stats(stats)
+                    .with_file_errors(errors)
+            } else {
+                let since = since
+                    .map(|since| {
+                        since
+                            .and_hms_opt(0, 0, 0)
+                            .and_then(|d| d.and_local_timezone(Local).single())
+                            .context(format!("Invalid date {since}"))
+                    })
+                    .transpose()?;
+                inspect_tree(root_dir, keep_going, since)?
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                report.print();
+            }
+            Ok(())
+        }
+        Some(Commands::BigNotes {
+            root_dir,
+            over,
+            under,
+            mode,
+            keep_going,
+            json,
+        }) => {
+            let mode = mode.unwrap_or(TextMode::LogSeq);
+            let (stats, errors) = reading_stats(root_dir, &mode, DEFAULT_WPM, keep_going)?;
+            let stats = filter_by_word_count(stats, over, under);
+            let count = stats.len();
+            let report = InspectReport::default()
+                .with_reading_stats(stats)
+                .with_file_errors(errors);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                report.print();
+                println!("{count} notes");
+            }
+            Ok(())
+        }
+        Some(Commands::CheckTemplates {
+            root_dir,
+            mode,
+            require,
+            json,
+        }) => {
+            let report = InspectReport::default()
+                .with_empty_props(notes_with_empty_required_props(root_dir, &mode, &require)?);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                report.print();
+            }
+            Ok(())
+        }
+        Some(Commands::Doctor {
+            root_dir,
+            mode,
+            require,
+            json,
+            fail_on,
+        }) => {
+            let opts = DoctorOptions::default().with_required_frontmatter(require);
+            let report = doctor(root_dir, &mode, &opts)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                report.print();
+            }
+            if report.has_issues(fail_on) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Commands::Tags {
+            root_dir,
+            mode,
+            max_depth,
+            prefix,
+            json,
+        }) => print_tags(root_dir, &mode, max_depth, prefix.as_deref(), json),
+        Some(Commands::Retag {
+            root_dir,
+            mode,
+            from,
+            to,
+            prefix,
+            max_depth,
+        }) => {
+            let rename = TagRename { from, to, prefix };
+            retag_tree(root_dir, &mode, &rename, max_depth)?
+                .iter()
+                .for_each(|f| println!("retagged {f:?}"));
+            Ok(())
+        }
+        Some(Commands::Tidy {
+            root_dir,
+            mode,
+            max_depth,
+        }) => {
+            tidy_tree(root_dir, &mode, max_depth)?
+                .iter()
+                .for_each(|f| println!("tidied {f:?}"));
+            Ok(())
+        }
+        Some(Commands::Move {
+            root_dir,
+            mode,
+            file,
+            dest_dir,
+        }) => {
+            let new_file = move_note(root_dir, &mode, file, dest_dir)?;
+            println!("Moved note to {new_file:?}");
+            Ok(())
+        }
+        Some(Commands::Tangle {
+            file,
+            mode,
+            out_dir,
+        }) => {
+            tangle(file, &mode, out_dir)?
+                .iter()
+                .for_each(|f| println!("wrote {f:?}"));
             Ok(())
         }
         Some(Commands::Convert {
             in_path,
+            extra_in_paths,
             out_path,
             inmode,
             outmode,
             imdir,
             imout,
+            mirror_deletions,
+            on_collision,
+            dry_run,
+            report,
+            frontmatter_format,
+            include,
+            exclude,
+            max_depth,
+            prop_map,
+            reverse_prop_map,
+            config,
+            timing,
+            progress,
         }) => {
+            if !extra_in_paths.is_empty() {
+                if let Some(p) = std::iter::once(&in_path)
+                    .chain(&extra_in_paths)
+                    .find(|p| !p.is_dir())
+                {
+                    bail!("{p:?} is no directory, only directories can be converted together!");
+                }
+                if mirror_deletions {
+                    bail!("--mirror-deletions can not be used with several input directories!");
+                }
+            }
             let mut imdir = imdir;
             let mut imout = imout;
             if let (Some(im_in), Some(im_out)) = (&imdir, &imout) {
@@ -250,18 +939,123 @@ fn run() -> Result<()> {
                 imdir = Some(im_in.canonicalize()?);
                 imout = Some(im_out.canonicalize()?);
             }
-            let mentioned_files = if in_path.is_dir() {
-                convert_tree(in_path, out_path, inmode, outmode, &imdir, &imout)
+            if config.strict && inmode != TextMode::Zk {
+                eprintln!("Warning: --strict only affects zk input");
+            }
+            if config.preserve_blank_lines && outmode != TextMode::LogSeq {
+                eprintln!("Warning: --preserve-blank-lines only affects logseq output");
+            }
+            let mut base_config = ConvertConfig::from(*config);
+            if let Some(prop_map) = prop_map {
+                base_config = base_config.merge(ConvertConfig::from_property_map_file(
+                    &prop_map,
+                    reverse_prop_map,
+                )?);
+            }
+            let conversion_report = if in_path == Path::new("-") {
+                if imdir.is_some() {
+                    eprintln!("Warning: images are not copied when reading from stdin");
+                }
+                let start = Instant::now();
+                let res = convert_reader(
+                    std::io::stdin().lock(),
+                    out_path.clone(),
+                    inmode,
+                    outmode,
+                    frontmatter_format,
+                    &base_config,
+                )
+                .map_err(Into::into);
+                ConversionReport {
+                    entries: vec![
+                        ConversionEntry::new(in_path, out_path, res).with_duration(start.elapsed()),
+                    ],
+                }
+            } else if in_path.is_dir() {
+                let bar = if progress && std::io::stdout().is_terminal() {
+                    ProgressBar::with_draw_target(None, ProgressDrawTarget::stdout())
+                } else {
+                    ProgressBar::hidden()
+                };
+                let filter = TreeFilter::new(&include, &exclude)?.with_max_depth(max_depth);
+                let options = TreeOptions::new(inmode.clone(), outmode.clone())
+                    .with_image_dirs(imdir.clone(), imout.clone())
+                    .with_frontmatter_format(frontmatter_format)
+                    .with_filter(filter);
+                let mut collisions = Collisions::new(on_collision);
+                let mut res = ConversionReport::default();
+                for root in std::iter::once(&in_path).chain(&extra_in_paths) {
+                    // the bar continues after the files of the previous trees
+                    let offset = res.entries.len() as u64;
+                    let tree_report = convert_tree(
+                        root.clone(),
+                        out_path.clone(),
+                        &options,
+                        &base_config,
+                        &mut collisions,
+                        &mut |done, total| {
+                            bar.set_length(offset + total as u64);
+                            bar.set_position(offset + done as u64);
+                        },
+                    )?;
+                    res.entries.extend(tree_report.entries);
+                }
+                bar.finish_and_clear();
+                if res.failures().is_empty() && mirror_deletions {
+                    let root_config =
+                        base_config.merge(ConvertConfig::resolve(&in_path, &in_path)?);
+                    let extension = root_config.output_extension();
+                    let removed = document_component::mirror_deletions(
+                        in_path,
+                        out_path,
+                        extension.as_deref().unwrap_or("md"),
+                        dry_run,
+                    )?;
+                    let prefix = if dry_run { "would remove" } else { "removed" };
+                    removed.iter().for_each(|f| println!("{prefix} {f:?}"));
+                }
+                res
             } else {
-                let file_info =
-                    FileInfo::try_new(in_path, Some(out_path), imdir.clone(), imout.clone())?;
-                convert_file(file_info, inmode, outmode)
-            }?;
+                let file_info = FileInfo::try_new(
+                    in_path.clone(),
+                    Some(out_path.clone()),
+                    imdir.clone(),
+                    imout.clone(),
+                )?
+                .with_frontmatter_format(frontmatter_format);
+                let in_dir = in_path
+                    .canonicalize()?
+                    .parent()
+                    .context(format!("{in_path:?} has no parent!"))?
+                    .to_path_buf();
+                let config = base_config.merge(ConvertConfig::resolve(&in_dir, &in_dir)?);
+                let start = Instant::now();
+                let res = convert_file(file_info, inmode, outmode, &config).map_err(Into::into);
+                ConversionReport {
+                    entries: vec![
+                        ConversionEntry::new(in_path, out_path, res).with_duration(start.elapsed()),
+                    ],
+                }
+            };
+            if let Some(report) = report {
+                conversion_report.write(&report)?;
+            }
+            if let Some(n) = timing {
+                conversion_report.print_timing(n);
+            }
+            if let Some(failure) = conversion_report.failures().first() {
+                bail!(
+                    "Failed to convert {:?}: {}",
+                    failure.source,
+                    failure.error.clone().unwrap_or_default()
+                );
+            }
+            let mentioned_files = conversion_report.mentioned_files();
 
             let mentioned_files: HashSet<String> = HashSet::from_iter(mentioned_files);
 
             if let (Some(imdir), Some(imout)) = (imdir, imout) {
-                let found_image_files = files_in_tree(&imdir, &Some(vec!["png"]))?;
+                let found_image_files = files_in_tree(&imdir, &Some(vec!["png"]), None)?;
                 let matched_files: Vec<PathBuf> = found_image_files
                     .into_iter()
                     .filter(|f| {
@@ -298,6 +1092,30 @@ fn run() -> Result<()> {
             }
             Ok(())
         }
+        Some(Commands::New {
+            root_dir,
+            template,
+            template_dir,
+            title,
+            vars,
+        }) => {
+            let mut vars = vars;
+            vars.insert(0, ("title".to_string(), title));
+            let settings = todoi::config::Settings::parse()?;
+            let template_dir = match template_dir {
+                Some(dir) => root_dir.join(dir),
+                None => settings.zk_template_dir(&root_dir),
+            };
+            let file = instantiate_template(
+                &root_dir,
+                &template_dir,
+                &template,
+                &vars,
+                &settings.zk_new.slug,
+            )?;
+            println!("Created {file:?}");
+            Ok(())
+        }
         Some(Commands::Creator {
             root_dir,
             name,
@@ -315,7 +1133,9 @@ fn run() -> Result<()> {
                             set_zk_creator_file(&name, &new_file)?;
                         }
                         CreatorCommand::ShowFile { relative } => {
-                            let mut file = get_zk_creator_file(&root_dir, &name)?;
+                            let settings = todoi::config::Settings::parse()?;
+                            let mut file = ZkHandler::from_settings(&root_dir, &settings)
+                                .creator_file(&name)?;
                             if let Some(relative) = relative
                                 && let Some(rel) = relative.parent()
                                 && let Some(rel) = pathdiff::diff_paths(&file, rel)
@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use tracing::debug;
+
+use crate::{
+    document_component::{DocumentComponent, PropValue},
+    parsing::{TextMode, parse_file},
+    util::{files_in_tree, write_atomic},
+};
+
+/// Moves file into dest_dir and updates relative `[alias](path)` links in the moved file and in all
+/// files in the tree rooted at root_dir that point to it. Wiki links reference notes by name and
+/// stay valid without changes. Files are written atomically, the original note is removed last.
+/// Returns the new path of the note.
+pub fn move_note<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    file: T,
+    dest_dir: T,
+) -> Result<PathBuf> {
+    let file = file
+        .as_ref()
+        .canonicalize()
+        .context(format!("{:?} does not exist!", file.as_ref()))?;
+    let old_dir = file
+        .parent()
+        .context(format!("{file:?} has no parent!"))?
+        .to_path_buf();
+    let dest_dir = dest_dir.as_ref();
+    if !dest_dir.exists() {
+        std::fs::create_dir_all(dest_dir).context(format!("Could not create {dest_dir:?}"))?;
+    }
+    let dest_dir = dest_dir.canonicalize()?;
+    let new_file = dest_dir.join(file.file_name().context(format!("{file:?} has no name!"))?);
+    if new_file.exists() {
+        bail!("{new_file:?} already exists!");
+    }
+
+    // links in the moved note are now relative to the destination directory
+    let mut text = std::fs::read_to_string(&file).context(format!("Could not read {file:?}"))?;
+    for target in link_targets(&file, mode)? {
+        if let Ok(resolved) = old_dir.join(&target).canonicalize() {
+            let new_target = if resolved == file {
+                new_file.clone()
+            } else {
+                resolved
+            };
+            if let Some(rel) = pathdiff::diff_paths(&new_target, &dest_dir) {
+                text = replace_link_target(&text, &target, &rel);
+            }
+        }
+    }
+
+    write_atomic(&new_file, &text)?;
+
+    // links pointing to the moved note
+    let other_files = files_in_tree(&root_dir, &Some(vec!["md"]), None)?
+        .into_iter()
+        .map(|f| f.canonicalize().context(format!("Could not resolve {f:?}")))
+        .collect::<Result<Vec<PathBuf>>>()?;
+    other_files
+        .iter()
+        .filter(|f| **f != file && **f != new_file)
+        .try_for_each(|f| {
+            let f_dir = f.parent().context(format!("{f:?} has no parent!"))?;
+            let mut f_text = std::fs::read_to_string(f).context(format!("Could not read {f:?}"))?;
+            let mut changed = false;
+            for target in link_targets(f, mode)? {
+                if f_dir.join(&target).canonicalize().ok().as_ref() == Some(&file)
+                    && let Some(rel) = pathdiff::diff_paths(&new_file, f_dir)
+                {
+                    debug!("updating link {target:?} in {f:?} to {rel:?}");
+                    f_text = replace_link_target(&f_text, &target, &rel);
+                    changed = true;
+                }
+            }
+            if changed {
+                write_atomic(f, &f_text)?;
+            }
+            Ok::<(), anyhow::Error>(())
+        })?;
+
+    std::fs::remove_file(&file).context(format!("Could not remove {file:?}"))?;
+    Ok(new_file)
+}
+
+/// returns the targets of all file links in the given file as they appear in the text
+fn link_targets(file: &Path, mode: &TextMode) -> Result<Vec<String>> {
+    let pd = parse_file(&file.to_path_buf(), mode)?;
+    let mut res = vec![];
+    pd.get_all_document_components(&|dc| {
+        matches!(
+            dc,
+            DocumentComponent::FileLink(_, _, _)
+                | DocumentComponent::Properties(..)
+                | DocumentComponent::Frontmatter(_)
+        )
+    })
+    .iter()
+    .for_each(|dc| match dc {
+        DocumentComponent::FileLink(mf, _, _) => res.push(mf.to_string()),
+        DocumentComponent::Properties(props, _) | DocumentComponent::Frontmatter(props) => {
+            props.iter().for_each(|p| {
+                p.values.iter().for_each(|v| {
+                    if let PropValue::FileLink(mf, _, _) = v {
+                        res.push(mf.to_string());
+                    }
+                })
+            });
+        }
+        _ => {}
+    });
+    res.sort();
+    res.dedup();
+    Ok(res)
+}
+
+fn replace_link_target(text: &str, target: &str, new_target: &Path) -> String {
+    let new_target = new_target.to_string_lossy().replace("\\", "/");
+    text.replace(&format!("]({target})"), &format!("]({new_target})"))
+}
+
+#[test]
+fn test_move_note_one_level_deeper() {
+    let root_tmp = tempfile::tempdir().unwrap();
+    let root = root_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("a.md"), "see [b](b.md)\n").unwrap();
+    std::fs::write(root.join("b.md"), "back to [a](a.md)\n").unwrap();
+
+    let new_file = move_note(&root, &TextMode::Zk, &root.join("b.md"), &root.join("sub")).unwrap();
+
+    let root = root.canonicalize().unwrap();
+    assert_eq!(new_file, root.join("sub").join("b.md"));
+    assert!(!root.join("b.md").exists());
+    assert_eq!(
+        std::fs::read_to_string(root.join("a.md")).unwrap(),
+        "see [b](sub/b.md)\n"
+    );
+    let moved_text = std::fs::read_to_string(&new_file).unwrap();
+    assert!(!root.join("sub").join("b.md.pkmt-tmp").exists());
+    assert_eq!(moved_text, "back to [a](../a.md)\n");
+    // the backlink still resolves to a.md
+    let backlink = root.join("sub").join("../a.md").canonicalize().unwrap();
+    assert_eq!(backlink, root.join("a.md"));
+}
@@ -10,27 +10,59 @@ use test_log::test;
 use crate::{
     document_component::{
         DocumentComponent, ListElem, MentionedFile, ParsedDocument, PropValue, Property,
-        collapse_text,
+        PropertyScope, collapse_text,
     },
-    parsing::md_parsing::{ListElement, MdComponent, parse_md_text},
+    parsing::{
+        InputEncoding,
+        md_parsing::{ListElement, MdComponent, parse_md_text_with},
+        recover_block,
+    },
+    util::{apply_substitution_table, default_substitutions, indent_spaces},
 };
 
 pub fn parse_logseq_file<T: AsRef<Path>>(file_path: T) -> Result<ParsedDocument> {
+    parse_logseq_file_with(
+        file_path,
+        false,
+        &InputEncoding::Utf8,
+        &default_substitutions(),
+    )
+}
+
+/// in best effort mode, blocks that fail to parse are kept as text. The substitutions are applied
+/// to the text before it is parsed.
+pub fn parse_logseq_file_with<T: AsRef<Path>>(
+    file_path: T,
+    best_effort: bool,
+    encoding: &InputEncoding,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument> {
     let file_path = file_path.as_ref().canonicalize()?;
-    let text = std::fs::read_to_string(&file_path)?;
-    let text = crate::util::apply_substitutions(&text);
+    let text = encoding.read(&file_path)?;
 
     let file_dir = file_path
         .parent()
         .context(format!("{file_path:?} has no parent!"))?
         .to_path_buf();
 
-    let pt = parse_logseq_text(&text, &Some(file_dir))?;
+    let pt = parse_logseq_text_with(&text, &Some(file_dir), best_effort, substitutions)?;
     Ok(ParsedDocument::ParsedFile(pt.into_components(), file_path))
 }
 
 pub fn parse_logseq_text(text: &str, file_dir: &Option<PathBuf>) -> Result<ParsedDocument> {
-    let parsed_md = parse_md_text(text).context("Failed to parse md")?;
+    parse_logseq_text_with(text, file_dir, false, &default_substitutions())
+}
+
+/// in best effort mode, blocks that fail to parse are kept as text. The substitutions are applied
+/// to the text before it is parsed.
+pub fn parse_logseq_text_with(
+    text: &str,
+    file_dir: &Option<PathBuf>,
+    best_effort: bool,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument> {
+    let text = apply_substitution_table(text, substitutions);
+    let parsed_md = parse_md_text_with(&text, &[]).context("Failed to parse md")?;
     println!("{parsed_md:?}");
     let mut components = vec![];
     parsed_md.into_iter().try_for_each(|comp| match comp {
@@ -38,8 +70,16 @@ pub fn parse_logseq_text(text: &str, file_dir: &Option<PathBuf>) -> Result<Parse
             components.push(DocumentComponent::Heading(level as u16, text));
             Ok::<(), anyhow::Error>(())
         }
+        MdComponent::ThematicBreak(marker) => {
+            components.push(DocumentComponent::ThematicBreak(marker));
+            Ok(())
+        }
+        MdComponent::DefinitionList(entries) => {
+            components.push(DocumentComponent::DefinitionList(entries));
+            Ok(())
+        }
         MdComponent::Text(text) => {
-            let tmp = parse_logseq_block(&text, file_dir)?;
+            let tmp = recover_block(parse_logseq_block(&text, file_dir), &text, best_effort)?;
             let mut comps = tmp.into_components();
             components.append(&mut comps);
             Ok(())
@@ -47,7 +87,7 @@ pub fn parse_logseq_text(text: &str, file_dir: &Option<PathBuf>) -> Result<Parse
         MdComponent::List(list_elements, terminated_by_blank_line) => {
             let list_elements: Result<Vec<ListElem>> = list_elements
                 .iter()
-                .map(|le| parse_md_list_element(le, file_dir))
+                .map(|le| parse_md_list_element(le, file_dir, best_effort))
                 .collect();
             components.push(DocumentComponent::List(
                 list_elements?,
@@ -57,19 +97,31 @@ pub fn parse_logseq_text(text: &str, file_dir: &Option<PathBuf>) -> Result<Parse
         }
     })?;
 
-    let components = collapse_text(&components);
+    let mut components = collapse_text(&components);
+    // properties before the first block are page properties
+    if let Some(DocumentComponent::Properties(_, scope)) = components
+        .iter_mut()
+        .find(|c| !matches!(c, DocumentComponent::Text(t) if t.trim().is_empty()))
+    {
+        *scope = PropertyScope::Page;
+    }
     Ok(ParsedDocument::ParsedText(components))
 }
 
 fn parse_md_list_element(
     list_element: &ListElement,
     file_dir: &Option<PathBuf>,
+    best_effort: bool,
 ) -> Result<ListElem> {
-    let contents = parse_logseq_block(&list_element.text, file_dir)?;
+    let contents = recover_block(
+        parse_logseq_block(&list_element.text, file_dir),
+        &list_element.text,
+        best_effort,
+    )?;
     let children: Result<Vec<ListElem>> = list_element
         .children
         .iter()
-        .map(|c| parse_md_list_element(c, file_dir))
+        .map(|c| parse_md_list_element(c, file_dir, best_effort))
         .collect();
     let mut res = ListElem::new(contents);
     res.children = children?;
@@ -122,9 +174,17 @@ fn parse_logseq_block(text: &str, _file_dir: &Option<PathBuf>) -> Result<ParsedD
     use LogSeqBlockToken::*;
     let text = text.trim();
     let mut properties = vec![];
+    // position of the first property so that text before the properties stays in front
+    let mut properties_index = None;
     let mut lexer = LogSeqBlockToken::lexer(text);
     let mut new_line_or_whitespace = true;
     let mut components = vec![];
+    // drawers like `:LOGBOOK:` are closed by `:END:` on its own line
+    let drawer_re = regex::Regex::new(
+        r"^:([A-Za-z][A-Za-z0-9_\-]*):[ \t]*(?:\n((?s:.*?)))?\n[ \t]*:END:[ \t]*",
+    )?;
+    let macro_re = regex::Regex::new(r"^\{\{(video|embed)[ \t]+(.+?)[ \t]*\}\}")?;
+    let embed_target_re = regex::Regex::new(r"^\[\[([^\[\]]+)\]\]$")?;
 
     while let Some(result) = lexer.next() {
         if let Ok(token) = result {
@@ -145,8 +205,17 @@ fn parse_logseq_block(text: &str, _file_dir: &Option<PathBuf>) -> Result<ParsedD
                     new_line_or_whitespace = true;
                     components.push(DocumentComponent::Text(lexer.slice().to_string()));
                 }
+                PropertyStart
+                    if !starts_line(text, lexer.span().start)
+                        || lexer.remainder().starts_with(|c: char| !c.is_whitespace()) =>
+                {
+                    // `::` inside of text or a property value does not start a property
+                    new_line_or_whitespace = false;
+                    components.push(DocumentComponent::Text(lexer.slice().to_string()));
+                }
                 PropertyStart => {
                     new_line_or_whitespace = false;
+                    properties_index.get_or_insert(components.len());
                     let prop_name = lexer.slice().replace("::", "").trim().to_string();
                     let prop_val = parse_property_value(&mut lexer)?;
                     properties.push((prop_name, prop_val));
@@ -167,6 +236,15 @@ fn parse_logseq_block(text: &str, _file_dir: &Option<PathBuf>) -> Result<ParsedD
                 }
                 TripleBackQuote => {
                     new_line_or_whitespace = false;
+                    // code lines are stored relative to the indentation of the opening fence
+                    let fence_start = lexer.span().start;
+                    let line_start = text[..fence_start].rfind('\n').map_or(0, |i| i + 1);
+                    let fence_indent = &text[line_start..fence_start];
+                    let fence_indent = if fence_indent.trim().is_empty() {
+                        fence_indent.len()
+                    } else {
+                        0
+                    };
                     let inner = text_until_token(TripleBackQuote, &mut lexer, true)?.0;
 
                     let (code_type, remaining) =
@@ -175,11 +253,71 @@ fn parse_logseq_block(text: &str, _file_dir: &Option<PathBuf>) -> Result<ParsedD
                         } else {
                             (None, inner.as_str())
                         };
+                    let code = remaining
+                        .lines()
+                        .map(|l| {
+                            let spaces = indent_spaces(l).min(fence_indent);
+                            &l[spaces..]
+                        })
+                        .collect::<Vec<&str>>()
+                        .join("\n");
                     components.push(DocumentComponent::CodeBlock(
-                        remaining.trim().to_string(),
+                        code.trim_start_matches('\n').trim_end().to_string(),
                         code_type,
                     ));
                 }
+                MiscText
+                    if lexer.slice().starts_with(':') && starts_line(text, lexer.span().start) =>
+                {
+                    new_line_or_whitespace = false;
+                    let start = lexer.span().start;
+                    let drawer = drawer_re.captures(&text[start..]).filter(|c| {
+                        &c[1] != "END"
+                            && text[start + c[0].len()..]
+                                .chars()
+                                .next()
+                                .is_none_or(|c| c == '\n')
+                    });
+                    if let Some(c) = drawer {
+                        // drawer lines are stored relative to the indentation of the opening line
+                        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+                        let drawer_indent = start - line_start;
+                        let contents = c
+                            .get(2)
+                            .map_or("", |m| m.as_str())
+                            .lines()
+                            .map(|l| &l[indent_spaces(l).min(drawer_indent)..])
+                            .collect::<Vec<&str>>()
+                            .join("\n");
+                        components.push(DocumentComponent::Drawer(c[1].to_string(), contents));
+                        lexer.bump(c[0].len() - lexer.slice().len());
+                    } else {
+                        components.push(DocumentComponent::Text(lexer.slice().to_string()));
+                    }
+                }
+                MiscText if lexer.slice().starts_with("{{") => {
+                    new_line_or_whitespace = false;
+                    let start = lexer.span().start;
+                    let comp = macro_re.captures(&text[start..]).and_then(|c| {
+                        let comp = match &c[1] {
+                            "video" => DocumentComponent::Media(c[2].to_string()),
+                            // block embeds like `{{embed ((uuid))}}` are kept as text
+                            _ => DocumentComponent::FileEmbed(
+                                MentionedFile::FileName(
+                                    embed_target_re.captures(&c[2])?[1].to_string(),
+                                ),
+                                None,
+                            ),
+                        };
+                        Some((comp, c[0].len()))
+                    });
+                    if let Some((comp, len)) = comp {
+                        components.push(comp);
+                        lexer.bump(len - lexer.slice().len());
+                    } else {
+                        components.push(DocumentComponent::Text(lexer.slice().to_string()));
+                    }
+                }
                 QuoteEnvStart => {
                     new_line_or_whitespace = false;
                     let inner = text_until_token(QuoteEnvEnd, &mut lexer, true)?.0;
@@ -212,13 +350,11 @@ fn parse_logseq_block(text: &str, _file_dir: &Option<PathBuf>) -> Result<ParsedD
     if !properties.is_empty() {
         let props = properties
             .iter()
-            .map(|(k, v)| {
-                Property::new(k.to_string(), true, vec![PropValue::String(v.to_string())])
-            })
+            .map(|(k, v)| Property::new(k.to_string(), true, vec![PropValue::parse_typed(v)]))
             .collect();
 
-        let props = DocumentComponent::Properties(props);
-        components.insert(0, props);
+        let props = DocumentComponent::Properties(props, PropertyScope::Block);
+        components.insert(properties_index.unwrap_or(0), props);
     }
     let pd = ParsedDocument::ParsedText(components);
     Ok(pd)
@@ -257,6 +393,12 @@ fn parse_heading(lexer: &mut Lexer<'_, LogSeqBlockToken>) -> (DocumentComponent,
     )
 }
 
+/// whether only whitespace precedes pos on its line
+fn starts_line(text: &str, pos: usize) -> bool {
+    let line_start = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+    text[line_start..pos].trim().is_empty()
+}
+
 /// returns (<text until token>, <text of token>)
 fn text_until_token(
     token: LogSeqBlockToken,
@@ -406,3 +548,144 @@ fn test_umlaut() {
     let expected = "- üÜäÄöÖß";
     assert_eq!(res, expected);
 }
+
+#[test]
+fn test_property_order_with_empty_values() {
+    let text = "- # Article\n\t- template:: article\n\t  source::\n\t  url::\n\t  tags:: [[article]]\n\t\t- notes";
+    let res = parse_logseq_text(text, &None).unwrap();
+    let res = res.to_logseq_text(&None);
+    assert_eq!(res, text.replace("\t", "    "));
+
+    let text = "- template:: article\n  source::\n  status:: #Inbox\n  url::";
+    let res = parse_logseq_text(text, &None).unwrap();
+    let res = res.to_logseq_text(&None);
+    assert_eq!(res, text);
+
+    // properties after the first line of a block stay in place
+    let text = "- some text\n  source::\n  url:: x";
+    let res = parse_logseq_text(text, &None).unwrap();
+    let res = res.to_logseq_text(&None);
+    assert_eq!(res, text);
+}
+
+#[test]
+fn test_delimiters_in_property_values() {
+    let text = "- a::b in text\n  code:: a::b\n  escaped:: x\\:: y";
+    let pd = parse_logseq_text(text, &None).unwrap();
+    let props = pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Properties(..)));
+    let DocumentComponent::Properties(props, _) = &props[0] else {
+        panic!("no properties: {pd:?}");
+    };
+    assert_eq!(
+        props,
+        &vec![
+            Property::new(
+                "code".to_string(),
+                true,
+                vec![PropValue::String("a::b".to_string())]
+            ),
+            Property::new(
+                "escaped".to_string(),
+                true,
+                vec![PropValue::String("x\\:: y".to_string())]
+            ),
+        ]
+    );
+    assert_eq!(pd.to_logseq_text(&None), text);
+
+    // escaped delimiters do not start a property
+    let text = "- key\\:: not a property";
+    let pd = parse_logseq_text(text, &None).unwrap();
+    assert!(
+        pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Properties(..)))
+            .is_empty()
+    );
+    assert_eq!(pd.to_logseq_text(&None), text);
+}
+
+#[test]
+fn test_logbook_drawer() {
+    let text = "- DONE task\n    - nested\n      :LOGBOOK:\n      CLOCK: [2024-01-02 Tue 10:00:00]--[2024-01-02 Tue 10:30:00] =>  00:30:00\n      :END:\n- next\n  :NOTES:\n  see [[other]] #tag\n  :END:";
+    let pd = parse_logseq_text(text, &None).unwrap();
+    let drawers = pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Drawer(..)));
+    assert_eq!(
+        drawers,
+        vec![
+            DocumentComponent::Drawer(
+                "LOGBOOK".to_string(),
+                "CLOCK: [2024-01-02 Tue 10:00:00]--[2024-01-02 Tue 10:30:00] =>  00:30:00"
+                    .to_string()
+            ),
+            DocumentComponent::Drawer("NOTES".to_string(), "see [[other]] #tag".to_string()),
+        ]
+    );
+    assert_eq!(pd.to_logseq_text(&None), text);
+
+    // `:END:` alone and unterminated drawers stay text
+    let pd = parse_logseq_text("- a\n  :END:\n- b\n  :LOGBOOK:\n  text", &None).unwrap();
+    assert!(
+        pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Drawer(..)))
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_best_effort() {
+    use DocumentComponent::*;
+    let text = "- first block\n- broken [[link\n- last [[block]]";
+    assert!(parse_logseq_text(text, &None).is_err());
+
+    let pd = parse_logseq_text_with(text, &None, true, &[]).unwrap();
+    let [List(elems, _)] = &pd.components()[..] else {
+        panic!("expected a single list, got {pd:?}");
+    };
+    assert_eq!(elems.len(), 3);
+    assert_eq!(
+        elems[1].contents.components(),
+        &[Text("broken [[link".to_string())]
+    );
+    assert!(
+        elems[2]
+            .contents
+            .components()
+            .iter()
+            .any(|c| matches!(c, FileLink(..)))
+    );
+}
+
+#[test]
+fn test_video_and_embed_macros() {
+    use DocumentComponent::*;
+    let text = "- watch {{video https://www.youtube.com/watch?v=abc}}\n- {{embed [[other note]]}}\n- {{embed ((64a1b2c3))}}";
+    let pd = parse_logseq_text(text, &None).unwrap();
+    let [List(elems, _)] = &pd.components()[..] else {
+        panic!("expected a single list, got {pd:?}");
+    };
+    assert_eq!(
+        elems[0].contents.components(),
+        &[
+            Text("watch ".to_string()),
+            Media("https://www.youtube.com/watch?v=abc".to_string())
+        ]
+    );
+    assert_eq!(
+        elems[1].contents.components(),
+        &[FileEmbed(
+            MentionedFile::FileName("other note".to_string()),
+            None
+        )]
+    );
+    assert!(
+        elems[2]
+            .contents
+            .components()
+            .iter()
+            .all(|c| matches!(c, Text(_)))
+    );
+
+    assert_eq!(pd.to_logseq_text(&None), text);
+    assert_eq!(
+        pd.to_zk_text(&None),
+        "- watch https://www.youtube.com/watch?v=abc\n- [other note](<other note.md>)\n- {{embed ((64a1b2c3))}}"
+    );
+}
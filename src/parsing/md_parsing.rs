@@ -1,4 +1,6 @@
-use crate::util::{SPACES_PER_INDENT, apply_substitutions};
+use std::ops::Range;
+
+use crate::util::{IndentUnit, SPACES_PER_INDENT, default_substitutions, replace_mapped};
 use anyhow::{Result, bail};
 use logos::{Lexer, Logos};
 use test_log::test;
@@ -26,12 +28,19 @@ impl ListElement {
     }
 }
 
+/// terms with their definitions
+pub type DefinitionEntries = Vec<(String, Vec<String>)>;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum MdComponent {
     Heading(usize, String),
     /// list elements, terminated by blank line
     List(Vec<ListElement>, bool),
     Text(String),
+    /// `---`, `***` or `___` on a line of its own, holds the marker as written
+    ThematicBreak(String),
+    /// terms with their definitions (`Term\n: definition`)
+    DefinitionList(DefinitionEntries),
 }
 impl MdComponent {
     fn new_text(text: &str) -> Self {
@@ -39,22 +48,31 @@ impl MdComponent {
     }
 }
 
-fn collapse_text(components: Vec<MdComponent>) -> Vec<MdComponent> {
+/// merges adjacent text components, their spans are joined
+fn collapse_text(components: Vec<(MdComponent, Range<usize>)>) -> Vec<(MdComponent, Range<usize>)> {
     let mut res = vec![];
-    let mut current_text = String::new();
-    components.into_iter().for_each(|c| {
+    let mut current_text: Option<(String, Range<usize>)> = None;
+    components.into_iter().for_each(|(c, span)| {
         if let MdComponent::Text(text) = c {
-            current_text.push_str(&text);
+            if let Some((current, current_span)) = &mut current_text {
+                current.push_str(&text);
+                current_span.end = span.end;
+            } else {
+                current_text = Some((text, span));
+            }
         } else {
-            if !current_text.is_empty() {
-                res.push(MdComponent::Text(current_text.clone()));
-                current_text = String::new();
+            if let Some((text, text_span)) = current_text.take()
+                && !text.is_empty()
+            {
+                res.push((MdComponent::Text(text), text_span));
             }
-            res.push(c);
+            res.push((c, span));
         }
     });
-    if !current_text.is_empty() {
-        res.push(MdComponent::Text(current_text.clone()));
+    if let Some((text, text_span)) = current_text
+        && !text.is_empty()
+    {
+        res.push((MdComponent::Text(text), text_span));
     }
     res
 }
@@ -88,63 +106,206 @@ impl MdToken {
     }
 }
 
+/// the built-in substitutions are applied to the text before it is parsed
 #[instrument]
 pub fn parse_md_text(text: &str) -> Result<Vec<MdComponent>> {
-    use MdToken::*;
-    let text = apply_substitutions(text);
-    let text = text.replace("\t", &" ".repeat(SPACES_PER_INDENT));
+    parse_md_text_with(text, &default_substitutions())
+}
+
+/// like parse_md_text, but applies the given substitutions instead of the built-in ones. Callers
+/// that already applied them pass none.
+pub fn parse_md_text_with(
+    text: &str,
+    substitutions: &[(String, String)],
+) -> Result<Vec<MdComponent>> {
+    Ok(parse_md_text_spanned_with(text, substitutions)?
+        .into_iter()
+        .map(|(c, _)| c)
+        .collect())
+}
+
+/// like parse_md_text, but additionally returns the byte range of text each component was parsed
+/// from. The range of a list covers all of its elements.
+pub fn parse_md_text_spanned(text: &str) -> Result<Vec<(MdComponent, Range<usize>)>> {
+    parse_md_text_spanned_with(text, &default_substitutions())
+}
+
+/// like parse_md_text_spanned with the given substitutions, the ranges refer to text before they
+/// are applied
+pub fn parse_md_text_spanned_with(
+    text: &str,
+    substitutions: &[(String, String)],
+) -> Result<Vec<(MdComponent, Range<usize>)>> {
+    let offsets: Vec<usize> = (0..=text.len()).collect();
+    let (text, offsets) = substitutions
+        .iter()
+        .filter(|(from, _)| !from.is_empty())
+        .fold((text.to_string(), offsets), |(acc, offsets), (from, to)| {
+            replace_mapped(&acc, &offsets, from, to)
+        });
+    let (text, offsets) = normalize_indent_mapped(&text, &offsets, IndentUnit::detect(&text));
+    let (text, offsets) = replace_mapped(&text, &offsets, "\t", &" ".repeat(SPACES_PER_INDENT));
+    Ok(parse_substituted_md_text(&text)?
+        .into_iter()
+        .map(|(c, span)| (c, offsets[span.start]..offsets[span.end]))
+        .collect())
+}
+
+/// rewrites the leading spaces of list items indented by unit to SPACES_PER_INDENT spaces per
+/// level. The lines of an item keep their indentation relative to it. Tabs are left to the caller.
+/// offsets are updated like in replace_mapped.
+fn normalize_indent_mapped(
+    text: &str,
+    offsets: &[usize],
+    unit: IndentUnit,
+) -> (String, Vec<usize>) {
+    let IndentUnit::Spaces(width) = unit else {
+        return (text.to_string(), offsets.to_vec());
+    };
+    if width == SPACES_PER_INDENT {
+        return (text.to_string(), offsets.to_vec());
+    }
+    let mut res = String::new();
+    let mut res_offsets = vec![];
+    // (source indent, normalized indent) of the enclosing list items
+    let mut items: Vec<(usize, usize)> = vec![];
+    let mut pos = 0;
+    for line in text.split_inclusive('\n') {
+        let rest = line.trim_start_matches(' ');
+        let indent = line.len() - rest.len();
+        let normalized = if rest.starts_with("- ") || rest.trim_end() == "-" {
+            while items.last().is_some_and(|(i, _)| *i >= indent) {
+                items.pop();
+            }
+            let normalized = (indent / width) * SPACES_PER_INDENT + indent % width;
+            items.push((indent, normalized));
+            normalized
+        } else if let Some((item_indent, item_normalized)) =
+            items.iter().rev().find(|(i, _)| *i <= indent)
+        {
+            item_normalized + indent - item_indent
+        } else {
+            indent
+        };
+        res.push_str(&" ".repeat(normalized));
+        res_offsets.extend(std::iter::repeat_n(offsets[pos], normalized));
+        res.push_str(rest);
+        res_offsets.extend_from_slice(&offsets[pos + indent..pos + line.len()]);
+        pos += line.len();
+    }
+    res_offsets.push(offsets[pos]);
+    (res, res_offsets)
+}
 
-    let mut lexer = MdToken::lexer(&text);
+/// returns the components with their ranges in text
+fn parse_substituted_md_text(text: &str) -> Result<Vec<(MdComponent, Range<usize>)>> {
+    use MdToken::*;
+    let mut lexer = MdToken::lexer(text);
     let mut res = vec![];
     let mut blank_line = true;
     let mut indent_spaces = 0;
     let mut last_terminated_line;
+    // index into res where the current line starts
+    let mut line_start = 0;
+    // a leading `---` opens frontmatter, whose closing `---` must not be read as a setext underline
+    let mut seen_content = false;
+    let mut in_frontmatter = false;
 
     while let Some(result) = lexer.next() {
         debug!("{result:?}: '{:?}'", lexer.slice());
         last_terminated_line = false;
         match result {
             Ok(token) => {
+                let start = lexer.span().start;
                 match token {
                     Space => {
-                        res.push(MdComponent::new_text(lexer.slice()));
+                        res.push((MdComponent::new_text(lexer.slice()), lexer.span()));
                     }
                     Newline => {
-                        res.push(MdComponent::new_text(lexer.slice()));
-                        blank_line = true;
-                    }
-                    ListStart => {
-                        if blank_line {
-                            let le = parse_list(&mut lexer, indent_spaces)?;
-                            res.push(le);
-                            // list is always terminated by a blank line
+                        if !in_frontmatter
+                            && let Some(level) = setext_underline_level(lexer.remainder())
+                            && let Some(title) = line_text(&res[line_start..])
+                        {
+                            let line_start_offset = res[line_start].1.start;
+                            res.truncate(line_start);
+                            consume_line(&mut lexer)?;
+                            res.push((
+                                MdComponent::Heading(level, title),
+                                line_start_offset..lexer.span().end,
+                            ));
                             last_terminated_line = true;
+                        } else if !in_frontmatter
+                            && let Some(term) = line_text(&res[line_start..])
+                            && let Some((entries, consumed)) =
+                                parse_definitions(term, lexer.remainder())
+                        {
+                            let line_start_offset = res[line_start].1.start;
+                            res.truncate(line_start);
+                            // the newline ending the last definition is kept as text
+                            lexer.bump(consumed);
+                            res.push((
+                                MdComponent::DefinitionList(entries),
+                                line_start_offset..lexer.span().end,
+                            ));
                         } else {
-                            res.push(MdComponent::new_text(lexer.slice()));
+                            res.push((MdComponent::new_text(lexer.slice()), lexer.span()));
                         }
+                        blank_line = true;
                     }
-
-                    Hashtag => {
-                        if blank_line {
-                            let (heading, found) = parse_heading(&mut lexer)?;
-                            res.push(heading);
-                            if found {
-                                blank_line = true;
-                                last_terminated_line = true;
-                            }
-                        } else {
-                            res.push(MdComponent::new_text(lexer.slice()));
+                    ListStart if blank_line && !in_frontmatter => {
+                        let le = parse_list(&mut lexer, indent_spaces)?;
+                        res.push((le, start..lexer.span().end));
+                        // list is always terminated by a blank line
+                        last_terminated_line = true;
+                    }
+                    Hashtag if blank_line => {
+                        let (heading, found) = parse_heading(&mut lexer)?;
+                        res.push((heading, start..lexer.span().end));
+                        if found {
+                            blank_line = true;
+                            last_terminated_line = true;
                         }
                     }
+                    Text if blank_line && lexer.slice() == "---" && !seen_content => {
+                        in_frontmatter = true;
+                        res.push((MdComponent::new_text(lexer.slice()), lexer.span()));
+                    }
+                    Text if blank_line && lexer.slice() == "---" && in_frontmatter => {
+                        in_frontmatter = false;
+                        res.push((MdComponent::new_text(lexer.slice()), lexer.span()));
+                    }
+                    Text if blank_line
+                        && is_thematic_break(lexer.slice())
+                        && line_text(&res[line_start..]).is_none()
+                        && lexer
+                            .remainder()
+                            .lines()
+                            .next()
+                            .unwrap_or("")
+                            .trim()
+                            .is_empty() =>
+                    {
+                        res.push((
+                            MdComponent::ThematicBreak(lexer.slice().to_string()),
+                            lexer.span(),
+                        ));
+                    }
                     _ => {
-                        res.push(MdComponent::new_text(lexer.slice()));
+                        res.push((MdComponent::new_text(lexer.slice()), lexer.span()));
                     }
                 }
 
+                if !token.is_blank() {
+                    seen_content = true;
+                }
+                if matches!(token, Newline) || last_terminated_line {
+                    line_start = res.len();
+                }
+
                 if !token.is_blank() && !last_terminated_line {
                     blank_line = false;
                 } else if blank_line {
-                    indent_spaces += lexer.slice().replace("\t", "    ").len();
+                    indent_spaces += lexer.slice().len();
                 }
             }
             Err(_) => {
@@ -156,6 +317,93 @@ pub fn parse_md_text(text: &str) -> Result<Vec<MdComponent>> {
     Ok(collapse_text(res))
 }
 
+/// returns the heading level if the first line of text is a setext underline (`===` or `---`).
+/// A single `-` is not accepted as it denotes an empty list element
+fn setext_underline_level(text: &str) -> Option<usize> {
+    let line = text.lines().next()?.trim();
+    if !line.is_empty() && line.chars().all(|c| c == '=') {
+        Some(1)
+    } else if line.len() >= 2 && line.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// parses the definitions of term at the start of text and the terms following it. Terms may be
+/// separated by a single blank line. Returns the entries and the length of text they span, not
+/// including the newline ending the last definition, or None if text does not start with `: `
+fn parse_definitions(term: String, text: &str) -> Option<(DefinitionEntries, usize)> {
+    if !text.starts_with(": ") {
+        return None;
+    }
+    let mut entries: DefinitionEntries = vec![(term, vec![])];
+    let mut consumed = 0;
+    loop {
+        let mut lines = text[consumed..].split_inclusive('\n');
+        let Some(line) = lines.next() else {
+            break;
+        };
+        if let Some(definition) = line.strip_prefix(": ") {
+            entries.last_mut()?.1.push(definition.trim().to_string());
+            consumed += line.len();
+            continue;
+        }
+        // the next term, optionally preceded by a blank line
+        let (skipped, term_line) = if line.trim().is_empty() {
+            (line.len(), lines.next())
+        } else {
+            (0, Some(line))
+        };
+        let is_term = |l: &str| {
+            !l.trim().is_empty()
+                && !l.starts_with(": ")
+                && !l.starts_with('#')
+                && !l.starts_with("- ")
+        };
+        match (term_line, lines.next()) {
+            (Some(term_line), Some(next)) if is_term(term_line) && next.starts_with(": ") => {
+                entries.push((term_line.trim().to_string(), vec![]));
+                consumed += skipped + term_line.len();
+            }
+            _ => break,
+        }
+    }
+    if text[..consumed].ends_with('\n') {
+        consumed -= 1;
+    }
+    Some((entries, consumed))
+}
+
+fn is_thematic_break(text: &str) -> bool {
+    text.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|marker| text.chars().all(|c| c == *marker))
+}
+
+/// returns the trimmed text of a line if it consists of non-blank text only
+fn line_text(components: &[(MdComponent, Range<usize>)]) -> Option<String> {
+    let mut text = String::new();
+    for (c, _) in components {
+        let MdComponent::Text(t) = c else {
+            return None;
+        };
+        text.push_str(t);
+    }
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// consumes all tokens up to and including the next newline
+fn consume_line(lexer: &mut Lexer<'_, MdToken>) -> Result<()> {
+    text_until_token(MdToken::Newline, lexer, false).map(|_| ())
+}
+
 /// returns Result<(heading comp, terminated by newline)>
 fn parse_heading(lexer: &mut Lexer<'_, MdToken>) -> Result<(MdComponent, bool)> {
     let mut level = 1;
@@ -258,7 +506,7 @@ fn parse_list(lexer: &mut Lexer<'_, MdToken>, indent_spaces: usize) -> Result<Md
             }
         }) && indents.trim().is_empty()
         {
-            let indent_spaces = indents.replace("\t", "    ").len();
+            let indent_spaces = indents.len();
             let le = ListElement::new_text(text.to_string());
             list_elements.push((indent_spaces, le));
         } else if let Some((_, le)) = list_elements.last_mut() {
@@ -402,3 +650,80 @@ fn test_list_with_dash() {
     )];
     assert_eq!(result, expected)
 }
+
+#[test]
+fn test_setext_headings() {
+    let text = "Title\n=====\nsome text\n\nSubtitle\n---\nmore text";
+    let result = parse_md_text(text).unwrap();
+    let expected = vec![
+        MdComponent::Heading(1, "Title".to_string()),
+        MdComponent::Text("some text\n\n".to_string()),
+        MdComponent::Heading(2, "Subtitle".to_string()),
+        MdComponent::Text("more text".to_string()),
+    ];
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_setext_frontmatter_is_kept() {
+    let text = "---\ntags: [a, b]\n---\n\ntext";
+    let result = parse_md_text(text).unwrap();
+    let expected = vec![MdComponent::Text(text.to_string())];
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_spanned_heading() {
+    let text = "some ’text’\n\n## Heading\nmore";
+    let result = parse_md_text_spanned(text).unwrap();
+    let start = text.find("##").unwrap();
+    let end = text.find("more").unwrap();
+    assert_eq!(
+        result[1],
+        (MdComponent::Heading(2, "Heading".to_string()), start..end)
+    );
+    assert_eq!(&text[result[0].1.clone()], "some ’text’\n\n");
+    assert_eq!(result[2].1, end..text.len());
+}
+
+#[test]
+fn test_substitutions_applied() {
+    let result = parse_md_text("it’s — here").unwrap();
+    assert_eq!(result, vec![MdComponent::Text("it's - here".to_string())]);
+    let text = "it’s";
+    let result = parse_md_text_with(text, &[]).unwrap();
+    assert_eq!(result, vec![MdComponent::Text(text.to_string())]);
+    // ranges refer to the text before the substitutions
+    let text = "a — b\n# H";
+    let result = parse_md_text_spanned(text).unwrap();
+    assert_eq!(result[1].1, text.find('#').unwrap()..text.len());
+}
+
+#[test]
+fn test_definition_list() {
+    let text = "Intro\n\nApple\n: a fruit\n: a company\n\nPear\n: another fruit\n\nmore";
+    let res = parse_md_text(text).unwrap();
+    let expected = vec![
+        MdComponent::Text("Intro\n\n".to_string()),
+        MdComponent::DefinitionList(vec![
+            (
+                "Apple".to_string(),
+                vec!["a fruit".to_string(), "a company".to_string()],
+            ),
+            ("Pear".to_string(), vec!["another fruit".to_string()]),
+        ]),
+        MdComponent::Text("\n\nmore".to_string()),
+    ];
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn test_tab_and_space_indented_lists() {
+    let tabs = parse_md_text("- a\n\t- b\n\t  more\n\t\t- c\n- d").unwrap();
+    let spaces = parse_md_text("- a\n  - b\n    more\n    - c\n- d").unwrap();
+    assert_eq!(tabs, spaces);
+
+    let text = "- a\n  - b\n    more";
+    let result = parse_md_text_spanned(text).unwrap();
+    assert_eq!(result[0].1, 0..text.len());
+}
@@ -1,17 +1,23 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use clap::{ValueEnum, builder::PossibleValue};
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 pub mod logseq_parsing;
 pub mod md_parsing;
 pub mod obsidian_parsing;
 pub mod zk_parsing;
 
-use crate::{document_component::ParsedDocument, util::files_in_tree};
-use logseq_parsing::{parse_logseq_file, parse_logseq_text};
-use obsidian_parsing::{parse_obsidian_file, parse_obsidian_text};
-use zk_parsing::{parse_zk_file, parse_zk_text};
+use crate::{
+    document_component::{DocumentComponent, MentionedFile, ParsedDocument},
+    error::PkmtError,
+    util::{default_substitutions, files_in_tree},
+};
+use logseq_parsing::{parse_logseq_file_with, parse_logseq_text, parse_logseq_text_with};
+use obsidian_parsing::{parse_obsidian_file_with, parse_obsidian_text, parse_obsidian_text_with};
+use zk_parsing::{parse_zk_file_with, parse_zk_text, parse_zk_text_with};
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TextMode {
     Obsidian,
     LogSeq,
@@ -33,6 +39,284 @@ impl ValueEnum for TextMode {
         })
     }
 }
+/// encoding of the notes that are read, a leading byte order mark is removed
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl ValueEnum for InputEncoding {
+    fn value_variants<'a>() -> &'a [Self] {
+        use InputEncoding::*;
+        &[Utf8, Utf16Le, Utf16Be]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use InputEncoding::*;
+        Some(match self {
+            Utf8 => PossibleValue::new("utf8"),
+            Utf16Le => PossibleValue::new("utf16le"),
+            Utf16Be => PossibleValue::new("utf16be"),
+        })
+    }
+}
+
+impl InputEncoding {
+    pub fn decode(&self, bytes: &[u8]) -> Result<String> {
+        use InputEncoding::*;
+        let text = match self {
+            Utf8 => String::from_utf8(bytes.to_vec()).context("Text is not valid UTF-8")?,
+            Utf16Le | Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    bail!("UTF-16 text has an odd number of bytes");
+                }
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|c| match self {
+                        Utf16Le => u16::from_le_bytes([c[0], c[1]]),
+                        _ => u16::from_be_bytes([c[0], c[1]]),
+                    })
+                    .collect();
+                String::from_utf16(&units).context("Text is not valid UTF-16")?
+            }
+        };
+        Ok(text
+            .strip_prefix('\u{feff}')
+            .map(|t| t.to_string())
+            .unwrap_or(text))
+    }
+
+    pub fn read<T: AsRef<Path>>(&self, file: T) -> Result<String> {
+        let file = file.as_ref();
+        let bytes = std::fs::read(file).context(format!("Failed to read {file:?}"))?;
+        self.decode(&bytes)
+            .context(format!("Failed to decode {file:?}"))
+    }
+}
+
+/// line terminator of written notes
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl ValueEnum for LineEnding {
+    fn value_variants<'a>() -> &'a [Self] {
+        use LineEnding::*;
+        &[Lf, Crlf]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use LineEnding::*;
+        Some(match self {
+            Lf => PossibleValue::new("lf"),
+            Crlf => PossibleValue::new("crlf"),
+        })
+    }
+}
+
+impl LineEnding {
+    /// terminates all lines of text with this line ending
+    pub fn apply(&self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n");
+        match self {
+            LineEnding::Lf => text,
+            LineEnding::Crlf => text.replace('\n', "\r\n"),
+        }
+    }
+}
+
+/// syntax used for rendering frontmatter
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum FrontmatterFormat {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ValueEnum for FrontmatterFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        use FrontmatterFormat::*;
+        &[Yaml, Toml, Json]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use FrontmatterFormat::*;
+        Some(match self {
+            Yaml => PossibleValue::new("yaml"),
+            Toml => PossibleValue::new("toml"),
+            Json => PossibleValue::new("json"),
+        })
+    }
+}
+
+/// how multi-value entries of yaml frontmatter are rendered
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontmatterListStyle {
+    /// `tags: [a, b]`
+    #[default]
+    Inline,
+    /// a block sequence with one `- value` line per value
+    Block,
+}
+
+impl ValueEnum for FrontmatterListStyle {
+    fn value_variants<'a>() -> &'a [Self] {
+        use FrontmatterListStyle::*;
+        &[Inline, Block]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use FrontmatterListStyle::*;
+        Some(match self {
+            Inline => PossibleValue::new("inline"),
+            Block => PossibleValue::new("block"),
+        })
+    }
+}
+
+/// syntax used for rendering links to other notes
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// `[[file|alias]]`
+    Wiki,
+    /// `[alias](file.md)`
+    Markdown,
+}
+
+impl ValueEnum for LinkStyle {
+    fn value_variants<'a>() -> &'a [Self] {
+        use LinkStyle::*;
+        &[Wiki, Markdown]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use LinkStyle::*;
+        Some(match self {
+            Wiki => PossibleValue::new("wiki"),
+            Markdown => PossibleValue::new("markdown"),
+        })
+    }
+}
+
+/// how the title of an admonition is rendered in logseq output
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CalloutTitleStyle {
+    /// `**Title**` as the first line of the quote
+    #[default]
+    Bold,
+    /// `### Title` above the quote
+    Heading,
+}
+
+impl ValueEnum for CalloutTitleStyle {
+    fn value_variants<'a>() -> &'a [Self] {
+        use CalloutTitleStyle::*;
+        &[Bold, Heading]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use CalloutTitleStyle::*;
+        Some(match self {
+            Bold => PossibleValue::new("bold"),
+            Heading => PossibleValue::new("heading"),
+        })
+    }
+}
+
+/// how tags are written in tags properties and frontmatter. Inline tags always keep their `#`,
+/// without it they would be plain text in every mode
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagStyle {
+    /// `#video`, `#[[multi word]]`
+    Hash,
+    /// `video`
+    Bare,
+}
+
+impl TagStyle {
+    /// style used by the mode unless another one is configured
+    pub fn of_mode(mode: &TextMode) -> Self {
+        match mode {
+            TextMode::LogSeq => TagStyle::Hash,
+            TextMode::Zk | TextMode::Obsidian => TagStyle::Bare,
+        }
+    }
+
+    /// renders tag, given with or without `#`, in this style. `[[page]]` references are kept in
+    /// the hash style as logseq treats them like tags
+    pub fn render(&self, tag: &str) -> String {
+        let tag = tag.trim();
+        if *self == TagStyle::Hash && tag.starts_with("[[") {
+            return tag.to_string();
+        }
+        let tag = tag.trim_start_matches('#');
+        let tag = tag
+            .strip_prefix("[[")
+            .and_then(|t| t.strip_suffix("]]"))
+            .unwrap_or(tag);
+        match self {
+            TagStyle::Hash if tag.contains(char::is_whitespace) => format!("#[[{tag}]]"),
+            TagStyle::Hash => format!("#{tag}"),
+            TagStyle::Bare => tag.to_string(),
+        }
+    }
+}
+
+/// how nested tags (`parent/child`) are written in tags properties and frontmatter
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NestedTagStyle {
+    /// `parent/child`
+    #[default]
+    Keep,
+    /// `parent`, `parent/child`
+    Explode,
+}
+
+impl ValueEnum for NestedTagStyle {
+    fn value_variants<'a>() -> &'a [Self] {
+        use NestedTagStyle::*;
+        &[Keep, Explode]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        use NestedTagStyle::*;
+        Some(match self {
+            Keep => PossibleValue::new("keep"),
+            Explode => PossibleValue::new("explode"),
+        })
+    }
+}
+
+impl NestedTagStyle {
+    /// the tags written for tag, given with or without `#`. `[[page]]` references are kept
+    pub fn expand(&self, tag: &str) -> Vec<String> {
+        match self {
+            NestedTagStyle::Explode if !tag.contains("[[") => tag
+                .match_indices('/')
+                .map(|(i, _)| &tag[..i])
+                .filter(|parent| !parent.trim_start_matches('#').is_empty())
+                .chain(std::iter::once(tag))
+                .map(|t| t.to_string())
+                .collect(),
+            _ => vec![tag.to_string()],
+        }
+    }
+}
+
 pub fn parse_text(
     text: &str,
     mode: &TextMode,
@@ -46,17 +330,160 @@ pub fn parse_text(
     }
 }
 
-pub fn parse_file(file: &PathBuf, mode: &TextMode) -> Result<ParsedDocument> {
+/// see parse_file_with for strict, best_effort and substitutions
+pub fn parse_text_with(
+    text: &str,
+    mode: &TextMode,
+    file_dir: &Option<PathBuf>,
+    strict: bool,
+    best_effort: bool,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument> {
     use TextMode::*;
     match mode {
-        Obsidian => parse_obsidian_file(file),
-        LogSeq => parse_logseq_file(file),
-        Zk => parse_zk_file(file),
+        Obsidian => parse_obsidian_text_with(text, file_dir, substitutions),
+        LogSeq => parse_logseq_text_with(text, file_dir, best_effort, substitutions),
+        Zk => parse_zk_text_with(text, file_dir, strict, best_effort, substitutions),
+    }
+}
+
+/// resolves the name of a `[[name]]` link against the directory of the linking note. The name may
+/// contain folders (`[[sub/dir/note]]`) and lack the `.md` extension. Names that do not refer to an
+/// existing file are kept as names.
+pub(crate) fn resolve_link_name(name: &str, file_dir: &Option<PathBuf>) -> Result<MentionedFile> {
+    if let Some(dir) = file_dir {
+        let file = dir.join(name);
+        let note = dir.join(format!("{name}.md"));
+        if let Some(found) = [file, note].into_iter().find(|f| f.exists()) {
+            return Ok(MentionedFile::FilePath(found.canonicalize()?));
+        }
     }
+    Ok(MentionedFile::FileName(name.to_string()))
+}
+
+/// in best effort mode, a block that fails to parse is kept as raw text and a warning is printed
+/// instead of failing the whole note
+pub(crate) fn recover_block(
+    parsed: Result<ParsedDocument>,
+    text: &str,
+    best_effort: bool,
+) -> Result<ParsedDocument> {
+    match parsed {
+        Err(e) if best_effort => {
+            eprintln!("Warning: keeping block as text, failed to parse it: {e:#}");
+            Ok(ParsedDocument::ParsedText(vec![DocumentComponent::Text(
+                text.to_string(),
+            )]))
+        }
+        parsed => parsed,
+    }
+}
+
+pub fn parse_file(file: &PathBuf, mode: &TextMode) -> Result<ParsedDocument, PkmtError> {
+    parse_file_with(
+        file,
+        mode,
+        false,
+        false,
+        &InputEncoding::Utf8,
+        &default_substitutions(),
+    )
+}
+
+/// in strict mode, constructs the parser of the mode does not support result in an error
+/// instead of being kept as text (only implemented for zk). In best effort mode, blocks that fail
+/// to parse are kept as text (only implemented for zk and logseq). The substitutions are applied
+/// to the text before it is parsed.
+pub fn parse_file_with(
+    file: &PathBuf,
+    mode: &TextMode,
+    strict: bool,
+    best_effort: bool,
+    encoding: &InputEncoding,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument, PkmtError> {
+    use TextMode::*;
+    match mode {
+        Obsidian => parse_obsidian_file_with(file, encoding, substitutions),
+        LogSeq => parse_logseq_file_with(file, best_effort, encoding, substitutions),
+        Zk => parse_zk_file_with(file, strict, best_effort, encoding, substitutions),
+    }
+    .map_err(|e| PkmtError::from_parse_error(file, e))
 }
 
 /// recursively parses all files in the given directory
 pub fn parse_all_files_in_dir(root_dir: &PathBuf, mode: &TextMode) -> Result<Vec<ParsedDocument>> {
-    let files = files_in_tree(root_dir, &Some(vec!["md"]))?;
-    files.iter().map(|f| parse_file(f, mode)).collect()
+    let files = files_in_tree(root_dir, &Some(vec!["md"]), None)?;
+    files
+        .iter()
+        .map(|f| parse_file(f, mode).map_err(anyhow::Error::from))
+        .collect()
+}
+
+#[test]
+fn test_byte_order_mark() {
+    use crate::document_component::DocumentComponent;
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let file = dir.join("utf8.md");
+    std::fs::write(&file, "\u{feff}---\ntitle: note\n---\n\n# Note").unwrap();
+    for mode in [TextMode::Zk, TextMode::LogSeq, TextMode::Obsidian] {
+        let pd = parse_file(&file, &mode).unwrap();
+        match &pd.components()[0] {
+            DocumentComponent::Text(text) => assert!(!text.contains('\u{feff}'), "{mode:?}"),
+            DocumentComponent::Frontmatter(_) => {}
+            other => panic!("unexpected first component {other:?} for {mode:?}"),
+        }
+    }
+    let pd = parse_file(&file, &TextMode::Zk).unwrap();
+    assert!(matches!(
+        pd.components()[0],
+        DocumentComponent::Frontmatter(_)
+    ));
+
+    let file = dir.join("utf16.md");
+    let bytes: Vec<u8> = "\u{feff}- first block"
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes())
+        .collect();
+    std::fs::write(&file, bytes).unwrap();
+    assert!(parse_file(&file, &TextMode::LogSeq).is_err());
+    let pd = parse_file_with(
+        &file,
+        &TextMode::LogSeq,
+        false,
+        false,
+        &InputEncoding::Utf16Le,
+        &default_substitutions(),
+    )
+    .unwrap();
+    assert_eq!(pd.to_logseq_text(&None), "- first block");
+}
+
+#[test]
+fn test_parse_file_errors() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("malformed.md");
+    std::fs::write(&file, "- see [[unclosed").unwrap();
+
+    match parse_file(&file, &TextMode::LogSeq) {
+        Err(PkmtError::Parse { file: f, .. }) => assert_eq!(f, file),
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+    assert!(matches!(
+        parse_file(&dir.join("missing.md"), &TextMode::LogSeq),
+        Err(PkmtError::Io { .. })
+    ));
+    // the context of the io error is kept
+    std::fs::create_dir(dir.join("folder.md")).unwrap();
+    match parse_file(&dir.join("folder.md"), &TextMode::LogSeq) {
+        Err(PkmtError::Io { source, .. }) => {
+            assert!(format!("{source:#}").starts_with("Failed to read"))
+        }
+        other => panic!("expected an io error, got {other:?}"),
+    }
 }
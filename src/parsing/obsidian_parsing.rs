@@ -1,15 +1,18 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
-    str::FromStr,
 };
 use test_log::test;
 use tracing::{debug, instrument};
 
 use crate::{
     document_component::ListElem,
-    parsing::md_parsing::{ListElement, MdComponent, parse_md_text},
-    util::apply_substitutions,
+    parsing::{
+        InputEncoding,
+        md_parsing::{ListElement, MdComponent, parse_md_text_with},
+        resolve_link_name,
+    },
+    util::{apply_substitution_table, default_substitutions, inline_field_pattern},
 };
 use anyhow::{Context, Result, bail};
 
@@ -53,30 +56,146 @@ enum ObsidianToken {
     MiscText,
     #[token("\\")]
     Backslash,
+    #[token("%%")]
+    CommentDelimiter,
 }
 
-pub fn parse_obsidian_file<T: AsRef<Path>>(file_path: T) -> Result<ParsedDocument> {
+/// the substitutions are applied to the text before it is parsed
+pub fn parse_obsidian_file_with<T: AsRef<Path>>(
+    file_path: T,
+    encoding: &InputEncoding,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument> {
     let file_path = file_path.as_ref().canonicalize()?;
-    let text = std::fs::read_to_string(&file_path)?;
+    let text = encoding.read(&file_path)?;
 
     let file_dir = file_path
         .parent()
         .context(format!("{file_path:?} has no parent!"))?
         .to_path_buf();
 
-    let pt = parse_obsidian_text(&text, &Some(file_dir))?;
+    let pt = parse_obsidian_text_with(&text, &Some(file_dir), substitutions)?;
     Ok(ParsedDocument::ParsedFile(pt.into_components(), file_path))
 }
 
-#[instrument]
+/// splits text into text and block comments, a block comment starts with a line beginning with
+/// `%%` that does not close it and ends at the next line ending with `%%`. Lines in code fences
+/// are never comments.
+/// Block comments are returned as Err(comment without delimiters).
+fn split_block_comments(text: &str) -> Vec<std::result::Result<String, String>> {
+    let mut res = vec![];
+    let mut current = String::new();
+    let mut comment: Option<String> = None;
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if comment.is_none() && (in_fence || trimmed.starts_with("```")) {
+            if trimmed.starts_with("```") {
+                in_fence = !in_fence;
+            }
+            current.push_str(line);
+        } else if let Some(c) = comment.as_mut() {
+            if let Some(last) = trimmed.strip_suffix("%%") {
+                c.push_str(last);
+                res.push(Err(comment.take().unwrap()));
+            } else {
+                c.push_str(line);
+            }
+        } else if let Some(rest) = trimmed.strip_prefix("%%")
+            && !rest.contains("%%")
+        {
+            if !current.is_empty() {
+                res.push(Ok(std::mem::take(&mut current)));
+            }
+            let mut c = rest.to_string();
+            c.push('\n');
+            comment = Some(c);
+        } else {
+            current.push_str(line);
+        }
+    }
+    if let Some(c) = comment {
+        // unterminated, keep it as text
+        current.push_str("%%");
+        current.push_str(&c);
+    }
+    if !current.is_empty() {
+        res.push(Ok(current));
+    }
+    res
+}
+
+/// splits off yaml frontmatter delimited by `---` lines, it is parsed like zk frontmatter.
+/// Returns the frontmatter and the remaining text, frontmatter that cannot be parsed is kept
+fn split_frontmatter<'a>(
+    text: &'a str,
+    file_dir: &Option<PathBuf>,
+) -> (Option<DocumentComponent>, &'a str) {
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return (None, text);
+    };
+    let mut frontmatter_len = text.len() - rest.len();
+    let mut closed = false;
+    for line in rest.split_inclusive('\n') {
+        frontmatter_len += line.len();
+        if line.trim_end() == "---" {
+            closed = true;
+            break;
+        }
+    }
+    if !closed {
+        return (None, text);
+    }
+    let pd = super::zk_parsing::parse_zk_text(&text[..frontmatter_len], file_dir);
+    match pd.map(|pd| pd.into_components().into_iter().next()) {
+        Ok(Some(fm @ DocumentComponent::Frontmatter(_))) => (Some(fm), &text[frontmatter_len..]),
+        _ => (None, text),
+    }
+}
+
 pub fn parse_obsidian_text(text: &str, file_dir: &Option<PathBuf>) -> Result<ParsedDocument> {
-    let parsed_md = parse_md_text(text).context("Failed to parse md")?;
+    parse_obsidian_text_with(text, file_dir, &default_substitutions())
+}
+
+/// the substitutions are applied to the text before it is parsed
+#[instrument(skip(substitutions))]
+pub fn parse_obsidian_text_with(
+    text: &str,
+    file_dir: &Option<PathBuf>,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument> {
+    let text = apply_substitution_table(text, substitutions);
+    let mut components = vec![];
+    let (frontmatter, text) = split_frontmatter(&text, file_dir);
+    components.extend(frontmatter);
+    for part in split_block_comments(text) {
+        match part {
+            Ok(text) => components.append(&mut parse_obsidian_blocks(&text, file_dir)?),
+            Err(comment) => components.push(DocumentComponent::Comment(comment)),
+        }
+    }
+    Ok(ParsedDocument::ParsedText(components))
+}
+
+fn parse_obsidian_blocks(text: &str, file_dir: &Option<PathBuf>) -> Result<Vec<DocumentComponent>> {
+    let parsed_md = parse_md_text_with(text, &[]).context("Failed to parse md")?;
     let mut components = vec![];
     parsed_md.into_iter().try_for_each(|comp| match comp {
         MdComponent::Heading(level, text) => {
             components.push(DocumentComponent::Heading(level as u16, text));
             Ok::<(), anyhow::Error>(())
         }
+        MdComponent::ThematicBreak(marker) => {
+            components.push(DocumentComponent::ThematicBreak(marker));
+            Ok(())
+        }
+        MdComponent::DefinitionList(entries) => {
+            components.push(DocumentComponent::DefinitionList(entries));
+            Ok(())
+        }
         MdComponent::Text(text) => {
             let tmp = parse_obsidian_text_inner(&text, file_dir)?;
             let mut comps = tmp.into_components();
@@ -96,7 +215,7 @@ pub fn parse_obsidian_text(text: &str, file_dir: &Option<PathBuf>) -> Result<Par
         }
     })?;
 
-    Ok(ParsedDocument::ParsedText(components))
+    Ok(components)
 }
 
 fn parse_md_list_element(
@@ -117,10 +236,9 @@ fn parse_md_list_element(
 #[instrument]
 pub fn parse_obsidian_text_inner(text: &str, file_dir: &Option<PathBuf>) -> Result<ParsedDocument> {
     use ObsidianToken::*;
-    let text = apply_substitutions(text);
-
-    let mut lexer = ObsidianToken::lexer(&text);
+    let mut lexer = ObsidianToken::lexer(text);
     let mut res = vec![];
+    let inline_field_re = regex::Regex::new(&inline_field_pattern())?;
 
     while let Some(result) = lexer.next() {
         println!("{result:?}: '{:?}'", lexer.slice());
@@ -178,11 +296,57 @@ pub fn parse_obsidian_text_inner(text: &str, file_dir: &Option<PathBuf>) -> Resu
                         }
                     }
                     MiscText => {
-                        res.push(DocumentComponent::Text(lexer.slice().to_string()));
+                        let slice = lexer.slice();
+                        // runs of misc text may end with the `(` of an inline field
+                        match slice.strip_suffix('(').and_then(|before| {
+                            inline_field_re
+                                .captures(lexer.remainder())
+                                .map(|c| (before, c))
+                        }) {
+                            Some((before, c)) => {
+                                if !before.is_empty() {
+                                    res.push(DocumentComponent::Text(before.to_string()));
+                                }
+                                res.push(DocumentComponent::InlineField(
+                                    c[1].to_string(),
+                                    c[2].to_string(),
+                                ));
+                                let len = c[0].len();
+                                lexer.bump(len);
+                            }
+                            None => res.push(DocumentComponent::Text(slice.to_string())),
+                        }
                     }
                     CarriageReturn => {
                         res.push(DocumentComponent::Text("\r".to_string()));
                     }
+                    CommentDelimiter => {
+                        // a single %% is kept as text
+                        let Some(len) = lexer.remainder().find("%%") else {
+                            res.push(DocumentComponent::Text("%%".to_string()));
+                            continue;
+                        };
+                        let comment = lexer.remainder()[..len].to_string();
+                        lexer.bump(len + 2);
+                        res.push(DocumentComponent::Comment(comment));
+                    }
+                    TripleBackQuote => {
+                        let remainder = lexer.remainder();
+                        let Some(end) = remainder.find("\n```") else {
+                            bail!(
+                                "Unterminated code block {}",
+                                construct_error_details(&lexer)
+                            )
+                        };
+                        let (code_type, code) = remainder[..end]
+                            .split_once('\n')
+                            .unwrap_or((&remainder[..end], ""));
+                        let code_type = Some(code_type.trim())
+                            .filter(|t| !t.is_empty())
+                            .map(|t| t.to_string());
+                        res.push(DocumentComponent::CodeBlock(code.to_string(), code_type));
+                        lexer.bump(end + 4);
+                    }
                     _ => todo!("Support missing token types: {token:?}"),
                 }
             }
@@ -271,20 +435,7 @@ fn parse_file_link(
     while let Some(Ok(token)) = lexer.next() {
         match token {
             ClosingDoubleBraces => {
-                let name = name.trim().to_string();
-                let mut mf = MentionedFile::FileName(name.clone());
-                if let Some(dir) = file_dir {
-                    let file = dir.join(&name);
-                    if file.exists() {
-                        let file = file.canonicalize()?;
-                        mf = MentionedFile::FilePath(file);
-                    }
-                    let Ok(file) = PathBuf::from_str(&name);
-
-                    if file.exists() {
-                        mf = MentionedFile::FilePath(file.canonicalize()?);
-                    }
-                }
+                let mf = resolve_link_name(name.trim(), file_dir)?;
                 return Ok((mf, section, rename));
             }
             SingleHash => {
@@ -470,3 +621,118 @@ fn test_nested_list() {
         panic!("Error: {res:?}");
     }
 }
+
+#[test]
+fn test_inline_comment() {
+    use crate::document_component::FileInfo;
+    let file_info = |strip| {
+        Some(
+            FileInfo::try_new(PathBuf::from("note.md"), None, None, None)
+                .unwrap()
+                .with_strip_comments(strip),
+        )
+    };
+    let res = parse_obsidian_text("some %%not yet done%% text", &None).unwrap();
+    assert_eq!(
+        res.components()[1],
+        DocumentComponent::Comment("not yet done".to_string())
+    );
+    assert_eq!(
+        res.to_zk_text(&file_info(false)),
+        "some <!--not yet done--> text"
+    );
+    assert_eq!(res.to_zk_text(&file_info(true)), "some  text");
+}
+
+#[test]
+fn test_comment_delimiters_as_text() {
+    use DocumentComponent::*;
+    let text = "```\n%%\nnot a comment\n%%\n```\n\n50%% done";
+    let res = parse_obsidian_text(text, &None).unwrap();
+    assert!(
+        res.get_all_document_components(&|c| matches!(c, Comment(_)))
+            .is_empty(),
+        "{res:?}"
+    );
+    assert!(
+        matches!(&res.components()[0], CodeBlock(code, None) if code == "%%\nnot a comment\n%%")
+    );
+    assert!(res.to_zk_text(&None).ends_with("50%% done"));
+
+    let res = parse_obsidian_text("%%\nhidden\n%%\ntext", &None).unwrap();
+    assert_eq!(res.components()[0], Comment("\nhidden\n".to_string()));
+}
+
+#[test]
+fn test_inline_field() {
+    let res = parse_obsidian_text("Project (status:: active), due soon", &None).unwrap();
+    assert!(
+        res.components().contains(&DocumentComponent::InlineField(
+            "status".to_string(),
+            "active".to_string()
+        )),
+        "{res:?}"
+    );
+    assert!(res.has_field("status", "active"));
+    assert_eq!(res.to_zk_text(&None), "Project (status:: active), due soon");
+}
+
+#[test]
+fn test_folder_qualified_link() {
+    use DocumentComponent::*;
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(dir.join("sub/dir")).unwrap();
+    std::fs::write(dir.join("sub/dir/note.md"), "linked").unwrap();
+
+    let res = parse_obsidian_text(
+        "see [[sub/dir/note]] and [[sub/missing|other]]",
+        &Some(dir.clone()),
+    )
+    .unwrap();
+    let expected = ParsedDocument::ParsedText(vec![
+        Text("see ".to_string()),
+        FileLink(
+            MentionedFile::FilePath(dir.join("sub/dir/note.md").canonicalize().unwrap()),
+            None,
+            None,
+        ),
+        Text(" and ".to_string()),
+        FileLink(
+            MentionedFile::FileName("sub/missing".to_string()),
+            None,
+            Some("other".to_string()),
+        ),
+    ]);
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn test_frontmatter() {
+    let text = "---\ntitle: note\n---\n# Note";
+    let (frontmatter, rest) = split_frontmatter(text, &None);
+    assert!(matches!(
+        frontmatter,
+        Some(DocumentComponent::Frontmatter(_))
+    ));
+    assert_eq!(rest, "# Note");
+    let pd = parse_obsidian_text(text, &None).unwrap();
+    assert!(matches!(
+        pd.components()[..],
+        [
+            DocumentComponent::Frontmatter(_),
+            DocumentComponent::Heading(1, _)
+        ]
+    ));
+    assert_eq!(pd.to_zk_text(&None), text);
+
+    let (frontmatter, rest) = split_frontmatter("---\r\ntitle: note\r\n---\r\ntext", &None);
+    assert!(frontmatter.is_some());
+    assert_eq!(rest, "text");
+    // a leading thematic break without a closing `---` is not frontmatter
+    let text = "---\ntext after a break";
+    assert_eq!(split_frontmatter(text, &None), (None, text));
+    // `---` lines later in the note are not frontmatter either
+    let text = "text\n---\ntitle: note\n---\n";
+    assert_eq!(split_frontmatter(text, &None), (None, text));
+}
@@ -7,9 +7,16 @@ use std::{
 use test_log::test;
 
 use crate::{
-    document_component::{ListElem, Property},
-    parsing::md_parsing::{ListElement, MdComponent, parse_md_text},
-    util::{apply_substitutions, file_link_pattern, link_name_pattern},
+    document_component::{ListElem, Property, PropertyScope},
+    parsing::{
+        InputEncoding,
+        md_parsing::{ListElement, MdComponent, parse_md_text_with},
+        recover_block, resolve_link_name,
+    },
+    util::{
+        apply_substitution_table, default_substitutions, file_link_pattern, inline_field_pattern,
+        is_external_url, shortest_link_name_pattern,
+    },
 };
 use anyhow::{Context, Result, bail};
 use tracing::{debug, instrument};
@@ -62,31 +69,92 @@ enum ZkToken {
 }
 
 pub fn parse_zk_file<T: AsRef<Path>>(file_path: T) -> Result<ParsedDocument> {
+    parse_zk_file_with(
+        file_path,
+        false,
+        false,
+        &InputEncoding::Utf8,
+        &default_substitutions(),
+    )
+}
+
+/// in strict mode, constructs that are not supported by the parser result in an error instead of
+/// being kept as text. In best effort mode, blocks that fail to parse are kept as text.
+/// The substitutions are applied to the text before it is parsed.
+pub fn parse_zk_file_with<T: AsRef<Path>>(
+    file_path: T,
+    strict: bool,
+    best_effort: bool,
+    encoding: &InputEncoding,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument> {
     let file_path = file_path.as_ref().canonicalize()?;
-    let text =
-        std::fs::read_to_string(&file_path).context("Failed to read zk file: {file_path:?}")?;
+    let text = encoding
+        .read(&file_path)
+        .context(format!("Failed to read zk file: {file_path:?}"))?;
 
     let file_dir = file_path
         .parent()
         .context(format!("{file_path:?} has no parent!"))?
         .to_path_buf();
 
-    let pt = parse_zk_text(&text, &Some(file_dir))
+    let pt = parse_zk_text_with(&text, &Some(file_dir), strict, best_effort, substitutions)
         .context(format!("Failed to parse zk file {file_path:?}"))?;
     Ok(ParsedDocument::ParsedFile(pt.into_components(), file_path))
 }
 
-#[instrument]
 pub fn parse_zk_text(text: &str, file_dir: &Option<PathBuf>) -> Result<ParsedDocument> {
-    let parsed_md = parse_md_text(text).context("Failed to parse md")?;
+    parse_zk_text_with(text, file_dir, false, false, &default_substitutions())
+}
+
+/// in strict mode, constructs that are not supported by the parser result in an error instead of
+/// being kept as text. In best effort mode, blocks that fail to parse are kept as text.
+/// The substitutions are applied to the text before it is parsed.
+#[instrument(skip(substitutions))]
+pub fn parse_zk_text_with(
+    text: &str,
+    file_dir: &Option<PathBuf>,
+    strict: bool,
+    best_effort: bool,
+    substitutions: &[(String, String)],
+) -> Result<ParsedDocument> {
+    let text = apply_substitution_table(text, substitutions);
+    parse_substituted_zk_text(&text, file_dir, strict, best_effort)
+}
+
+/// parses text the substitutions were already applied to
+fn parse_substituted_zk_text(
+    text: &str,
+    file_dir: &Option<PathBuf>,
+    strict: bool,
+    best_effort: bool,
+) -> Result<ParsedDocument> {
+    if let Some((frontmatter, rest)) = split_alt_frontmatter(text, file_dir)? {
+        let mut pd = parse_substituted_zk_text(rest, file_dir, strict, best_effort)?;
+        pd.insert_component(0, frontmatter);
+        return Ok(pd);
+    }
+    let parsed_md = parse_md_text_with(text, &[]).context("Failed to parse md")?;
     let mut components = vec![];
     parsed_md.into_iter().try_for_each(|comp| match comp {
         MdComponent::Heading(level, text) => {
             components.push(DocumentComponent::Heading(level as u16, text));
             Ok::<(), anyhow::Error>(())
         }
+        MdComponent::ThematicBreak(marker) => {
+            components.push(DocumentComponent::ThematicBreak(marker));
+            Ok(())
+        }
+        MdComponent::DefinitionList(entries) => {
+            components.push(DocumentComponent::DefinitionList(entries));
+            Ok(())
+        }
         MdComponent::Text(text) => {
-            let tmp = parse_zk_text_inner(&text, file_dir)?;
+            let tmp = recover_block(
+                parse_zk_text_inner(&text, file_dir, strict, best_effort),
+                &text,
+                best_effort,
+            )?;
             let mut comps = tmp.into_components();
             components.append(&mut comps);
             Ok(())
@@ -94,7 +162,7 @@ pub fn parse_zk_text(text: &str, file_dir: &Option<PathBuf>) -> Result<ParsedDoc
         MdComponent::List(list_elements, terminated_by_blank_line) => {
             let list_elements: Result<Vec<ListElem>> = list_elements
                 .iter()
-                .map(|le| parse_md_list_element(le, file_dir))
+                .map(|le| parse_md_list_element(le, file_dir, strict, best_effort))
                 .collect();
             components.push(DocumentComponent::List(
                 list_elements?,
@@ -110,12 +178,18 @@ pub fn parse_zk_text(text: &str, file_dir: &Option<PathBuf>) -> Result<ParsedDoc
 fn parse_md_list_element(
     list_element: &ListElement,
     file_dir: &Option<PathBuf>,
+    strict: bool,
+    best_effort: bool,
 ) -> Result<ListElem> {
-    let contents = parse_zk_text_inner(&list_element.text, file_dir)?;
+    let contents = recover_block(
+        parse_zk_text_inner(&list_element.text, file_dir, strict, best_effort),
+        &list_element.text,
+        best_effort,
+    )?;
     let children: Result<Vec<ListElem>> = list_element
         .children
         .iter()
-        .map(|c| parse_md_list_element(c, file_dir))
+        .map(|c| parse_md_list_element(c, file_dir, strict, best_effort))
         .collect();
     let mut res = ListElem::new(contents);
     res.children = children?;
@@ -123,20 +197,26 @@ fn parse_md_list_element(
 }
 
 #[instrument(skip_all)]
-pub fn parse_zk_text_inner(text: &str, file_dir: &Option<PathBuf>) -> Result<ParsedDocument> {
+pub fn parse_zk_text_inner(
+    text: &str,
+    file_dir: &Option<PathBuf>,
+    strict: bool,
+    best_effort: bool,
+) -> Result<ParsedDocument> {
     use ZkToken::*;
-    let text = apply_substitutions(text);
-    debug!("text after subsitutions: {text:?}");
-
-    let mut lexer = ZkToken::lexer(&text);
+    let mut lexer = ZkToken::lexer(text);
     let mut res = vec![];
     let mut blank_line = true;
     // opening [ is not included as this is only run right after encountering [
+    // targets with spaces may be enclosed in <>
     let file_link_re = regex::Regex::new(&format!(
-        r"{}\]\({}\)",
-        link_name_pattern(),
+        r"{}\]\(<?{}>?\)",
+        shortest_link_name_pattern(),
         file_link_pattern()
     ))?;
+    // `#` is run right before, purely numeric tags like `#1` are kept as text
+    let tag_re = regex::Regex::new(r"^[\p{L}\p{N}_][\p{L}\p{N}_/\-]*")?;
+    let inline_field_re = regex::Regex::new(&inline_field_pattern())?;
 
     while let Some(result) = lexer.next() {
         debug!(
@@ -164,20 +244,39 @@ pub fn parse_zk_text_inner(text: &str, file_dir: &Option<PathBuf>) -> Result<Par
                             debug!("found property start: {lexer:?}");
                             let name = lexer.slice().trim().trim_end_matches("::=").trim();
                             let prop = parse_property(&mut lexer, name.to_string(), file_dir)?;
-                            res.push(DocumentComponent::Properties(vec![prop]));
+                            res.push(DocumentComponent::Properties(
+                                vec![prop],
+                                PropertyScope::Block,
+                            ));
                         } else {
                             res.push(DocumentComponent::Text(lexer.slice().to_string()));
                         }
                     }
                     SingleHash => {
-                        res.push(DocumentComponent::Text("#".to_string()));
+                        // headings are handled by the markdown parser, so a `#` preceded by
+                        // whitespace starts an inline tag
+                        let after_space = text[..lexer.span().start]
+                            .chars()
+                            .last()
+                            .is_none_or(char::is_whitespace);
+                        match tag_re.find(lexer.remainder()) {
+                            Some(tag)
+                                if after_space && !tag.as_str().chars().all(char::is_numeric) =>
+                            {
+                                let tag = tag.as_str().to_string();
+                                lexer.bump(tag.len());
+                                res.push(DocumentComponent::Tag(tag));
+                                blank_line = false;
+                            }
+                            _ => res.push(DocumentComponent::Text("#".to_string())),
+                        }
                     }
                     Name => {
                         res.push(DocumentComponent::Text(lexer.slice().to_string()));
                         blank_line = false;
                     }
                     AdNoteStart => {
-                        res.push(parse_adnote(&mut lexer, file_dir)?);
+                        res.push(parse_adnote(&mut lexer, file_dir, strict, best_effort)?);
                         blank_line = false;
                     }
                     Space => {
@@ -206,14 +305,22 @@ pub fn parse_zk_text_inner(text: &str, file_dir: &Option<PathBuf>) -> Result<Par
                                 file_link_re.as_str()
                             );
 
-                            let mf = if path.exists() {
-                                MentionedFile::FilePath(path)
+                            let target = path.as_os_str().to_string_lossy().to_string();
+                            let file_link = if is_external_url(&target) {
+                                DocumentComponent::Url(target, name)
+                            } else if path.exists() {
+                                DocumentComponent::FileLink(
+                                    MentionedFile::FilePath(path),
+                                    None,
+                                    name,
+                                )
                             } else {
-                                MentionedFile::FileName(
-                                    path.as_os_str().to_string_lossy().to_string(),
+                                DocumentComponent::FileLink(
+                                    MentionedFile::FileName(target),
+                                    None,
+                                    name,
                                 )
                             };
-                            let file_link = DocumentComponent::FileLink(mf, None, name);
                             debug!("Found file link {file_link:?}");
                             res.push(file_link);
 
@@ -255,6 +362,26 @@ pub fn parse_zk_text_inner(text: &str, file_dir: &Option<PathBuf>) -> Result<Par
                         res.push(DocumentComponent::Text("\\".to_string()));
                         blank_line = false;
                     }
+                    // inline code is kept as is, links and pipes in it are not special
+                    MiscText if lexer.slice() == "`" => {
+                        if let Some(len) = inline_code_len(lexer.remainder()) {
+                            lexer.bump(len);
+                        }
+                        res.push(DocumentComponent::Text(lexer.slice().to_string()));
+                        blank_line = false;
+                    }
+                    MiscText if lexer.slice() == "(" => {
+                        if let Some(c) = inline_field_re.captures(lexer.remainder()) {
+                            res.push(DocumentComponent::InlineField(
+                                c[1].to_string(),
+                                c[2].to_string(),
+                            ));
+                            lexer.bump(c[0].len());
+                        } else {
+                            res.push(DocumentComponent::Text("(".to_string()));
+                        }
+                        blank_line = false;
+                    }
                     MiscText => {
                         res.push(DocumentComponent::Text(lexer.slice().to_string()));
                         blank_line = false;
@@ -266,14 +393,29 @@ pub fn parse_zk_text_inner(text: &str, file_dir: &Option<PathBuf>) -> Result<Par
                         res.push(DocumentComponent::Text("- ".to_string()));
                     }
                     FrontmatterDelim => {
-                        let fm = parse_frontmatter(&mut lexer, file_dir)?;
-                        res.push(fm);
+                        // frontmatter has to be the first non-empty part of the text
+                        if res
+                            .iter()
+                            .all(|c| matches!(c, DocumentComponent::Text(t) if t.trim().is_empty()))
+                        {
+                            let fm = parse_frontmatter(&mut lexer, file_dir)?;
+                            res.push(fm);
+                        } else {
+                            res.push(DocumentComponent::Text(lexer.slice().to_string()));
+                            blank_line = false;
+                        }
                     }
                     Unicode => {
                         let slice = lexer.slice();
                         res.push(DocumentComponent::Text(slice.to_string()));
                     }
                     _ => {
+                        if strict {
+                            bail!(
+                                "Unsupported {token:?} in strict mode: {}",
+                                construct_error_details(&lexer)
+                            );
+                        }
                         debug!(
                             "Support missing token types: {token:?}. Falling back to adding text"
                         );
@@ -415,27 +557,40 @@ fn parse_frontmatter(
         };
         match token {
             FrontmatterDelim => {
-                let mut props = vec![];
-                text.lines().try_for_each(|l| {
-                    let tmp: anyhow::Result<()> = if l.is_empty() {
-                        Ok(())
-                    } else {
-                        let parts = l
-                            .split_once(":")
-                            .context("frontmatter lines need to contain a colon, got {l:?}")?;
-                        let name = parts.0.trim();
-                        let (vals, is_multi) = parse_prop_values(parts.1);
-                        props.push(Property::new_parse(
-                            name.to_string(),
+                // name, values, is_multi, whether `- value` lines may follow
+                let mut entries: Vec<(String, Vec<String>, bool, bool)> = vec![];
+                for l in text.lines() {
+                    if l.trim().is_empty() {
+                        continue;
+                    }
+                    // item of a yaml block sequence
+                    if let Some((_, vals, is_multi, true)) = entries.last_mut()
+                        && let Some(item) = l.trim().strip_prefix("- ")
+                    {
+                        vals.push(item.trim().to_string());
+                        *is_multi = true;
+                        continue;
+                    }
+                    let parts = l.split_once(":").context(format!(
+                        "frontmatter lines need to contain a colon, got {l:?}"
+                    ))?;
+                    let name = parts.0.trim();
+                    let (vals, is_multi) = parse_prop_values(parts.1);
+                    let block = parts.1.trim().is_empty();
+                    entries.push((name.to_string(), vals, is_multi, block));
+                }
+                let props = entries
+                    .into_iter()
+                    .map(|(name, vals, is_multi, _)| {
+                        Property::new_parse(
+                            name,
                             !is_multi,
                             &vals,
                             crate::parsing::TextMode::Zk,
                             file_dir,
-                        ));
-                        Ok(())
-                    };
-                    tmp
-                })?;
+                        )
+                    })
+                    .collect();
                 return Ok(DocumentComponent::Frontmatter(props));
             }
             _ => {
@@ -446,6 +601,97 @@ fn parse_frontmatter(
     bail!("Reached the end of frontmatter!");
 }
 
+/// splits off frontmatter in toml (delimited by `+++`) or json (a leading object) format.
+/// Returns the parsed frontmatter and the remaining text
+fn split_alt_frontmatter<'a>(
+    text: &'a str,
+    file_dir: &Option<PathBuf>,
+) -> Result<Option<(DocumentComponent, &'a str)>> {
+    let trimmed = text.trim_start();
+    if let Some(rest) = trimmed.strip_prefix("+++")
+        && rest.starts_with(['\n', '\r'])
+    {
+        let Some((body, rest)) = rest.split_once("\n+++") else {
+            bail!("Missing closing +++ of toml frontmatter!");
+        };
+        let table: toml::Table =
+            toml::from_str(body).context("Failed to parse toml frontmatter")?;
+        let props = table
+            .iter()
+            .map(|(name, value)| {
+                let (vals, is_multi) = match value {
+                    toml::Value::Array(values) => {
+                        (values.iter().map(toml_value_text).collect(), true)
+                    }
+                    value => (vec![toml_value_text(value)], false),
+                };
+                Property::new_parse(
+                    name.to_string(),
+                    !is_multi,
+                    &vals,
+                    super::TextMode::Zk,
+                    file_dir,
+                )
+            })
+            .collect();
+        let rest = rest.strip_prefix('\r').unwrap_or(rest);
+        return Ok(Some((DocumentComponent::Frontmatter(props), rest)));
+    }
+    if trimmed.starts_with('{') {
+        let mut stream =
+            serde_json::Deserializer::from_str(trimmed).into_iter::<serde_json::Value>();
+        if let Some(Ok(serde_json::Value::Object(map))) = stream.next() {
+            let props = map
+                .iter()
+                .map(|(name, value)| {
+                    let (vals, is_multi) = match value {
+                        serde_json::Value::Array(values) => {
+                            (values.iter().map(json_value_text).collect(), true)
+                        }
+                        value => (vec![json_value_text(value)], false),
+                    };
+                    Property::new_parse(
+                        name.to_string(),
+                        !is_multi,
+                        &vals,
+                        super::TextMode::Zk,
+                        file_dir,
+                    )
+                })
+                .collect();
+            let rest = &trimmed[stream.byte_offset()..];
+            return Ok(Some((DocumentComponent::Frontmatter(props), rest)));
+        }
+    }
+    Ok(None)
+}
+
+fn toml_value_text(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.to_string(),
+        v => v.to_string(),
+    }
+}
+
+fn json_value_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// returns the length of the rest of an inline code span whose first backtick was just consumed,
+/// including the closing backticks. Code spans do not extend over blank lines
+fn inline_code_len(remainder: &str) -> Option<usize> {
+    let ticks = 1 + remainder.chars().take_while(|c| *c == '`').count();
+    let code = &remainder[ticks - 1..];
+    let end = code.find(&"`".repeat(ticks))?;
+    if code[..end].contains("\n\n") {
+        return None;
+    }
+    Some(ticks - 1 + end + ticks)
+}
+
 fn construct_error_details(lexer: &Lexer<'_, ZkToken>) -> String {
     let orig_slice = lexer.slice();
     let slice = orig_slice.escape_default().to_string();
@@ -483,11 +729,35 @@ fn consume_tokens(lexer: &mut Lexer<'_, ZkToken>) -> Result<()> {
 fn parse_adnote(
     lexer: &mut Lexer<'_, ZkToken>,
     file_dir: &Option<PathBuf>,
+    strict: bool,
+    best_effort: bool,
 ) -> Result<DocumentComponent> {
     let mut text = String::new();
+    // lengths of the fenced blocks opened inside the admonition body
+    let mut open_fences: Vec<usize> = vec![];
     while let Some(Ok(token)) = lexer.next() {
         match token {
+            ZkToken::AdNoteStart if open_fences.is_empty() => {
+                open_fences.push(3);
+                text.push_str(lexer.slice());
+            }
             ZkToken::TripleBackQuote => {
+                let extra = lexer.remainder().chars().take_while(|c| *c == '`').count();
+                lexer.bump(extra);
+                let fence_len = 3 + extra;
+                let rest_of_line = lexer.remainder().split('\n').next().unwrap_or_default();
+                let is_bare = rest_of_line.trim().is_empty();
+                if let Some(open_len) = open_fences.last() {
+                    if is_bare && fence_len >= *open_len {
+                        open_fences.pop();
+                    }
+                    text.push_str(lexer.slice());
+                    continue;
+                } else if !is_bare {
+                    open_fences.push(fence_len);
+                    text.push_str(lexer.slice());
+                    continue;
+                }
                 let text = text.trim_start_matches("\n").trim_end_matches("\n");
                 let mut properties = HashMap::new();
                 let mut body_text = String::new();
@@ -506,7 +776,7 @@ fn parse_adnote(
                         body_text.push_str(line);
                     }
                 }
-                let pd = parse_zk_text(&body_text, file_dir)?;
+                let pd = parse_substituted_zk_text(&body_text, file_dir, strict, best_effort)?;
                 return Ok(DocumentComponent::Admonition(
                     pd.into_components(),
                     properties,
@@ -548,20 +818,7 @@ fn parse_file_link(
     while let Some(Ok(token)) = lexer.next() {
         match token {
             ClosingDoubleBraces => {
-                let name = name.trim().to_string();
-                let mut mf = MentionedFile::FileName(name.clone());
-                if let Some(dir) = file_dir {
-                    let file = dir.join(&name);
-                    if file.exists() {
-                        let file = file.canonicalize()?;
-                        mf = MentionedFile::FilePath(file);
-                    }
-                    let Ok(file) = PathBuf::from_str(&name);
-
-                    if file.exists() {
-                        mf = MentionedFile::FilePath(file.canonicalize()?);
-                    }
-                }
+                let mf = resolve_link_name(name.trim(), file_dir)?;
                 return Ok((mf, section, rename));
             }
             SingleHash => {
@@ -766,11 +1023,14 @@ fn test_multi_property() {
     use crate::document_component::PropValue;
     let text = "property::= [test]";
     let res = parse_zk_text(text, &None);
-    let prop = DocumentComponent::Properties(vec![Property::new(
-        "property".to_string(),
-        false,
-        vec![PropValue::String("test".to_string())],
-    )]);
+    let prop = DocumentComponent::Properties(
+        vec![Property::new(
+            "property".to_string(),
+            false,
+            vec![PropValue::String("test".to_string())],
+        )],
+        PropertyScope::Block,
+    );
     debug!("final parse: {res:?}");
     if let Ok(pd) = res {
         let expected = ParsedDocument::ParsedText(vec![prop]);
@@ -786,8 +1046,10 @@ fn test_multi_property() {
 fn test_multi_property_empty() {
     let text = "property::= []";
     let res = parse_zk_text(text, &None);
-    let prop =
-        DocumentComponent::Properties(vec![Property::new("property".to_string(), false, vec![])]);
+    let prop = DocumentComponent::Properties(
+        vec![Property::new("property".to_string(), false, vec![])],
+        PropertyScope::Block,
+    );
     debug!("final parse: {res:?}");
     if let Ok(pd) = res {
         let expected = ParsedDocument::ParsedText(vec![prop]);
@@ -805,11 +1067,14 @@ fn test_multi_property_single_char() {
     use crate::document_component::PropValue;
     let text = "p ::= [a]";
     let res = parse_zk_text(text, &None);
-    let prop = DocumentComponent::Properties(vec![Property::new(
-        "p".to_string(),
-        false,
-        vec![PropValue::String("a".to_string())],
-    )]);
+    let prop = DocumentComponent::Properties(
+        vec![Property::new(
+            "p".to_string(),
+            false,
+            vec![PropValue::String("a".to_string())],
+        )],
+        PropertyScope::Block,
+    );
     debug!("final parse: {res:?}");
     if let Ok(pd) = res {
         let expected = ParsedDocument::ParsedText(vec![prop]);
@@ -826,15 +1091,18 @@ fn test_single_property_file_name() {
     use crate::document_component::PropValue;
     let text = "property::= [test](../test.md)";
     let res = parse_zk_text(text, &None);
-    let prop = DocumentComponent::Properties(vec![Property::new(
-        "property".to_string(),
-        true,
-        vec![PropValue::FileLink(
-            MentionedFile::FileName("../test.md".to_string()),
-            None,
-            Some("test".to_string()),
+    let prop = DocumentComponent::Properties(
+        vec![Property::new(
+            "property".to_string(),
+            true,
+            vec![PropValue::FileLink(
+                MentionedFile::FileName("../test.md".to_string()),
+                None,
+                Some("test".to_string()),
+            )],
         )],
-    )]);
+        PropertyScope::Block,
+    );
     debug!("final parse: {res:?}");
     if let Ok(pd) = res {
         let expected = ParsedDocument::ParsedText(vec![prop]);
@@ -852,15 +1120,18 @@ fn test_multi_property_file_name() {
     use crate::document_component::PropValue;
     let text = "property::= [[test](../test.md)]";
     let res = parse_zk_text(text, &None);
-    let prop = DocumentComponent::Properties(vec![Property::new(
-        "property".to_string(),
-        false,
-        vec![PropValue::FileLink(
-            MentionedFile::FileName("../test.md".to_string()),
-            None,
-            Some("test".to_string()),
+    let prop = DocumentComponent::Properties(
+        vec![Property::new(
+            "property".to_string(),
+            false,
+            vec![PropValue::FileLink(
+                MentionedFile::FileName("../test.md".to_string()),
+                None,
+                Some("test".to_string()),
+            )],
         )],
-    )]);
+        PropertyScope::Block,
+    );
     debug!("final parse: {res:?}");
     if let Ok(pd) = res {
         let expected = ParsedDocument::ParsedText(vec![prop]);
@@ -878,11 +1149,14 @@ fn test_property_text() {
     use crate::document_component::PropValue;
     let text = "property ::= value";
     let res = parse_zk_text(text, &None);
-    let prop = DocumentComponent::Properties(vec![Property::new(
-        "property".to_string(),
-        true,
-        vec![PropValue::String("value".to_string())],
-    )]);
+    let prop = DocumentComponent::Properties(
+        vec![Property::new(
+            "property".to_string(),
+            true,
+            vec![PropValue::String("value".to_string())],
+        )],
+        PropertyScope::Block,
+    );
     debug!("final parse: {res:?}");
     if let Ok(pd) = res {
         let expected = ParsedDocument::ParsedText(vec![prop]);
@@ -938,3 +1212,220 @@ fn test_link_with_special() {
     let res = res.to_zk_text(&None);
     assert_eq!(text, res);
 }
+
+#[test]
+fn test_thematic_break() {
+    let text = "first paragraph\n\n---\n\nsecond paragraph";
+    let res = parse_zk_text(text, &None).unwrap();
+    let expected = ParsedDocument::ParsedText(vec![
+        DocumentComponent::Text("first paragraph\n\n".to_string()),
+        DocumentComponent::ThematicBreak("---".to_string()),
+        DocumentComponent::Text("\n\nsecond paragraph".to_string()),
+    ]);
+    assert_eq!(res, expected);
+    let res = res.to_zk_text(&None);
+    assert_eq!(text, res);
+    // the other markers are kept as written
+    for marker in ["***", "___", "-----"] {
+        let text = format!("first paragraph\n\n{marker}\n\nsecond paragraph");
+        let res = parse_zk_text(&text, &None).unwrap();
+        assert_eq!(res.to_zk_text(&None), text);
+    }
+}
+
+#[test]
+fn test_toml_and_json_frontmatter() {
+    let yaml = parse_zk_text("---\ntitle: note\ntags: [a, b]\n---\n\n# note", &None).unwrap();
+    let toml = parse_zk_text(
+        "+++\ntitle = \"note\"\ntags = [\"a\", \"b\"]\n+++\n\n# note",
+        &None,
+    )
+    .unwrap();
+    let json = parse_zk_text(
+        "{\n  \"title\": \"note\",\n  \"tags\": [\"a\", \"b\"]\n}\n\n# note",
+        &None,
+    )
+    .unwrap();
+    let frontmatter = |pd: &ParsedDocument| match pd.components().first() {
+        Some(DocumentComponent::Frontmatter(props)) => props.clone(),
+        c => panic!("expected frontmatter, got {c:?}"),
+    };
+    assert_eq!(frontmatter(&toml), frontmatter(&yaml));
+    assert_eq!(frontmatter(&json), frontmatter(&yaml));
+    assert_eq!(toml.to_zk_text(&None), yaml.to_zk_text(&None));
+    assert_eq!(json.to_zk_text(&None), yaml.to_zk_text(&None));
+
+    let file_info =
+        crate::document_component::FileInfo::try_new("note.md".into(), None, None, None)
+            .unwrap()
+            .with_frontmatter_format(super::FrontmatterFormat::Toml);
+    assert_eq!(
+        yaml.to_zk_text(&Some(file_info)),
+        "+++\ntitle = \"note\"\ntags = [\"a\", \"b\"]\n+++\n\n# note"
+    );
+}
+
+#[test]
+fn test_adnote_with_code_block() {
+    let text =
+        "```ad-note\ntitle: Example\nsome text\n```python\nx = 1\n```\nmore text\n```\n\nafter";
+    let res = parse_zk_text(text, &None).unwrap();
+    let admonitions: Vec<&DocumentComponent> = res
+        .components()
+        .iter()
+        .filter(|c| matches!(c, DocumentComponent::Admonition(_, _)))
+        .collect();
+    assert_eq!(admonitions.len(), 1);
+    assert_eq!(res.to_zk_text(&None), text);
+}
+
+#[test]
+fn test_strict_mode() {
+    let text = "# Note\nsee [[other note]]";
+    assert!(parse_zk_text(text, &None).is_ok());
+    assert!(parse_zk_text_with(text, &None, true, false, &[]).is_err());
+    assert!(parse_zk_text_with("# Note\n- a [link](other.md)", &None, true, false, &[]).is_ok());
+
+    // in best effort mode, only the failing block inside an admonition is kept as text
+    let text = "before\n\n```ad-note\ntitle: t\nsee [[other note]]\n```";
+    let pd = parse_zk_text_with(text, &None, true, true, &[]).unwrap();
+    let admonitions =
+        pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Admonition(..)));
+    let [DocumentComponent::Admonition(body, props)] = &admonitions[..] else {
+        panic!("expected an admonition, got {pd:?}");
+    };
+    assert_eq!(props.get("title"), Some(&"t".to_string()));
+    assert_eq!(
+        body,
+        &vec![DocumentComponent::Text("see [[other note]]".to_string())]
+    );
+}
+
+#[test]
+fn test_inline_tag() {
+    use DocumentComponent::*;
+    let text = "see #graph-theory here";
+    let pd = parse_zk_text(text, &None).unwrap();
+    let expected = ParsedDocument::ParsedText(vec![
+        Text("see ".to_string()),
+        Tag("graph-theory".to_string()),
+        Text(" here".to_string()),
+    ]);
+    assert_eq!(pd, expected);
+    assert_eq!(pd.to_zk_text(&None), text);
+
+    let pd = parse_zk_text("issue #1 and a#b", &None).unwrap();
+    assert!(
+        pd.get_all_document_components(&|c| matches!(c, Tag(_)))
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_local_and_external_links() {
+    use DocumentComponent::*;
+    let text = "see [the note](note.md) and [the site](https://example.com/a?b=c)";
+    let pd = parse_zk_text(text, &None).unwrap();
+    let expected = ParsedDocument::ParsedText(vec![
+        Text("see ".to_string()),
+        FileLink(
+            MentionedFile::FileName("note.md".to_string()),
+            None,
+            Some("the note".to_string()),
+        ),
+        Text(" and ".to_string()),
+        Url(
+            "https://example.com/a?b=c".to_string(),
+            Some("the site".to_string()),
+        ),
+    ]);
+    assert_eq!(pd, expected);
+    assert_eq!(pd.to_zk_text(&None), text);
+    // urls without a name are their own link text
+    let pd = ParsedDocument::ParsedText(vec![Url("https://example.com".to_string(), None)]);
+    assert_eq!(
+        pd.to_zk_text(&None),
+        "[https://example.com](https://example.com)"
+    );
+    assert!(is_external_url("mailto:me@example.com"));
+    assert!(!is_external_url("notes/https.md"));
+}
+
+#[test]
+fn test_pipe_in_inline_code() {
+    use DocumentComponent::*;
+    let text = "use `a|b` with [the note](note.md), `[x|y](y.md)` and ``a`|`b``";
+    let pd = parse_zk_text(text, &None).unwrap();
+    let expected = ParsedDocument::ParsedText(vec![
+        Text("use `a|b` with ".to_string()),
+        FileLink(
+            MentionedFile::FileName("note.md".to_string()),
+            None,
+            Some("the note".to_string()),
+        ),
+        Text(", `[x|y](y.md)` and ``a`|`b``".to_string()),
+    ]);
+    assert_eq!(pd, expected);
+    assert_eq!(pd.to_zk_text(&None), text);
+    assert!(parse_zk_text_with("see `[[x|y]]`", &None, true, false, &[]).is_ok());
+}
+
+#[test]
+fn test_delimiters_in_property_values() {
+    let text = "code ::= a::b\nescaped ::= x \\::= y";
+    let pd = parse_zk_text(text, &None).unwrap();
+    let props = pd.get_all_document_components(&|c| matches!(c, DocumentComponent::Properties(..)));
+    assert_eq!(
+        props,
+        vec![
+            DocumentComponent::Properties(
+                vec![Property::new(
+                    "code".to_string(),
+                    true,
+                    vec![crate::document_component::PropValue::String(
+                        "a::b".to_string()
+                    )]
+                )],
+                PropertyScope::Block
+            ),
+            DocumentComponent::Properties(
+                vec![Property::new(
+                    "escaped".to_string(),
+                    true,
+                    vec![crate::document_component::PropValue::String(
+                        "x \\::= y".to_string()
+                    )]
+                )],
+                PropertyScope::Block
+            ),
+        ]
+    );
+    assert_eq!(pd.to_zk_text(&None), text);
+}
+
+#[test]
+fn test_inline_field() {
+    use DocumentComponent::*;
+    let pd = parse_zk_text("The project (status:: active) is (not a field) late", &None).unwrap();
+    assert_eq!(
+        pd.components(),
+        &[
+            Text("The project ".to_string()),
+            InlineField("status".to_string(), "active".to_string()),
+            Text(" is (not a field) late".to_string()),
+        ]
+    );
+    assert!(pd.has_field("status", "active"));
+    assert!(!pd.has_field("status", "done"));
+    assert_eq!(
+        pd.to_zk_text(&None),
+        "The project (status:: active) is (not a field) late"
+    );
+    assert_eq!(
+        pd.to_logseq_text(&None),
+        "- The project (status:: active) is (not a field) late"
+    );
+
+    let pd = parse_zk_text("- reviewed (url::)", &None).unwrap();
+    assert_eq!(pd.to_zk_text(&None), "- reviewed (url::)");
+}
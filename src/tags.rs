@@ -0,0 +1,366 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use regex::{Captures, Regex};
+
+use crate::{
+    document_component::{DocumentComponent, MentionedFile, ParsedDocument, PropValue},
+    parsing::{TagStyle, TextMode, parse_file},
+    util::{files_in_tree, write_atomic},
+};
+
+/// counts the tags in the `tags` properties and the inline `#tags` of all notes in the tree.
+/// If prefix is set, only the tags starting with it are counted
+pub fn collect_tags<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    max_depth: Option<usize>,
+    prefix: Option<&str>,
+) -> Result<BTreeMap<String, usize>> {
+    let mut res = BTreeMap::new();
+    for f in files_in_tree(root_dir, &Some(vec!["md"]), max_depth)? {
+        let pd = parse_file(&f, mode).context(format!("Failed to parse {f:?}"))?;
+        tags_in_document(&pd)
+            .into_iter()
+            .filter(|t| prefix.is_none_or(|prefix| t.starts_with(prefix)))
+            .for_each(|t| *res.entry(t).or_insert(0) += 1);
+    }
+    Ok(res)
+}
+
+/// `#tag` or `#[[multi word tag]]`, the tag is in the first or second group. Purely numeric tags
+/// like `#1` are ignored
+fn inline_tag_re() -> Regex {
+    Regex::new(r"(?:^|\s)#(?:\[\[([^\]]+)\]\]|([\p{L}\p{N}_/\-]*[\p{L}_/\-][\p{L}\p{N}_/\-]*))")
+        .unwrap()
+}
+
+/// the inline `#tags` of the note, without `#`
+pub(crate) fn inline_tags_in_document(pd: &ParsedDocument) -> Vec<String> {
+    let inline_tag_re = inline_tag_re();
+    let mut res = vec![];
+    pd.get_all_document_components(&|dc| {
+        matches!(dc, DocumentComponent::Text(_) | DocumentComponent::Tag(_))
+    })
+    .iter()
+    .for_each(|dc| match dc {
+        DocumentComponent::Text(text) => {
+            inline_tag_re.captures_iter(text).for_each(|c| {
+                if let Some(tag) = c.get(1).or_else(|| c.get(2)) {
+                    res.push(tag.as_str().to_string());
+                }
+            });
+        }
+        DocumentComponent::Tag(tag) => res.push(tag.to_string()),
+        _ => {}
+    });
+    res
+}
+
+/// tags of the `tags` properties and the inline `#tags` of the note
+pub(crate) fn tags_in_document(pd: &ParsedDocument) -> Vec<String> {
+    let mut res = inline_tags_in_document(pd);
+    pd.get_all_document_components(&|dc| {
+        matches!(
+            dc,
+            DocumentComponent::Properties(..) | DocumentComponent::Frontmatter(_)
+        )
+    })
+    .iter()
+    .for_each(|dc| match dc {
+        DocumentComponent::Properties(props, _) | DocumentComponent::Frontmatter(props) => {
+            props
+                .iter()
+                .filter(|p| p.has_name("tags"))
+                .flat_map(|p| p.values.iter())
+                .for_each(|v| {
+                    let tag = match v {
+                        PropValue::String(s) => TagStyle::Bare.render(s),
+                        PropValue::Number(_) | PropValue::Date(_) => {
+                            TagStyle::Bare.render(&v.to_mode_text(&TextMode::Zk, &None))
+                        }
+                        PropValue::FileLink(MentionedFile::FileName(name), _, _) => {
+                            name.trim_end_matches(".md").to_string()
+                        }
+                        PropValue::FileLink(MentionedFile::FilePath(path), _, _) => path
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                    };
+                    if !tag.is_empty() {
+                        res.push(tag);
+                    }
+                });
+        }
+        _ => {}
+    });
+    res
+}
+
+/// renames a tag, or the prefix of all tags starting with it, e.g. `project/` to `area/`
+#[derive(Debug, Clone)]
+pub struct TagRename {
+    pub from: String,
+    pub to: String,
+    pub prefix: bool,
+}
+
+impl TagRename {
+    /// the new name of tag, given without `#`, or None if it is not renamed
+    pub fn apply(&self, tag: &str) -> Option<String> {
+        if self.prefix {
+            tag.strip_prefix(&self.from)
+                .map(|rest| format!("{}{rest}", self.to))
+        } else {
+            (tag == self.from).then(|| self.to.clone())
+        }
+    }
+
+    /// renames the tags in a comma separated tags value, keeping the `#` of hash style tags and
+    /// the brackets of `[[page]]` tags
+    fn apply_to_value(&self, value: &str) -> Option<String> {
+        let mut renamed = false;
+        let tags: Vec<String> = value
+            .split(',')
+            .map(|t| match self.apply(&TagStyle::Bare.render(t)) {
+                Some(new) => {
+                    renamed = true;
+                    if t.trim().starts_with('#') {
+                        TagStyle::Hash.render(&new)
+                    } else if t.trim().starts_with("[[") {
+                        format!("[[{new}]]")
+                    } else {
+                        new
+                    }
+                }
+                None => t.trim().to_string(),
+            })
+            .collect();
+        renamed.then(|| tags.join(", "))
+    }
+
+    /// renames the inline tags of a line, inline code is kept as it is
+    fn apply_to_inline_tags(&self, line: &str, inline_tag_re: &Regex) -> String {
+        line.split('`')
+            .enumerate()
+            .map(|(i, part)| {
+                if i % 2 == 1 {
+                    return part.to_string();
+                }
+                inline_tag_re
+                    .replace_all(part, |c: &Captures| {
+                        let whole = c.get(0).unwrap();
+                        let tag = c.get(1).or_else(|| c.get(2)).unwrap();
+                        match self.apply(tag.as_str()) {
+                            Some(new) => format!(
+                                "{}{new}{}",
+                                &part[whole.start()..tag.start()],
+                                &part[tag.end()..whole.end()]
+                            ),
+                            None => whole.as_str().to_string(),
+                        }
+                    })
+                    .to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("`")
+    }
+
+    /// renames the tags in the `tags` properties and frontmatter entries and the inline tags of a
+    /// note. Only the tags are rewritten, all other text is kept as it is. Returns None if no tag
+    /// was renamed
+    pub fn apply_to_text(&self, text: &str) -> Option<String> {
+        let inline_tag_re = inline_tag_re();
+        let property_re = Regex::new(r"^(\s*(?:- )?tags::[ \t]*)(.*?)(\s*)$").unwrap();
+        let frontmatter_re = Regex::new(r"^(tags:[ \t]*)(.*?)(\s*)$").unwrap();
+        let item_re = Regex::new(r"^(\s*-[ \t]+)(.*?)(\s*)$").unwrap();
+        let mut in_frontmatter = false;
+        let mut in_tags_list = false;
+        let mut in_fence = false;
+        let mut res = String::new();
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let trimmed = line.trim();
+            let new_line = if i == 0 && trimmed == "---" {
+                in_frontmatter = true;
+                line.to_string()
+            } else if in_frontmatter {
+                if trimmed == "---" {
+                    in_frontmatter = false;
+                    line.to_string()
+                } else if let Some(c) = frontmatter_re.captures(line) {
+                    let value = &c[2];
+                    in_tags_list = value.is_empty();
+                    let renamed = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                        Some(inner) => self.apply_to_value(inner).map(|v| format!("[{v}]")),
+                        None => self.apply_to_value(value),
+                    };
+                    renamed.map_or(line.to_string(), |v| format!("{}{v}{}", &c[1], &c[3]))
+                } else if in_tags_list && let Some(c) = item_re.captures(line) {
+                    self.apply_to_value(&c[2])
+                        .map_or(line.to_string(), |v| format!("{}{v}{}", &c[1], &c[3]))
+                } else {
+                    in_tags_list = false;
+                    line.to_string()
+                }
+            } else if trimmed.starts_with("```") {
+                in_fence = !in_fence;
+                line.to_string()
+            } else if in_fence {
+                line.to_string()
+            } else if let Some(c) = property_re.captures(line) {
+                self.apply_to_value(&c[2])
+                    .map_or(line.to_string(), |v| format!("{}{v}{}", &c[1], &c[3]))
+            } else {
+                self.apply_to_inline_tags(line, &inline_tag_re)
+            };
+            res.push_str(&new_line);
+        }
+        (res != text).then_some(res)
+    }
+}
+
+/// renames tags in all notes of the tree in place. Returns the files that were changed.
+/// Only the tags are rewritten, the rest of the notes is kept as it is
+pub fn retag_tree<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    rename: &TagRename,
+    max_depth: Option<usize>,
+) -> Result<Vec<PathBuf>> {
+    if *mode == TextMode::Obsidian {
+        bail!("Retagging obsidian notes is not supported yet!");
+    }
+    let mut changed = vec![];
+    for f in files_in_tree(root_dir, &Some(vec!["md"]), max_depth)? {
+        let text = std::fs::read_to_string(&f).context(format!("Failed to read {f:?}"))?;
+        if let Some(new) = rename.apply_to_text(&text) {
+            write_atomic(&f, &new)?;
+            changed.push(f);
+        }
+    }
+    Ok(changed)
+}
+
+pub fn print_tags<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    max_depth: Option<usize>,
+    prefix: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    let tags = collect_tags(root_dir, mode, max_depth, prefix)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tags)?);
+    } else {
+        let mut tags: Vec<(String, usize)> = tags.into_iter().collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        tags.iter()
+            .for_each(|(tag, count)| println!("{count:>6} {tag}"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_collect_tags() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("a.md"),
+        "---\ntags: [reading, fitness]\n---\n\n# A\nsome #idea and #reading",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("b.md"),
+        "---\ntags: [reading]\n---\n\n# B\n- issue #1",
+    )
+    .unwrap();
+
+    let tags = collect_tags(&dir, &TextMode::Zk, None, None).unwrap();
+    let expected = BTreeMap::from([
+        ("fitness".to_string(), 1),
+        ("idea".to_string(), 1),
+        ("reading".to_string(), 3),
+    ]);
+    assert_eq!(tags, expected);
+    let tags = collect_tags(&dir, &TextMode::Zk, None, Some("re")).unwrap();
+    assert_eq!(tags, BTreeMap::from([("reading".to_string(), 3)]));
+}
+
+#[test]
+fn test_retag_prefix() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (zk, logseq) = (dir.join("zk"), dir.join("logseq"));
+    std::fs::create_dir_all(&zk).unwrap();
+    std::fs::create_dir_all(&logseq).unwrap();
+    std::fs::write(
+        zk.join("a.md"),
+        "---\ntags: [project/pkmt, reading]\n---\n\n# A\nsee #project/garden and #projects",
+    )
+    .unwrap();
+    std::fs::write(zk.join("b.md"), "---\ntags: [reading]\n---\n\n# B").unwrap();
+    std::fs::write(
+        logseq.join("c.md"),
+        "- c\n  tags:: #project/pkmt, #[[project/x y]]",
+    )
+    .unwrap();
+
+    let rename = TagRename {
+        from: "project/".to_string(),
+        to: "area/".to_string(),
+        prefix: true,
+    };
+    let changed = retag_tree(&zk, &TextMode::Zk, &rename, None).unwrap();
+    assert_eq!(changed, vec![zk.join("a.md")]);
+    assert_eq!(
+        std::fs::read_to_string(zk.join("a.md")).unwrap(),
+        "---\ntags: [area/pkmt, reading]\n---\n\n# A\nsee #area/garden and #projects"
+    );
+    let changed = retag_tree(&logseq, &TextMode::LogSeq, &rename, None).unwrap();
+    assert_eq!(changed, vec![logseq.join("c.md")]);
+    assert_eq!(
+        std::fs::read_to_string(logseq.join("c.md")).unwrap(),
+        "- c\n  tags:: #area/pkmt, #[[area/x y]]"
+    );
+    assert_eq!(
+        collect_tags(&zk, &TextMode::Zk, None, Some("project/")).unwrap(),
+        BTreeMap::new()
+    );
+    assert_eq!(
+        collect_tags(&zk, &TextMode::Zk, None, Some("area/")).unwrap(),
+        BTreeMap::from([("area/garden".to_string(), 1), ("area/pkmt".to_string(), 1)])
+    );
+}
+
+#[test]
+fn test_retag_keeps_the_rest_of_the_note() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let text = "---\ntitle: A\ntags:\n  - project/pkmt\n  - reading\n---\n\n\n#  A\n\
+        see #project/x,   `#project/code` and [[project/page]]\n\n\n\
+        ```sh\necho #project/in-code\n```\n   - odd  indent #project/y\n";
+    std::fs::write(dir.join("a.md"), text).unwrap();
+    let rename = TagRename {
+        from: "project/".to_string(),
+        to: "area/".to_string(),
+        prefix: true,
+    };
+    let changed = retag_tree(&dir, &TextMode::Zk, &rename, None).unwrap();
+    assert_eq!(changed, vec![dir.join("a.md")]);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("a.md")).unwrap(),
+        text.replace("- project/pkmt", "- area/pkmt")
+            .replace("#project/x", "#area/x")
+            .replace("#project/y", "#area/y")
+    );
+    // nothing to rename, the note is not written
+    assert!(
+        retag_tree(&dir, &TextMode::Zk, &rename, None)
+            .unwrap()
+            .is_empty()
+    );
+}
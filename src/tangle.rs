@@ -0,0 +1,143 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    document_component::{DocumentComponent, ParsedDocument},
+    parsing::{TextMode, parse_file},
+};
+
+/// file extensions of code block languages, other languages are used as extension directly
+const LANGUAGE_EXTENSIONS: [(&str, &str); 16] = [
+    ("bash", "sh"),
+    ("c++", "cpp"),
+    ("cpp", "cpp"),
+    ("csharp", "cs"),
+    ("haskell", "hs"),
+    ("javascript", "js"),
+    ("julia", "jl"),
+    ("kotlin", "kt"),
+    ("markdown", "md"),
+    ("python", "py"),
+    ("ruby", "rb"),
+    ("rust", "rs"),
+    ("shell", "sh"),
+    ("text", "txt"),
+    ("typescript", "ts"),
+    ("yaml", "yml"),
+];
+
+fn extension_of(lang: &str) -> String {
+    let lang = lang.to_lowercase();
+    LANGUAGE_EXTENSIONS
+        .iter()
+        .find(|(l, _)| *l == lang)
+        .map(|(_, ext)| ext.to_string())
+        .unwrap_or(lang)
+}
+
+/// returns (language, suggested file name, body) of all code blocks in pd.
+/// A first line of the form `# filename: name` sets the file name and is removed from the body,
+/// otherwise the name is `block_<n>` with the extension of the language.
+pub fn extract_code_blocks(pd: &ParsedDocument) -> Vec<(Option<String>, String, String)> {
+    pd.get_all_document_components(&|dc| matches!(dc, DocumentComponent::CodeBlock(..)))
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, dc)| match dc {
+            DocumentComponent::CodeBlock(code, lang) => {
+                let lang = lang
+                    .as_ref()
+                    .and_then(|l| l.split_whitespace().next())
+                    .map(|l| l.to_string());
+                let (first_line, rest) = code.split_once('\n').unwrap_or((&code, ""));
+                if let Some(name) = first_line.trim().strip_prefix("# filename:")
+                    && !name.trim().is_empty()
+                {
+                    Some((lang, name.trim().to_string(), rest.to_string()))
+                } else {
+                    let ext = lang.as_deref().map_or("txt".to_string(), extension_of);
+                    Some((lang, format!("block_{}.{ext}", i + 1), code))
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// writes the code blocks of file that have a language tag to out_dir. Blocks with the same file
+/// name are concatenated. Returns the written files.
+pub fn tangle<T: AsRef<Path>>(file: T, mode: &TextMode, out_dir: T) -> Result<Vec<PathBuf>> {
+    let file = file.as_ref();
+    let out_dir = out_dir.as_ref();
+    let pd = parse_file(&file.to_path_buf(), mode).context(format!("Failed to parse {file:?}"))?;
+    let mut files: BTreeMap<String, String> = BTreeMap::new();
+    extract_code_blocks(&pd)
+        .into_iter()
+        .filter(|(lang, _, _)| lang.is_some())
+        .for_each(|(_, name, body)| {
+            let text = files.entry(name).or_default();
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&body);
+            text.push('\n');
+        });
+    if !files.is_empty() {
+        std::fs::create_dir_all(out_dir).context(format!("Could not create {out_dir:?}"))?;
+    }
+    files
+        .into_iter()
+        .map(|(name, text)| {
+            if Path::new(&name)
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+            {
+                bail!("Code block file name {name:?} is not a path inside the output directory");
+            }
+            let target = out_dir.join(name);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).context(format!("Could not create {parent:?}"))?;
+            }
+            std::fs::write(&target, text).context(format!("Could not write {target:?}"))?;
+            Ok(target)
+        })
+        .collect()
+}
+
+#[test]
+fn test_tangle_python_block() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    let note = dir.join("note.md");
+    std::fs::write(
+        &note,
+        "- a script\n  ```python\n  print(\"hi\")\n  ```\n- untagged\n  ```\n  text\n  ```",
+    )
+    .unwrap();
+
+    let files = tangle(&note, &TextMode::LogSeq, &dir.join("code")).unwrap();
+    assert_eq!(files, vec![dir.join("code").join("block_1.py")]);
+    assert_eq!(
+        std::fs::read_to_string(&files[0]).unwrap(),
+        "print(\"hi\")\n"
+    );
+
+    let pd = crate::parsing::parse_text(
+        "- main\n  ```rust\n  # filename: src/main.rs\n  fn main() {}\n  ```",
+        &TextMode::LogSeq,
+        &None,
+    )
+    .unwrap();
+    assert_eq!(
+        extract_code_blocks(&pd),
+        vec![(
+            Some("rust".to_string()),
+            "src/main.rs".to_string(),
+            "fn main() {}".to_string()
+        )]
+    );
+}
@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    convert_config::ConvertConfig,
+    document_component::{FileInfo, dedupe_property_values, sort_properties},
+    parsing::{TextMode, parse_file},
+    util::files_in_tree,
+};
+
+/// sorts the properties of all notes in the tree in place, using the property order of the
+/// `pkmt-convert.toml` files. Repeated property values are removed if the config sets
+/// dedupe_property_values. Returns the files that were changed.
+pub fn tidy_tree<T: AsRef<Path>>(
+    root_dir: T,
+    mode: &TextMode,
+    max_depth: Option<usize>,
+) -> Result<Vec<PathBuf>> {
+    if *mode == TextMode::Obsidian {
+        bail!("Tidying obsidian notes is not supported yet!");
+    }
+    let root_dir = root_dir.as_ref().canonicalize()?;
+    let mut changed = vec![];
+    for f in files_in_tree(&root_dir, &Some(vec!["md"]), max_depth)? {
+        let config = ConvertConfig::resolve(&root_dir, f.parent().unwrap_or(&root_dir))?;
+        let mut pd = parse_file(&f, mode).context(format!("Failed to parse {f:?}"))?;
+        let file_info = Some(FileInfo::try_new(f.clone(), Some(f.clone()), None, None)?);
+        let before = pd.to_string(mode.clone(), &file_info);
+        if config.dedupe_property_values == Some(true) {
+            dedupe_property_values(&mut pd);
+        }
+        sort_properties(&mut pd, &config.property_order());
+        let after = pd.to_string(mode.clone(), &file_info);
+        if before != after {
+            std::fs::write(&f, after).context(format!("Failed to write to {f:?}"))?;
+            changed.push(f);
+        }
+    }
+    Ok(changed)
+}
+
+#[test]
+fn test_tidy_sorts_properties() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    let text = "---\nauthor: someone\ntags: [a, b]\ndate: 2024-01-01\n---\n\n# Note";
+    std::fs::write(dir.join("a.md"), text).unwrap();
+    std::fs::write(dir.join("sub").join("b.md"), text).unwrap();
+    std::fs::write(
+        dir.join("sub").join(ConvertConfig::FILE_NAME),
+        "property_order = [\"date\"]\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("sorted.md"), "---\ntags: [a]\n---\n\n# Sorted").unwrap();
+
+    let mut changed = tidy_tree(&dir, &TextMode::Zk, None).unwrap();
+    changed.sort();
+    let dir = dir.canonicalize().unwrap();
+    assert_eq!(
+        changed,
+        vec![dir.join("a.md"), dir.join("sub").join("b.md")]
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("a.md")).unwrap(),
+        "---\ntags: [a, b]\ndate: 2024-01-01\nauthor: someone\n---\n\n# Note"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("sub").join("b.md")).unwrap(),
+        "---\ndate: 2024-01-01\nauthor: someone\ntags: [a, b]\n---\n\n# Note"
+    );
+}
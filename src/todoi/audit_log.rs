@@ -0,0 +1,119 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// a note created by `pkmt todoi`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuditEntry {
+    /// rfc3339 timestamp of the creation
+    pub timestamp: String,
+    /// content of the todoist task
+    pub task: String,
+    /// template or task type the note was created from
+    pub template: String,
+    /// the created note, or the journal the task was added to
+    pub file: PathBuf,
+}
+
+impl AuditEntry {
+    pub fn new(task: &str, template: &str, file: &Path) -> Self {
+        AuditEntry {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            task: task.to_string(),
+            template: template.to_string(),
+            file: file.to_path_buf(),
+        }
+    }
+}
+
+impl std::fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} [{}] {:?}: {}",
+            self.timestamp, self.template, self.file, self.task
+        )
+    }
+}
+
+/// json lines file with one entry per created note
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        AuditLog { path }
+    }
+
+    pub fn default_path() -> PathBuf {
+        let dirs = directories::ProjectDirs::from("TF", "TF", "pkmt").unwrap();
+        dirs.data_local_dir().join("todoi_log.jsonl")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(format!("Failed to create {parent:?}"))?;
+        }
+        let line = serde_json::to_string(entry).context("Failed to serialize log entry")?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open the log {:?}", self.path))?;
+        writeln!(file, "{line}").context(format!("Failed to write to the log {:?}", self.path))
+    }
+
+    /// all entries, oldest first. Returns no entries if the log does not exist yet
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let text = std::fs::read_to_string(&self.path)
+            .context(format!("Failed to read the log {:?}", self.path))?;
+        text.lines()
+            .filter(|l| !l.trim().is_empty())
+            .enumerate()
+            .map(|(i, l)| {
+                serde_json::from_str(l).context(format!(
+                    "Failed to parse entry {} of the log {:?}",
+                    i + 1,
+                    self.path
+                ))
+            })
+            .collect()
+    }
+
+    /// the last count entries, oldest first
+    pub fn recent(&self, count: usize) -> Result<Vec<AuditEntry>> {
+        let entries = self.entries()?;
+        let skip = entries.len().saturating_sub(count);
+        Ok(entries.into_iter().skip(skip).collect())
+    }
+}
+
+#[test]
+fn test_audit_log() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let log = AuditLog::new(dir.join("log").join("todoi_log.jsonl"));
+    assert_eq!(log.entries().unwrap(), vec![]);
+
+    let first = AuditEntry::new("https://example.com/a", "article", &dir.join("a.md"));
+    let second = AuditEntry::new("https://example.com/b", "youtube", &dir.join("b.md"));
+    log.append(&first).unwrap();
+    log.append(&second).unwrap();
+
+    assert_eq!(log.entries().unwrap(), vec![first.clone(), second.clone()]);
+    assert_eq!(log.recent(1).unwrap(), vec![second]);
+    assert!(chrono::DateTime::parse_from_rfc3339(&first.timestamp).is_ok());
+}
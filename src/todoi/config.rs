@@ -1,9 +1,20 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local, Utc};
 use serde::{Deserialize, Serialize};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
+use crate::{parsing::LineEnding, util::SlugOptions};
+
+/// zk template directory relative to the notebook root unless configured otherwise
+pub const DEFAULT_TEMPLATE_DIR: &str = ".zk/templates";
+/// property created notes link back to their todoist task in
+pub const DEFAULT_TASK_URL_PROPERTY: &str = "todoist";
+
 #[derive(Deserialize, Zeroize, ZeroizeOnDrop)]
 pub struct Keys {
     pub yt_api_key: String,
@@ -29,20 +40,33 @@ impl Keys {
 pub struct Config {
     pub keys: Keys,
     tags: Tags,
+    pub settings: Settings,
 }
 
 impl Config {
     pub fn show_paths() {
         let tags_path = Tags::tags_config_path();
         let keys_file = Keys::keys_file().unwrap();
+        let settings_path = Settings::settings_path();
+
+        println!(
+            "tags file: {tags_path:?}\nkeys file: {keys_file:?}\nsettings file: {settings_path:?}"
+        );
+    }
 
-        println!("tags file: {tags_path:?}\nkeys file: {keys_file:?}");
+    pub fn new(keys: Keys, tags: Tags, settings: Settings) -> Self {
+        Config {
+            keys,
+            tags,
+            settings,
+        }
     }
 
     pub fn load() -> Result<Self> {
         let keys = Keys::parse()?;
         let tags = Tags::parse()?;
-        Ok(Config { keys, tags })
+        let settings = Settings::parse()?;
+        Ok(Config::new(keys, tags, settings))
     }
 
     pub fn get_url_tags(&self, url: &str) -> Vec<String> {
@@ -82,7 +106,8 @@ impl Config {
             .find(|ct| ct.channel == channel)
             .map(|ct| ct.tags.clone())
     }
-    pub fn get_keyword_tags(&self, text: &str) -> Vec<String> {
+    /// returns the tags of all keywords contained in text (case-insensitive)
+    pub fn get_keyword_tags_for(&self, text: &str) -> Vec<String> {
         self.tags
             .kw_tag
             .iter()
@@ -195,6 +220,207 @@ impl Tags {
     }
 }
 
+/// optional settings, all of which fall back to the default behaviour if the settings file is
+/// missing
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub zk_journal: JournalSettings,
+    #[serde(default)]
+    pub zk_note_date: NoteDateSettings,
+    #[serde(default)]
+    pub zk_hooks: HookSettings,
+    #[serde(default)]
+    pub zk_new: NoteCreationSettings,
+    #[serde(default)]
+    pub sbs: SbsSettings,
+    /// status set in notes created for a task type (`youtube`, `sbs`, `yt_playlist` or the
+    /// template name), other notes keep the status of the template
+    #[serde(default)]
+    pub initial_status: BTreeMap<String, String>,
+    /// path of the zk binary, if it is not on PATH
+    pub zk_binary: Option<PathBuf>,
+    /// directory of the zk templates, relative paths are resolved against the notebook root
+    pub zk_template_dir: Option<PathBuf>,
+    /// property of created notes that is filled with the link to the todoist task
+    pub task_url_property: Option<String>,
+    /// line terminator of the notes and journals that are written
+    pub line_ending: Option<LineEnding>,
+}
+
+impl Settings {
+    pub fn parse() -> Result<Self> {
+        let settings_path = Settings::settings_path();
+        if !settings_path.exists() {
+            return Ok(Settings::default());
+        }
+        let text = std::fs::read_to_string(&settings_path)
+            .context(format!("Failed to read settings file {settings_path:?}"))?
+            .replace("\r\n", "\n");
+        toml::from_str(&text).context("Failed to parse settings!")
+    }
+
+    fn settings_path() -> PathBuf {
+        let dirs = directories::ProjectDirs::from("TF", "TF", "pkmt").unwrap();
+        dirs.config_local_dir().join("settings.toml")
+    }
+
+    pub fn zk_binary(&self) -> PathBuf {
+        self.zk_binary
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("zk"))
+    }
+
+    pub fn task_url_property(&self) -> String {
+        self.task_url_property
+            .clone()
+            .unwrap_or_else(|| DEFAULT_TASK_URL_PROPERTY.to_string())
+    }
+
+    pub fn zk_template_dir(&self, root_dir: &Path) -> PathBuf {
+        root_dir.join(
+            self.zk_template_dir
+                .as_deref()
+                .unwrap_or(Path::new(DEFAULT_TEMPLATE_DIR)),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JournalSettings {
+    /// heading of the journal section new mentions are added to. If unset, mentions are appended
+    /// to the end of the journal
+    pub section: Option<String>,
+    /// whether the section should be created if the journal does not contain it yet
+    #[serde(default)]
+    pub create_section: bool,
+    /// level used when creating the section heading
+    #[serde(default = "JournalSettings::default_section_level")]
+    pub section_level: u16,
+    /// chrono format of the journal path relative to the notebook root, e.g.
+    /// `journal/%Y-%m-%d.md`. If unset, `zk daily-path` is used
+    pub date_format: Option<String>,
+}
+
+impl JournalSettings {
+    fn default_section_level() -> u16 {
+        2
+    }
+}
+
+impl Default for JournalSettings {
+    fn default() -> Self {
+        Self {
+            section: None,
+            create_section: false,
+            section_level: JournalSettings::default_section_level(),
+            date_format: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SbsSettings {
+    /// regex matching the suffix that is removed from the titles of Stronger by Science articles
+    pub title_suffix: String,
+}
+
+impl Default for SbsSettings {
+    fn default() -> Self {
+        Self {
+            title_suffix: r"\s*[\u{2022}|\-\u{2013}]\s*Stronger by Science".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HookSettings {
+    /// shell command run after a note was created, `{file}` is replaced by the path of the note
+    pub post_create: Option<String>,
+}
+
+/// how the file names of created notes are chosen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdScheme {
+    /// title turned into a file name, see `SlugOptions`
+    #[default]
+    Slug,
+    /// creation time like `20250102223000`
+    Timestamp,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct NoteCreationSettings {
+    /// create notes by filling in the template directly instead of running `zk new`
+    #[serde(default)]
+    pub native: bool,
+    #[serde(default)]
+    pub id_scheme: IdScheme,
+    /// file names of notes created with the slug id scheme and `pkmt new`
+    #[serde(default)]
+    pub slug: SlugOptions,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    #[default]
+    Now,
+    TaskCreated,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NoteDateSettings {
+    /// frontmatter property that is overwritten with the date. If unset, the date from the
+    /// template is kept
+    pub property: Option<String>,
+    /// chrono format of the date
+    #[serde(default = "NoteDateSettings::default_format")]
+    pub format: String,
+    #[serde(default)]
+    pub source: DateSource,
+    /// offset like `+02:00` the date is converted to. If unset, the local timezone is used
+    pub utc_offset: Option<String>,
+}
+
+impl NoteDateSettings {
+    fn default_format() -> String {
+        "%Y-%m-%d %H:%M:%S".to_string()
+    }
+
+    /// returns the formatted date for a note created from a task created at created_at.
+    /// Falls back to the current time if the task creation time is not available
+    pub fn stamp(&self, created_at: Option<&str>) -> Result<String> {
+        let time = match (self.source, created_at) {
+            (DateSource::TaskCreated, Some(created_at)) => DateTime::parse_from_rfc3339(created_at)
+                .context(format!("Failed to parse task creation time {created_at:?}"))?
+                .with_timezone(&Utc),
+            _ => Utc::now(),
+        };
+        let res = if let Some(offset) = &self.utc_offset {
+            let offset: FixedOffset = offset
+                .parse()
+                .context(format!("Invalid utc offset {offset:?}"))?;
+            time.with_timezone(&offset).format(&self.format).to_string()
+        } else {
+            time.with_timezone(&Local).format(&self.format).to_string()
+        };
+        Ok(res)
+    }
+}
+
+impl Default for NoteDateSettings {
+    fn default() -> Self {
+        Self {
+            property: None,
+            format: NoteDateSettings::default_format(),
+            source: DateSource::default(),
+            utc_offset: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ChannelTags {
     channel: String,
@@ -0,0 +1,81 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::todoist_api::TodoistTask;
+
+/// ids of the tasks that were handled in previous runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HandledTasks {
+    ids: BTreeSet<String>,
+}
+
+impl HandledTasks {
+    pub fn default_path() -> PathBuf {
+        let dirs = directories::ProjectDirs::from("TF", "TF", "pkmt").unwrap();
+        dirs.data_local_dir().join("todoi_handled.toml")
+    }
+
+    /// returns an empty set if the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(HandledTasks::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .context(format!("Failed to read handled tasks from {path:?}"))?;
+        toml::from_str(&text).context(format!("Failed to parse handled tasks in {path:?}"))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(format!("Failed to create {parent:?}"))?;
+        }
+        let text = toml::to_string(self).context("Failed to serialize handled tasks")?;
+        std::fs::write(path, text).context(format!("Failed to write handled tasks to {path:?}"))
+    }
+
+    pub fn contains(&self, task: &TodoistTask) -> bool {
+        self.ids.contains(&task.id)
+    }
+
+    /// splits tasks into the ones not handled before and the ones handled in a previous run
+    pub fn split_new(&self, tasks: Vec<TodoistTask>) -> (Vec<TodoistTask>, Vec<TodoistTask>) {
+        tasks.into_iter().partition(|t| !self.contains(t))
+    }
+
+    pub fn record(&mut self, tasks: &[TodoistTask]) {
+        self.ids.extend(tasks.iter().map(|t| t.id.clone()));
+    }
+}
+
+#[test]
+fn test_handled_task_is_skipped() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let path = dir.join("handled.toml");
+    let task = |id: &str| TodoistTask {
+        id: id.to_string(),
+        content: format!("task {id}"),
+        parent_id: None,
+        project_id: None,
+        labels: vec![],
+        priority: None,
+        due: None,
+        created_at: None,
+        url: None,
+    };
+
+    let mut handled = HandledTasks::load(&path).unwrap();
+    assert_eq!(handled, HandledTasks::default());
+    handled.record(&[task("1")]);
+    handled.save(&path).unwrap();
+
+    let handled = HandledTasks::load(&path).unwrap();
+    let (new_tasks, skipped) = handled.split_new(vec![task("1"), task("2")]);
+    assert_eq!(new_tasks, vec![task("2")]);
+    assert_eq!(skipped, vec![task("1")]);
+}
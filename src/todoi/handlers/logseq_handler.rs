@@ -1,18 +1,22 @@
 use crate::document_component::MentionedFile;
 use std::{
+    collections::BTreeMap,
     fmt::Debug,
     path::{Path, PathBuf},
     vec,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use tracing::info;
 
 use crate::todoi::{
-    TaskData, fill_all_props_le, get_list_elem_with_doc_elem, handlers::TaskDataHandler,
+    TaskData, TaskDetails, fill_all_props_le, get_list_elem_with_doc_elem,
+    handlers::{TaskDataHandler, append_raw},
+    set_status,
 };
 use crate::{
     document_component::{DocumentComponent, ListElem, ParsedDocument, PropValue},
-    parsing::logseq_parsing::parse_logseq_file,
+    parsing::{LineEnding, TextMode, logseq_parsing::parse_logseq_file},
 };
 
 #[derive(Debug)]
@@ -35,7 +39,7 @@ impl LogSeqTemplates {
     /// returns the list element containing the properties matching the template name
     pub fn get_template_comp(&self, template_name: &str) -> Option<ListElem> {
         get_list_elem_with_doc_elem(&self.templates_pd, &|elem| match elem {
-            DocumentComponent::Properties(props) => props.iter().any(|p| {
+            DocumentComponent::Properties(props, _) => props.iter().any(|p| {
                 p.has_name("template") && p.has_value(&PropValue::String(template_name.to_string()))
             }),
             _ => false,
@@ -46,20 +50,23 @@ impl LogSeqTemplates {
         let mut res = vec![];
         self.templates_pd
             .get_all_document_components(&|c: &DocumentComponent| match &c {
-                DocumentComponent::Properties(props) => {
+                DocumentComponent::Properties(props, _) => {
                     props.iter().any(|p| p.has_name("template"))
                 }
                 _ => false,
             })
             .iter()
             .for_each(|c| {
-                if let DocumentComponent::Properties(props) = &c
+                if let DocumentComponent::Properties(props, _) = &c
                     && let Some(p) = props.iter().find(|p| p.has_name("template"))
                 {
                     p.values.iter().for_each(|v| {
                         let tm = match v {
                             PropValue::FileLink(mf, _, _) => mf.to_string(),
                             PropValue::String(text) => text.to_string(),
+                            PropValue::Number(_) | PropValue::Date(_) => {
+                                v.to_mode_text(&TextMode::LogSeq, &None)
+                            }
                         };
                         res.push(tm);
                     });
@@ -74,17 +81,38 @@ pub struct LogSeqHandler {
     templates: LogSeqTemplates,
     todays_journal: ParsedDocument,
     todays_journal_file: PathBuf,
+    /// text of an existing journal that could not be parsed, new blocks are appended to it
+    unparsed_journal: Option<String>,
+    initial_status: BTreeMap<String, String>,
+    line_ending: LineEnding,
 }
 
 impl LogSeqHandler {
-    pub fn new(graph_root: PathBuf) -> Result<Self> {
+    pub fn new(graph_root: PathBuf, initial_status: BTreeMap<String, String>) -> Result<Self> {
         let today = chrono::offset::Local::now();
         let todays_journal_file = graph_root
             .join("journals")
             .join(today.format("%Y_%m_%d.md").to_string());
+        let mut unparsed_journal = None;
         let todays_journal = if todays_journal_file.exists() {
-            println!("loaded existing journal file");
-            parse_logseq_file(&todays_journal_file)?
+            match parse_logseq_file(&todays_journal_file) {
+                Ok(pd) => {
+                    println!("loaded existing journal file");
+                    pd
+                }
+                Err(e) => {
+                    // keep note creation working with a broken journal
+                    println!(
+                        "Could not parse journal {todays_journal_file:?}, appending new blocks as text"
+                    );
+                    info!("Could not parse journal {todays_journal_file:?}: {e:?}");
+                    unparsed_journal = Some(
+                        std::fs::read_to_string(&todays_journal_file)
+                            .context(format!("Could not read {todays_journal_file:?}"))?,
+                    );
+                    ParsedDocument::ParsedFile(vec![], todays_journal_file.clone())
+                }
+            }
         } else {
             println!("creating new journal file!");
             ParsedDocument::ParsedFile(vec![], todays_journal_file.clone())
@@ -113,16 +141,73 @@ impl LogSeqHandler {
             templates,
             todays_journal,
             todays_journal_file,
+            unparsed_journal,
+            initial_status,
+            line_ending: LineEnding::default(),
         };
         Ok(res)
     }
+
+    /// line terminator of the written journal
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// the filled in template block for an interactive task
+    fn interactive_block(&self, task_data: &TaskData, details: &TaskDetails) -> Result<ListElem> {
+        let TaskData::Interactive(template_name, url, _, tags, sources) = task_data else {
+            bail!("{task_data:?} is not an interactive task");
+        };
+        let tags = details.merge_tags(tags);
+        let mut comp = self
+            .templates
+            .get_template_comp(template_name)
+            .context(format!("No template {template_name:?}"))?;
+        let mut properties: Vec<(&str, Vec<PropValue>)> = vec![
+            (
+                "source",
+                sources
+                    .iter()
+                    .map(|s| PropValue::String(s.to_string()))
+                    .collect(),
+            ),
+            (
+                "tags",
+                tags.iter()
+                    .map(|t| PropValue::String(t.to_string()))
+                    .collect(),
+            ),
+        ];
+        if let Some(url) = url {
+            properties.push(("url", vec![PropValue::String(url.to_string())]))
+        }
+        fill_all_props_le(&mut comp, &properties);
+        fill_all_props_le(&mut comp, &details.properties());
+        if let Some(status) = task_data.initial_status(&self.initial_status) {
+            comp.for_each_property_mut(&mut |p| set_status(p, status));
+        }
+        Ok(comp)
+    }
 }
 
 impl TaskDataHandler for LogSeqHandler {
-    fn handle_task_data(&mut self, task_data: &TaskData) -> Result<bool> {
+    fn handle_task_data(
+        &mut self,
+        task_data: &TaskData,
+        details: &TaskDetails,
+    ) -> Result<Option<PathBuf>> {
         use TaskData::*;
+        let task_properties = details.properties();
+        let initial_status = task_data.initial_status(&self.initial_status);
+        let status = |le: &mut ListElem| {
+            if let Some(status) = initial_status {
+                le.for_each_property_mut(&mut |p| set_status(p, status));
+            }
+        };
         match task_data {
-            Youtube(url, title, channel, tags) => {
+            Youtube(url, title, channel, tags, timestamp) => {
+                let tags = details.merge_tags(tags);
                 // retrieve the youtube template frmo the templates file
                 // then fill in the properties
                 // then add a child list item with the youtube embed (or fall back to simply adding
@@ -150,6 +235,14 @@ impl TaskDataHandler for LogSeqHandler {
                     ),
                 ];
                 fill_all_props_le(&mut yt_template, &properties);
+                if let Some(timestamp) = timestamp {
+                    fill_all_props_le(
+                        &mut yt_template,
+                        &[("timestamp", vec![PropValue::Number(*timestamp as f64)])],
+                    );
+                }
+                fill_all_props_le(&mut yt_template, &task_properties);
+                status(&mut yt_template);
 
                 // embed child
                 if let Some(le) = yt_template.children.get_mut(0)
@@ -166,6 +259,7 @@ impl TaskDataHandler for LogSeqHandler {
                 self.todays_journal.add_component(yt_block);
             }
             TaskData::Sbs(url, author, title, tags, description) => {
+                let tags = details.merge_tags(tags);
                 if let Some(comp) = self.templates.get_template_comp("article") {
                     let mut comp = comp.clone();
                     let mut source = vec![PropValue::String("[[Stronger by Science]]".to_string())];
@@ -194,11 +288,14 @@ impl TaskDataHandler for LogSeqHandler {
                         properties.push(("description", vec![PropValue::String(title.clone())]));
                     }
                     fill_all_props_le(&mut comp, &properties);
+                    fill_all_props_le(&mut comp, &task_properties);
+                    status(&mut comp);
                     let comp = DocumentComponent::List(vec![comp], false);
                     self.todays_journal.add_component(comp);
                 }
             }
-            TaskData::YtPlaylist(url, channel, title) => {
+            TaskData::YtPlaylist(url, channel, title, tags) => {
+                let tags = details.merge_tags(tags);
                 let mut temp = self
                     .templates
                     .get_template_comp("youtube_playlist")
@@ -207,27 +304,6 @@ impl TaskDataHandler for LogSeqHandler {
                     ("description", vec![PropValue::String(title.to_string())]),
                     ("authors", vec![PropValue::String(format!("[[{channel}]]"))]),
                     ("url", vec![PropValue::String(url.to_string())]),
-                ];
-                fill_all_props_le(&mut temp, properties);
-                let list = DocumentComponent::List(vec![temp], false);
-                self.todays_journal.add_component(list);
-            }
-            TaskData::Interactive(template_name, url, title, tags, sources) => {
-                let mut comp = self.templates.get_template_comp(template_name).unwrap();
-                let mut add = vec![];
-                if let Some(title) = title {
-                    add.push(("description", vec![title.clone()]));
-                }
-                add.push(("tags", tags.clone()));
-
-                let mut properties: Vec<(&str, Vec<PropValue>)> = vec![
-                    (
-                        "source",
-                        sources
-                            .iter()
-                            .map(|s| PropValue::String(s.to_string()))
-                            .collect(),
-                    ),
                     (
                         "tags",
                         tags.iter()
@@ -235,25 +311,41 @@ impl TaskDataHandler for LogSeqHandler {
                             .collect(),
                     ),
                 ];
-                if let Some(url) = url {
-                    properties.push(("url", vec![PropValue::String(url.to_string())]))
-                }
-                fill_all_props_le(&mut comp, &properties);
-                let list = DocumentComponent::List(vec![comp], false);
+                fill_all_props_le(&mut temp, properties);
+                fill_all_props_le(&mut temp, &task_properties);
+                status(&mut temp);
+                let list = DocumentComponent::List(vec![temp], false);
+                self.todays_journal.add_component(list);
+            }
+            Interactive(..) => {
+                let list = DocumentComponent::List(
+                    vec![self.interactive_block(task_data, details)?],
+                    false,
+                );
                 self.todays_journal.add_component(list);
             }
             _ => {
-                return Ok(false);
+                return Ok(None);
             }
         }
 
+        let mut journal_text = self.todays_journal.to_logseq_text(&None);
+        if let Some(text) = &self.unparsed_journal {
+            journal_text = append_raw(text, &journal_text);
+        }
         std::fs::write(
             &self.todays_journal_file,
-            self.todays_journal.to_logseq_text(&None),
+            self.line_ending.apply(&journal_text),
         )
         .context(format!("Could not write to {:?}", self.todays_journal_file))?;
-        Ok(true)
+        Ok(Some(self.todays_journal_file.clone()))
+    }
+    fn preview(&self, task_data: &TaskData, details: &TaskDetails) -> Result<String> {
+        let list =
+            DocumentComponent::List(vec![self.interactive_block(task_data, details)?], false);
+        Ok(ParsedDocument::ParsedText(vec![list]).to_logseq_text(&None))
     }
+
     fn get_template_names(&self) -> Result<Vec<String>> {
         Ok(self.templates.template_names())
     }
@@ -1,13 +1,15 @@
 use std::path::PathBuf;
 
 use crate::{
-    document_component::{DocumentComponent, PropValue},
+    document_component::{DocumentComponent, ParsedDocument, PropValue},
     parsing::{TextMode, parse_all_files_in_dir},
     todoi::{
-        TaskData,
+        TaskData, TaskDetails,
+        audit_log::{AuditEntry, AuditLog},
         config::Config,
         get_task_data_full,
         handlers::{logseq_handler::LogSeqHandler, zk_handler::ZkHandler},
+        print_unhandled,
         todoist_api::TodoistTask,
     },
 };
@@ -17,8 +19,25 @@ use tracing::instrument;
 
 pub mod logseq_handler;
 pub mod zk_handler;
+
+/// appends addition to the unparsed text of a journal that could not be parsed
+fn append_raw(text: &str, addition: &str) -> String {
+    let text = text.trim_end();
+    if text.is_empty() {
+        addition.to_string()
+    } else {
+        format!("{text}\n{addition}")
+    }
+}
 pub trait TaskDataHandler {
-    fn handle_task_data(&mut self, task_data: &TaskData) -> Result<bool>;
+    /// returns the note (or journal) the task was written to, None if it was not handled
+    fn handle_task_data(
+        &mut self,
+        task_data: &TaskData,
+        details: &TaskDetails,
+    ) -> Result<Option<PathBuf>>;
+    /// text of the note an interactive task would create, nothing is written
+    fn preview(&self, task_data: &TaskData, details: &TaskDetails) -> Result<String>;
     fn get_template_names(&self) -> Result<Vec<String>>;
 }
 
@@ -28,10 +47,19 @@ pub fn handle_tasks_main(
     config: &Config,
     mode: TextMode,
     root_dir: &PathBuf,
+    report_unhandled: bool,
+    preview: bool,
 ) -> Result<Vec<TodoistTask>> {
+    let line_ending = config.settings.line_ending.unwrap_or_default();
     let mut handler: Box<dyn TaskDataHandler> = match mode {
-        TextMode::Zk => Box::new(ZkHandler::new(root_dir.to_path_buf())),
-        TextMode::LogSeq => Box::new(LogSeqHandler::new(root_dir.to_path_buf())?),
+        TextMode::Zk => Box::new(ZkHandler::from_settings(root_dir, &config.settings)),
+        TextMode::LogSeq => Box::new(
+            LogSeqHandler::new(
+                root_dir.to_path_buf(),
+                config.settings.initial_status.clone(),
+            )?
+            .with_line_ending(line_ending),
+        ),
         _ => todo!(),
     };
     let all_urls = get_all_urls(root_dir, mode)?;
@@ -46,51 +74,195 @@ pub fn handle_tasks_main(
             }
         })
         .collect();
-    let tasks = get_task_data_full(&deduped_tasks, config, &handler.get_template_names()?);
+    let (tasks, unhandled) = get_task_data_full(
+        &deduped_tasks,
+        config,
+        &handler.get_template_names()?,
+        Some(handler.as_ref()).filter(|_| preview),
+    );
 
+    let url_property = config.settings.task_url_property();
+    let audit_log = AuditLog::new(AuditLog::default_path());
     let tasks: Result<Vec<(bool, TodoistTask)>> = tasks
         .into_iter()
-        .map(|(td, task)| handler.handle_task_data(&td).map(|e| (e, task)))
+        .map(|(td, task)| {
+            let details = TaskDetails::from(&task).with_url_property(url_property.clone());
+            handle_task(handler.as_mut(), &td, &task, &details, &audit_log).map(|e| (e, task))
+        })
         .collect();
     debug!("filtering handled tasks: {tasks:?}");
     let tasks = tasks?
         .iter()
         .filter_map(|(done, task)| if *done { Some(task.clone()) } else { None })
         .collect();
+    if report_unhandled {
+        print_unhandled(&unhandled);
+    }
     Ok(tasks)
 }
 
-fn get_all_urls(root_dir: &PathBuf, mode: TextMode) -> Result<Vec<String>> {
+/// handles a single task and records the created note in the audit log
+fn handle_task(
+    handler: &mut dyn TaskDataHandler,
+    task_data: &TaskData,
+    task: &TodoistTask,
+    details: &TaskDetails,
+    audit_log: &AuditLog,
+) -> Result<bool> {
+    let Some(file) = handler.handle_task_data(task_data, details)? else {
+        return Ok(false);
+    };
+    let template = task_data.kind().unwrap_or_default();
+    if let Err(e) = audit_log.append(&AuditEntry::new(&task.content, template, &file)) {
+        eprintln!("Warning: could not log the note {file:?}: {e:#}");
+    }
+    Ok(true)
+}
+
+pub fn get_all_urls(root_dir: &PathBuf, mode: TextMode) -> Result<Vec<String>> {
     let parsed_documents = parse_all_files_in_dir(root_dir, &mode)?;
-    let prop_dcs: Vec<DocumentComponent> = parsed_documents
-        .iter()
-        .flat_map(|pd| {
-            pd.get_all_document_components(&|dc: &DocumentComponent| {
-                if let DocumentComponent::Properties(props) = dc {
-                    props.iter().any(|p| p.has_name("url"))
-                } else {
-                    false
-                }
-            })
-            .into_iter()
+    Ok(collect_urls(&parsed_documents))
+}
+
+/// returns the string values of all `url` properties in documents
+pub fn collect_urls(documents: &[ParsedDocument]) -> Vec<String> {
+    let mut res = vec![];
+    documents.iter().for_each(|pd| {
+        pd.for_each_component(&mut |dc| {
+            if let DocumentComponent::Properties(props, _) = dc {
+                props
+                    .iter()
+                    .filter(|p| p.has_name("url"))
+                    .flat_map(|p| p.values.iter())
+                    .for_each(|v| {
+                        if let PropValue::String(s) = v {
+                            res.push(s.clone());
+                        }
+                    });
+            }
         })
-        .collect();
-    let tmp: Vec<String> = prop_dcs
-        .iter()
-        .filter_map(|dc| {
-            if let DocumentComponent::Properties(props) = dc {
-                let tmp = props.iter().filter(|p| p.has_name("url")).flat_map(|p| {
-                    p.values.iter().filter_map(|v| match v {
-                        PropValue::String(s) => Some(s.clone()),
+    });
+    res
+}
+
+#[test]
+fn test_collect_urls_matches_cloning_traversal() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(dir.join("pages")).unwrap();
+    std::fs::write(
+        dir.join("pages").join("a.md"),
+        "- video\n  url:: https://example.com/a\n  - nested\n    url:: https://example.com/b",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("b.md"),
+        "url:: https://example.com/page\n\n- no url\n- other\n  source:: https://example.com/c",
+    )
+    .unwrap();
+
+    // the previous implementation, which clones the selected components
+    let cloning = |documents: &[ParsedDocument]| -> Vec<String> {
+        documents
+            .iter()
+            .flat_map(|pd| {
+                pd.get_all_document_components(&|dc| {
+                    matches!(dc, DocumentComponent::Properties(props, _) if props.iter().any(|p| p.has_name("url")))
+                })
+            })
+            .flat_map(|dc| match dc {
+                DocumentComponent::Properties(props, _) => props
+                    .into_iter()
+                    .filter(|p| p.has_name("url"))
+                    .flat_map(|p| p.values)
+                    .filter_map(|v| match v {
+                        PropValue::String(s) => Some(s),
                         _ => None,
                     })
-                });
-                Some(tmp)
-            } else {
-                None
-            }
-        })
-        .flatten()
-        .collect();
-    Ok(tmp)
+                    .collect(),
+                _ => vec![],
+            })
+            .collect()
+    };
+
+    let documents = parse_all_files_in_dir(&dir, &TextMode::LogSeq).unwrap();
+    let mut urls = collect_urls(&documents);
+    assert_eq!(urls, cloning(&documents));
+    urls.sort();
+    assert_eq!(
+        urls,
+        vec![
+            "https://example.com/a",
+            "https://example.com/b",
+            "https://example.com/page"
+        ]
+    );
+}
+
+#[test]
+fn test_created_note_is_logged() {
+    use crate::todoi::config::{IdScheme, JournalSettings, NoteCreationSettings, NoteDateSettings};
+    use std::collections::BTreeMap;
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let templates = dir.join(".zk").join("templates");
+    std::fs::create_dir_all(&templates).unwrap();
+    std::fs::write(
+        templates.join("article.md"),
+        "---\ntags: [inbox]\n---\n\n# {{title}}\n- url::= ",
+    )
+    .unwrap();
+    let mut handler = ZkHandler::new(
+        dir.clone(),
+        JournalSettings {
+            date_format: Some("journal.md".to_string()),
+            ..Default::default()
+        },
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("/nonexistent/pkmt_test/zk"),
+        NoteCreationSettings {
+            native: true,
+            id_scheme: IdScheme::Slug,
+            ..Default::default()
+        },
+        BTreeMap::new(),
+    )
+    .with_template_dir(templates);
+    let task = TodoistTask {
+        id: "1".to_string(),
+        content: "https://example.com/article".to_string(),
+        parent_id: None,
+        project_id: None,
+        labels: vec![],
+        priority: None,
+        due: None,
+        created_at: None,
+        url: None,
+    };
+    let task_data = TaskData::Interactive(
+        "article.md".to_string(),
+        Some(task.content.clone()),
+        Some("Some Article".to_string()),
+        vec![],
+        vec![],
+    );
+    let audit_log = AuditLog::new(dir.join("todoi_log.jsonl"));
+
+    let handled = handle_task(
+        &mut handler,
+        &task_data,
+        &task,
+        &TaskDetails::from(&task),
+        &audit_log,
+    )
+    .unwrap();
+    assert!(handled);
+    let text = std::fs::read_to_string(audit_log.path()).unwrap();
+    assert_eq!(text.lines().count(), 1);
+    let entry: AuditEntry = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    assert_eq!(entry.task, "https://example.com/article");
+    assert_eq!(entry.template, "article");
+    assert_eq!(entry.file, dir.join("some-article.md"));
+    assert!(entry.file.exists());
 }
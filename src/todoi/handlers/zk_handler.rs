@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::Debug,
     fs::DirEntry,
     path::{Path, PathBuf},
@@ -10,38 +10,137 @@ use std::{
 use anyhow::{Context, Result, bail};
 use tracing::{debug, info, instrument};
 
-use crate::todoi::{TaskData, handlers::TaskDataHandler, url_is_duplicate};
+use crate::todoi::{
+    TaskData, TaskDetails,
+    config::{
+        DEFAULT_TEMPLATE_DIR, IdScheme, JournalSettings, NoteCreationSettings, NoteDateSettings,
+        Settings,
+    },
+    handlers::{TaskDataHandler, append_raw},
+    set_status, url_is_duplicate,
+};
 use crate::{
     document_component::{
         DocumentComponent, FileInfo, ListElem, MentionedFile, ParsedDocument, PropValue,
     },
-    parsing::{TextMode, parse_file, zk_parsing},
+    parsing::{LineEnding, TagStyle, TextMode, parse_file, zk_parsing},
+    util::{SlugOptions, slugify},
 };
 
 #[derive(Debug)]
 pub struct ZkHandler {
     root_dir: PathBuf,
+    template_dir: PathBuf,
+    journal_settings: JournalSettings,
+    note_date_settings: NoteDateSettings,
+    post_create_hook: Option<String>,
+    zk_binary: PathBuf,
+    note_creation: NoteCreationSettings,
+    initial_status: BTreeMap<String, String>,
+    line_ending: LineEnding,
 }
 
 impl ZkHandler {
-    pub fn new(root_dir: PathBuf) -> Self {
-        Self { root_dir }
+    pub fn new(
+        root_dir: PathBuf,
+        journal_settings: JournalSettings,
+        note_date_settings: NoteDateSettings,
+        post_create_hook: Option<String>,
+        zk_binary: PathBuf,
+        note_creation: NoteCreationSettings,
+        initial_status: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            template_dir: root_dir.join(DEFAULT_TEMPLATE_DIR),
+            root_dir,
+            journal_settings,
+            note_date_settings,
+            post_create_hook,
+            zk_binary,
+            note_creation,
+            initial_status,
+            line_ending: LineEnding::default(),
+        }
+    }
+
+    /// handler for the notebook at root_dir configured by settings
+    pub fn from_settings(root_dir: &Path, settings: &Settings) -> Self {
+        ZkHandler::new(
+            root_dir.to_path_buf(),
+            settings.zk_journal.clone(),
+            settings.zk_note_date.clone(),
+            settings.zk_hooks.post_create.clone(),
+            settings.zk_binary(),
+            settings.zk_new.clone(),
+            settings.initial_status.clone(),
+        )
+        .with_template_dir(settings.zk_template_dir(root_dir))
+        .with_line_ending(settings.line_ending.unwrap_or_default())
+    }
+
+    /// line terminator of the written notes and journals
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
     }
 
+    /// reads the templates from template_dir instead of `.zk/templates` in the notebook root
+    pub fn with_template_dir(mut self, template_dir: PathBuf) -> Self {
+        self.template_dir = template_dir;
+        self
+    }
+
+    /// runs the post creation hook for file if one is configured. Failures are only logged
     #[instrument]
-    fn get_zk_file(title: &str, template_path: PathBuf) -> Result<PathBuf> {
+    fn run_post_create_hook(&self, file: &Path) {
         use std::process::Command;
+        let Some(hook) = &self.post_create_hook else {
+            return;
+        };
+        let cmd = hook.replace("{file}", &file.to_string_lossy());
+        debug!("running post creation hook {cmd:?}");
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        match Command::new(shell).arg(flag).arg(&cmd).output() {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                println!("Post creation hook {cmd:?} failed: {stderr}");
+                info!("Post creation hook {cmd:?} failed: {stderr}");
+            }
+            Err(e) => {
+                println!("Failed to run post creation hook {cmd:?}: {e:?}");
+                info!("Failed to run post creation hook {cmd:?}: {e:?}");
+            }
+        }
+    }
+
+    /// runs `zk new`, with id instead of the id zk would generate if it is given
+    #[instrument]
+    fn get_zk_file(
+        zk_binary: &Path,
+        title: &str,
+        id: Option<&str>,
+        template_path: PathBuf,
+    ) -> Result<PathBuf> {
         debug!("trying to get zk file for {title}");
 
         let title = title.replace('"', "");
-        let output = Command::new("zk")
-            .arg("new")
-            .arg("--no-input")
-            .arg(format!("--title=\"{title}\""))
-            .arg(format!("--template={}", template_path.to_str().unwrap()))
-            .arg("-p")
-            .output()
-            .context(format!("failed to retrieve zk file for {title}"))?;
+        let mut args = vec![
+            "new".to_string(),
+            "--no-input".to_string(),
+            format!("--title=\"{title}\""),
+            format!("--template={}", template_path.to_str().unwrap()),
+            "-p".to_string(),
+        ];
+        if let Some(id) = id {
+            args.push(format!("--id={id}"));
+        }
+        let output =
+            run_zk(zk_binary, &args).context(format!("failed to retrieve zk file for {title}"))?;
         if !output.status.success() {
             println!("Failed to create zk file for title {title:?}!");
             bail!("Could not create zk file for {title:?}");
@@ -50,10 +149,65 @@ impl ZkHandler {
         Ok(PathBuf::from_str(p.trim())?)
     }
 
+    /// creates a note from the template in the notebook root without running `zk new`. Only the
+    /// `{{title}}` placeholder is filled in
     #[instrument]
-    fn get_zk_journal_file() -> Result<PathBuf> {
-        use std::process::Command;
-        let output = Command::new("zk").arg("daily-path").output()?;
+    fn create_note_native(&self, title: &str, template_path: &Path) -> Result<PathBuf> {
+        let file = self.root_dir.join(format!("{}.md", self.note_id(title)));
+        if file.exists() {
+            bail!("Note {file:?} already exists!");
+        }
+        let text = std::fs::read_to_string(template_path)
+            .context(format!("Failed to read template {template_path:?}"))?
+            .replace("{{title}}", title)
+            .replace("{{ title }}", title);
+        std::fs::write(&file, self.line_ending.apply(&text))
+            .context(format!("Failed to write to {file:?}"))?;
+        debug!("created note {file:?} natively");
+        Ok(file)
+    }
+
+    /// id of a new note with the given title according to the id scheme
+    fn note_id(&self, title: &str) -> String {
+        match self.note_creation.id_scheme {
+            IdScheme::Slug => slug_or_timestamp(title, &self.note_creation.slug),
+            IdScheme::Timestamp => timestamp_id(),
+        }
+    }
+
+    /// the creator note of name, created natively or using the zk CLI if there is none yet
+    pub fn creator_file(&self, name: &str) -> Result<PathBuf> {
+        get_zk_creator_file(
+            &creator_lookup_path()?,
+            &self.template_dir,
+            name,
+            |title, template| self.create_note(title, template),
+        )
+    }
+
+    /// creates a note from the template, either natively or using the zk CLI
+    fn create_note(&self, title: &str, template_path: PathBuf) -> Result<PathBuf> {
+        if self.note_creation.native {
+            self.create_note_native(title, &template_path)
+        } else {
+            ZkHandler::get_zk_file(
+                &self.zk_binary,
+                title,
+                Some(&self.note_id(title)),
+                template_path,
+            )
+        }
+    }
+
+    #[instrument]
+    fn get_zk_journal_file(&self) -> Result<PathBuf> {
+        if let Some(date_format) = &self.journal_settings.date_format {
+            let today = chrono::offset::Local::now();
+            let p = self.root_dir.join(today.format(date_format).to_string());
+            debug!("daily path from configured format: {p:?}");
+            return Ok(p);
+        }
+        let output = run_zk(&self.zk_binary, &["daily-path".to_string()])?;
         let p = std::str::from_utf8(&output.stdout)?.trim();
         debug!("daily path: {p:?}");
         Ok(PathBuf::from_str(p)?)
@@ -66,7 +220,7 @@ impl ZkHandler {
         prop_name: &str,
         file_dir: &Option<PathBuf>,
     ) -> Result<bool> {
-        let file = get_zk_creator_file(&self.root_dir, author)?;
+        let file = self.creator_file(author)?;
         debug!("Found creator file {file:?} for {author:?}");
         self.fill_props(
             pd,
@@ -81,21 +235,51 @@ impl ZkHandler {
         Ok(true)
     }
 
+    fn template_file(&self, task_data: &TaskData) -> PathBuf {
+        match task_data {
+            TaskData::Youtube(..) => self.template_dir.join("yt_video.md"),
+            TaskData::Sbs(_, _, _, _, _) => self.template_dir.join("article.md"),
+            TaskData::YtPlaylist(_, _, _, _) => self.template_dir.join("yt_playlist.md"),
+            TaskData::Interactive(template_name, _, _, _, _) => {
+                self.template_dir.join(template_name)
+            }
+            _ => todo!("not implemented: conversion of {task_data:?} to zk."),
+        }
+    }
+
+    /// sets the status configured for the task type, otherwise the status of the template is kept
+    fn set_initial_status(&self, pd: &mut ParsedDocument, task_data: &TaskData) {
+        if let Some(status) = task_data.initial_status(&self.initial_status) {
+            pd.for_each_property_mut(&mut |p| set_status(p, status));
+        }
+    }
+
     #[instrument()]
     fn add_to_zk_pd(
         &self,
         pd: &mut ParsedDocument,
         task_data: &TaskData,
+        details: &TaskDetails,
         file_dir: &Option<PathBuf>,
     ) -> bool {
         let frontmatter =
             pd.get_document_component_mut(&|dc| matches!(dc, DocumentComponent::Frontmatter(_)));
 
-        let tags_to_add: Vec<String> = task_data
-            .get_tags()
+        let tags_to_add: Vec<String> = details
+            .merge_tags(&task_data.get_tags())
             .iter()
-            .map(|t| t.trim_start_matches('#').to_string())
+            .map(|t| TagStyle::Bare.render(t))
             .collect();
+        let date = match &self.note_date_settings.property {
+            Some(prop_name) => match self.note_date_settings.stamp(details.created_at.as_deref()) {
+                Ok(date) => Some((prop_name, date)),
+                Err(e) => {
+                    println!("Failed to compute note date: {e:?}");
+                    return false;
+                }
+            },
+            None => None,
+        };
 
         let tags_success = if let Some(dc) = frontmatter {
             if let DocumentComponent::Frontmatter(properties) = dc {
@@ -103,6 +287,11 @@ impl ZkHandler {
                     if p.has_name("tags") {
                         p.add_values_parse(&tags_to_add, &TextMode::Zk, file_dir);
                     }
+                    if let Some((prop_name, date)) = &date
+                        && p.has_name(prop_name)
+                    {
+                        p.values = vec![PropValue::String(date.clone())];
+                    }
                 }
                 true
             } else {
@@ -117,6 +306,11 @@ impl ZkHandler {
             false
         };
         if tags_success {
+            self.set_initial_status(pd, task_data);
+            details
+                .properties()
+                .iter()
+                .for_each(|(name, values)| self.fill_props(pd, name, values, file_dir));
             match task_data {
                 TaskData::Sbs(url, author, _, _, desc) => {
                     self.fill_property(pd, "url", &[url.to_string()], file_dir);
@@ -134,8 +328,12 @@ impl ZkHandler {
                         self.fill_property(pd, "description", &[desc.to_string()], file_dir);
                     }
                 }
-                TaskData::Youtube(url, title, channel, _) => {
+                TaskData::Youtube(url, title, channel, _, timestamp) => {
                     self.fill_property(pd, "url", &[url.to_string()], file_dir);
+                    if let Some(timestamp) = timestamp {
+                        let value = PropValue::Number(*timestamp as f64);
+                        self.fill_props(pd, "timestamp", &[value], file_dir);
+                    }
                     let success = self.fill_in_creator(pd, channel, "channel", file_dir);
                     if success.is_err() {
                         println!("Could not fill in creator for {url:?}: {success:?}");
@@ -143,7 +341,7 @@ impl ZkHandler {
                     }
                     self.fill_property(pd, "description", &[title.to_string()], file_dir);
                 }
-                TaskData::YtPlaylist(url, channel, _) => {
+                TaskData::YtPlaylist(url, channel, _, _) => {
                     self.fill_property(pd, "url", &[url.to_string()], file_dir);
                     let success = self.fill_in_creator(pd, channel, "channel", file_dir);
                     if success.is_err() {
@@ -170,16 +368,40 @@ impl ZkHandler {
 
     #[instrument]
     fn append_to_zk_journal(&self, dc: DocumentComponent) -> Result<bool> {
-        let journal_path = ZkHandler::get_zk_journal_file()?;
-        let mut pd = parse_file(&journal_path, &TextMode::Zk)?;
-        debug!("adding {dc:?} to journal file");
-        pd.add_component(dc);
-        let file_info =
-            FileInfo::try_new(journal_path.clone(), Some(journal_path.clone()), None, None)?;
-        let journal_text = pd.to_zk_text(&Some(file_info));
+        let journal_path = self.get_zk_journal_file()?;
+        let file_info = Some(FileInfo::try_new(
+            journal_path.clone(),
+            Some(journal_path.clone()),
+            None,
+            None,
+        )?);
+        let parsed = if journal_path.exists() {
+            parse_file(&journal_path, &TextMode::Zk)
+        } else {
+            if let Some(parent) = journal_path.parent() {
+                std::fs::create_dir_all(parent).context(format!("Could not create {parent:?}"))?;
+            }
+            Ok(ParsedDocument::ParsedFile(vec![], journal_path.clone()))
+        };
+        let journal_text = match parsed {
+            Ok(mut pd) => {
+                debug!("adding {dc:?} to journal file");
+                add_to_journal_pd(&mut pd, dc, &self.journal_settings);
+                pd.to_zk_text(&file_info)
+            }
+            Err(e) => {
+                // keep note creation working with a broken journal
+                println!("Could not parse journal {journal_path:?}, appending the mention as text");
+                info!("Could not parse journal {journal_path:?}: {e:?}");
+                let text = std::fs::read_to_string(&journal_path)
+                    .context(format!("Could not read {journal_path:?}"))?;
+                let mention = ParsedDocument::ParsedText(vec![dc]).to_zk_text(&file_info);
+                append_raw(&text, &mention)
+            }
+        };
         debug!("new journal text: {journal_text:?}");
 
-        std::fs::write(&journal_path, journal_text)
+        std::fs::write(&journal_path, self.line_ending.apply(&journal_text))
             .context(format!("Could not write file {journal_path:?}"))?;
         Ok(true)
     }
@@ -193,11 +415,11 @@ impl ZkHandler {
         file_dir: &Option<PathBuf>,
     ) {
         let property = pd.get_document_component_mut(&|dc| match dc {
-            DocumentComponent::Properties(props) => props.iter().any(|p| p.has_name(prop_name)),
+            DocumentComponent::Properties(props, _) => props.iter().any(|p| p.has_name(prop_name)),
             _ => false,
         });
         if let Some(prop) = property
-            && let DocumentComponent::Properties(props) = prop
+            && let DocumentComponent::Properties(props, _) = prop
         {
             props.iter_mut().for_each(|p| {
                 if p.has_name(prop_name) {
@@ -218,11 +440,11 @@ impl ZkHandler {
         file_dir: &Option<PathBuf>,
     ) {
         let property = pd.get_document_component_mut(&|dc| match dc {
-            DocumentComponent::Properties(props) => props.iter().any(|p| p.has_name(prop_name)),
+            DocumentComponent::Properties(props, _) => props.iter().any(|p| p.has_name(prop_name)),
             _ => false,
         });
         if let Some(prop) = property
-            && let DocumentComponent::Properties(props) = prop
+            && let DocumentComponent::Properties(props, _) = prop
         {
             props.iter_mut().for_each(|p| {
                 if p.has_name(prop_name) {
@@ -235,67 +457,95 @@ impl ZkHandler {
 
 impl TaskDataHandler for ZkHandler {
     #[instrument]
-    fn handle_task_data(&mut self, task_data: &TaskData) -> Result<bool> {
+    fn handle_task_data(
+        &mut self,
+        task_data: &TaskData,
+        details: &TaskDetails,
+    ) -> Result<Option<PathBuf>> {
         debug!("handling {task_data:?}");
         if let Some(url) = task_data.get_url()
             && url_is_duplicate(url, &self.root_dir, &TextMode::Zk)?
         {
             info!("Duplicate url: {url}! Skipping {task_data:?}");
-            return Ok(false);
+            return Ok(None);
         }
         let Some(title) = task_data.get_title() else {
             debug!("no title!");
-            return Ok(false);
-        };
-        let template_file = match task_data {
-            TaskData::Youtube(_url, _, _channel, _tags) => {
-                self.root_dir.join(".zk/templates/yt_video.md")
-            }
-            TaskData::Sbs(_, _, _, _, _) => self.root_dir.join(".zk/templates/article.md"),
-            TaskData::YtPlaylist(_, _, _) => self.root_dir.join(".zk/templates/yt_playlist.md"),
-            TaskData::Interactive(template_name, _, _, _, _) => {
-                self.root_dir.join(".zk/templates").join(template_name)
-            }
-            _ => todo!("not implemented: conversion of {task_data:?} to zk."),
+            return Ok(None);
         };
+        let template_file = self.template_file(task_data);
         debug!("using template {template_file:?}");
-        let Ok(zk_file) = ZkHandler::get_zk_file(&title, template_file) else {
-            return Ok(false);
+        let Ok(zk_file) = self.create_note(&title, template_file) else {
+            return Ok(None);
         };
         if !zk_file.exists() {
             println!("zk file {zk_file:?} was not created!");
             info!("zk file {zk_file:?} was not created!");
-            return Ok(false);
+            return Ok(None);
         }
         debug!("parsing: {zk_file:?}");
         let pd = zk_parsing::parse_zk_file(&zk_file);
         debug!("{pd:?}");
         let mut pd = pd?;
-        let success = self.add_to_zk_pd(&mut pd, task_data, &Some(zk_file.clone()));
+        let success = self.add_to_zk_pd(&mut pd, task_data, details, &Some(zk_file.clone()));
         if success {
             let file_info = FileInfo::try_new(zk_file.clone(), Some(zk_file.clone()), None, None)?;
             let text = pd.to_zk_text(&Some(file_info));
             debug!("added {task_data:?} to pd with result: {text:?}");
 
-            std::fs::write(&zk_file, text).context(format!("Failed to write to {zk_file:?}!"))?;
-            let mention =
-                DocumentComponent::FileLink(MentionedFile::FilePath(zk_file), None, Some(title));
+            std::fs::write(&zk_file, self.line_ending.apply(&text))
+                .context(format!("Failed to write to {zk_file:?}!"))?;
+            self.run_post_create_hook(&zk_file);
+            let mention = DocumentComponent::FileLink(
+                MentionedFile::FilePath(zk_file.clone()),
+                None,
+                Some(title),
+            );
             let journal_mention = DocumentComponent::List(
                 vec![ListElem::new(ParsedDocument::ParsedText(vec![mention]))],
                 false,
             );
             let success = self.append_to_zk_journal(journal_mention)?;
-            Ok(success)
+            Ok(Some(zk_file).filter(|_| success))
         } else {
             debug!("failed to add {task_data:?}");
-            Ok(false)
+            Ok(None)
+        }
+    }
+
+    /// the creator notes of sources are not looked up, as that may create them
+    fn preview(&self, task_data: &TaskData, details: &TaskDetails) -> Result<String> {
+        let Some(title) = task_data.get_title() else {
+            bail!("{task_data:?} has no title");
+        };
+        let task_data = match task_data {
+            TaskData::Interactive(template_name, url, title, tags, _) => TaskData::Interactive(
+                template_name.clone(),
+                url.clone(),
+                title.clone(),
+                tags.clone(),
+                vec![],
+            ),
+            _ => task_data.clone(),
+        };
+        let template_file = self.template_file(&task_data);
+        let text = std::fs::read_to_string(&template_file)
+            .context(format!("Failed to read template {template_file:?}"))?
+            .replace("{{title}}", &title)
+            .replace("{{ title }}", &title);
+        let file_dir = Some(self.root_dir.clone());
+        let mut pd = zk_parsing::parse_zk_text(&text, &file_dir)?;
+        if !self.add_to_zk_pd(&mut pd, &task_data, details, &file_dir) {
+            bail!("Failed to fill in {template_file:?}");
         }
+        Ok(pd.to_zk_text(&None))
     }
 
     fn get_template_names(&self) -> Result<Vec<String>> {
-        let p = self.root_dir.join(".zk/templates");
+        let p = &self.template_dir;
         let dir_entries: Vec<DirEntry> = p
-            .read_dir()?
+            .read_dir()
+            .context(format!("Could not read template directory {p:?}"))?
             .map(|f| f.context(""))
             .collect::<Result<Vec<DirEntry>>>()?;
         let res: Result<Vec<Option<String>>> = dir_entries
@@ -321,41 +571,114 @@ impl TaskDataHandler for ZkHandler {
     }
 }
 
-pub fn get_zk_creator_file(root_dir: &Path, name: &str) -> Result<PathBuf> {
+/// Adds dc to the end of the configured journal section. Falls back to appending it to the end of
+/// the journal if no section is configured or the section is missing and should not be created.
+fn add_to_journal_pd(pd: &mut ParsedDocument, dc: DocumentComponent, settings: &JournalSettings) {
+    let Some(section) = &settings.section else {
+        pd.add_component(dc);
+        return;
+    };
+    let comps = pd.components();
+    let start = comps.iter().position(
+        |c| matches!(c, DocumentComponent::Heading(_, title) if title.trim() == section.trim()),
+    );
+    let Some(start) = start else {
+        if settings.create_section {
+            pd.add_component(DocumentComponent::Heading(
+                settings.section_level,
+                section.trim().to_string(),
+            ));
+        }
+        pd.add_component(dc);
+        return;
+    };
+    let DocumentComponent::Heading(level, _) = comps[start] else {
+        unreachable!("start has to point to a heading");
+    };
+    // the section ends at the next heading of the same or a lower level
+    let end = comps[start + 1..]
+        .iter()
+        .position(|c| matches!(c, DocumentComponent::Heading(l, _) if *l <= level))
+        .map(|pos| pos + start + 1)
+        .unwrap_or(comps.len());
+    // extend a list at the end of the section instead of starting a new one
+    if end > start + 1
+        && let DocumentComponent::List(new_elems, _) = &dc
+        && let Some(DocumentComponent::List(list_elems, _)) = pd._get_nth_child_mut(end - 1)
+    {
+        list_elems.extend(new_elems.iter().cloned());
+        return;
+    }
+    pd.insert_component(end, dc);
+}
+
+/// creation time like `20250102223000`
+fn timestamp_id() -> String {
+    chrono::offset::Local::now()
+        .format("%Y%m%d%H%M%S")
+        .to_string()
+}
+
+/// slug of title, or a timestamp id if the title has no letters or digits
+fn slug_or_timestamp(title: &str, slug: &SlugOptions) -> String {
+    let res = slugify(title, slug);
+    if res.is_empty() { timestamp_id() } else { res }
+}
+
+/// runs the zk CLI with the given arguments, reporting a missing binary as such
+fn run_zk(zk_binary: &Path, args: &[String]) -> Result<std::process::Output> {
+    match std::process::Command::new(zk_binary).args(args).output() {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => bail!(
+            "the `zk` CLI was not found on PATH (tried {zk_binary:?}), install it or set `zk_binary` in the settings"
+        ),
+        res => res.context(format!("Failed to run {zk_binary:?} {args:?}")),
+    }
+}
+
+/// the table of creator notes shared by all notebooks
+fn creator_lookup_path() -> Result<PathBuf> {
     if let Some(base_dirs) = directories::BaseDirs::new() {
         let data_dir = base_dirs.data_dir().join("pkmt");
         if !data_dir.exists() {
             std::fs::create_dir(&data_dir).context("Could not create {data_dir:?}")?;
         }
-
-        let lookup_path = data_dir.join("creator_lookup.toml");
-        let mut lookup: HashMap<String, PathBuf> = if lookup_path.exists() {
-            debug!("loading lookup table from file.");
-            let text = std::fs::read_to_string(&lookup_path)
-                .context("Expected {lookup_path:?} to exist!")?;
-            toml::from_str(&text)?
-        } else {
-            debug!("creating now lookup table.");
-            HashMap::new()
-        };
-        if let Some(path) = lookup.get(name) {
-            debug!("{name:?}: found creator file in lookup: {path:?}");
-            Ok(path.to_path_buf())
-        } else {
-            let template_file = root_dir.join(".zk").join("templates").join("creator.md");
-            let file = ZkHandler::get_zk_file(name, template_file)?;
-            debug!("{name:?}: created new creator file: {file:?}");
-            lookup.insert(name.to_string(), file.clone());
-            let text = toml::to_string(&lookup)?;
-            std::fs::write(&lookup_path, text)
-                .context(format!("Could not write to {lookup_path:?}"))?;
-            Ok(file)
-        }
+        Ok(data_dir.join("creator_lookup.toml"))
     } else {
         bail!("Could not create basedirs!")
     }
 }
 
+/// the creator note of name in the lookup table, the note is created with create from the creator
+/// template if there is none yet
+fn get_zk_creator_file(
+    lookup_path: &Path,
+    template_dir: &Path,
+    name: &str,
+    create: impl FnOnce(&str, PathBuf) -> Result<PathBuf>,
+) -> Result<PathBuf> {
+    let mut lookup: HashMap<String, PathBuf> = if lookup_path.exists() {
+        debug!("loading lookup table from file.");
+        let text =
+            std::fs::read_to_string(lookup_path).context("Expected {lookup_path:?} to exist!")?;
+        toml::from_str(&text)?
+    } else {
+        debug!("creating now lookup table.");
+        HashMap::new()
+    };
+    if let Some(path) = lookup.get(name) {
+        debug!("{name:?}: found creator file in lookup: {path:?}");
+        Ok(path.to_path_buf())
+    } else {
+        let template_file = template_dir.join("creator.md");
+        let file = create(name, template_file)?;
+        debug!("{name:?}: created new creator file: {file:?}");
+        lookup.insert(name.to_string(), file.clone());
+        let text = toml::to_string(&lookup)?;
+        std::fs::write(lookup_path, text).context(format!("Could not write to {lookup_path:?}"))?;
+        Ok(file)
+    }
+}
+
 pub fn set_zk_creator_file(name: &str, new_file: &PathBuf) -> Result<()> {
     if !new_file.exists() {
         bail!("new creator file {new_file:?} does not exist!");
@@ -386,6 +709,65 @@ pub fn set_zk_creator_file(name: &str, new_file: &PathBuf) -> Result<()> {
     }
 }
 
+/// finds the root of the zk notebook containing start like zk does: the closest ancestor (or start
+/// itself) containing a `.zk` directory
+pub fn discover_zk_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".zk").is_dir())
+        .map(|dir| dir.to_path_buf())
+}
+
+/// creates a note in root_dir from the template with the given name in template_dir. `{{name}}` placeholders
+/// are replaced by the corresponding values in vars, which are also added to properties and
+/// frontmatter entries of the same name. The `title` variable determines the file name.
+pub fn instantiate_template(
+    root_dir: &Path,
+    template_dir: &Path,
+    template: &str,
+    vars: &[(String, String)],
+    slug: &SlugOptions,
+) -> Result<PathBuf> {
+    let mut template_file = template_dir.join(template);
+    if template_file.extension().is_none() {
+        template_file.set_extension("md");
+    }
+    if !template_file.exists() {
+        bail!("Template {template_file:?} does not exist!");
+    }
+    let Some((_, title)) = vars.iter().find(|(name, _)| name == "title") else {
+        bail!("No title given for the new note!");
+    };
+    let file = root_dir.join(format!("{}.md", slug_or_timestamp(title, slug)));
+    if file.exists() {
+        bail!("Note {file:?} already exists!");
+    }
+
+    let mut text = std::fs::read_to_string(&template_file)
+        .context(format!("Failed to read template {template_file:?}"))?;
+    for (name, value) in vars {
+        text = text
+            .replace(&format!("{{{{{name}}}}}"), value)
+            .replace(&format!("{{{{ {name} }}}}"), value);
+    }
+    let file_dir = Some(root_dir.to_path_buf());
+    let mut pd = zk_parsing::parse_zk_text(&text, &file_dir)
+        .context(format!("Failed to parse template {template_file:?}"))?;
+    pd.for_each_property_mut(&mut |p| {
+        let values: Vec<String> = vars
+            .iter()
+            .filter(|(name, _)| p.has_name(name))
+            .map(|(_, value)| value.clone())
+            .collect();
+        p.add_values_parse(&values, &TextMode::Zk, &file_dir);
+    });
+
+    let file_info = FileInfo::try_new(file.clone(), Some(file.clone()), None, None)?;
+    std::fs::write(&file, pd.to_zk_text(&Some(file_info)))
+        .context(format!("Failed to write to {file:?}"))?;
+    Ok(file)
+}
+
 #[ignore = "Test is hard to get right as the logic relies on the zk lookup file. A proper test would need some restructuring"]
 #[test]
 fn test_add_to_yt_pd() {
@@ -412,14 +794,23 @@ tags: [video, youtube, inbox]
     let Ok(mut pd) = res else {
         panic!("parsing failed: {res:?}");
     };
-    let zk_handler = ZkHandler::new("/home/tobias/kasten".into());
+    let zk_handler = ZkHandler::new(
+        "/home/tobias/kasten".into(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    );
     let task_data = TaskData::Youtube(
         "url".to_string(),
         "title".to_string(),
         "test_channel".to_string(),
         vec!["tag1".to_string(), "tag2".to_string()],
+        None,
     );
-    let _ = zk_handler.add_to_zk_pd(&mut pd, &task_data, &None);
+    let _ = zk_handler.add_to_zk_pd(&mut pd, &task_data, &TaskDetails::default(), &None);
     let res = pd.to_zk_text(&None);
     let expected = "---
 date: 2024-12-31 01:09:55
@@ -432,3 +823,471 @@ tags: [video, youtube, inbox, tag1, tag2]
 - url ::= url";
     assert_eq!(res, expected);
 }
+
+#[test]
+fn test_add_to_journal_section() {
+    use crate::parsing::zk_parsing::parse_zk_text;
+    let text = "# 2025-01-01\n\n## Inbox\n- a\n\n## Other\n- b";
+    let mut pd = parse_zk_text(text, &None).unwrap();
+    let settings = JournalSettings {
+        section: Some("Inbox".to_string()),
+        ..Default::default()
+    };
+    let mention = DocumentComponent::List(
+        vec![ListElem::new(ParsedDocument::ParsedText(vec![
+            DocumentComponent::FileLink(
+                MentionedFile::FileName("note.md".to_string()),
+                None,
+                Some("note".to_string()),
+            ),
+        ]))],
+        false,
+    );
+    add_to_journal_pd(&mut pd, mention, &settings);
+    let res = pd.to_zk_text(&None);
+    let expected = "# 2025-01-01\n\n## Inbox\n- a\n- [note](note.md)\n\n## Other\n- b";
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn test_add_task_details() {
+    use crate::parsing::zk_parsing::parse_zk_text;
+    use crate::todoi::todoist_api::TodoistTask;
+    let task: TodoistTask = serde_json::from_str(
+        r#"{"id": "1", "content": "task", "parent_id": null, "labels": ["reading"], "priority": 3, "due": {"date": "2025-01-02"}}"#,
+    )
+    .unwrap();
+    let details = TaskDetails::from(&task);
+    let text = "---\ntags: [inbox]\n---\n\n# title\n- priority::= \n- due::= ";
+    let mut pd = parse_zk_text(text, &None).unwrap();
+    let zk_handler = ZkHandler::new(
+        "/tmp".into(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    );
+    let task_data = TaskData::Interactive("note".to_string(), None, None, vec![], vec![]);
+    assert!(zk_handler.add_to_zk_pd(&mut pd, &task_data, &details, &None));
+    let res = pd.to_zk_text(&None);
+    let expected =
+        "---\ntags: [inbox, reading]\n---\n\n# title\n- priority ::= 3\n- due ::= 2025-01-02";
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn test_task_url_property() {
+    use crate::parsing::zk_parsing::parse_zk_text;
+    use crate::todoi::todoist_api::TodoistTask;
+    let task: TodoistTask = serde_json::from_str(
+        r#"{"id": "1", "content": "task", "parent_id": null, "url": "https://todoist.com/showTask?id=1"}"#,
+    )
+    .unwrap();
+    let zk_handler = ZkHandler::new(
+        "/tmp".into(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    );
+    let task_data = TaskData::Interactive("note".to_string(), None, None, vec![], vec![]);
+
+    let text = "---\ntags: [inbox]\n---\n\n# title\n- todoist::= ";
+    let mut pd = parse_zk_text(text, &None).unwrap();
+    assert!(zk_handler.add_to_zk_pd(&mut pd, &task_data, &TaskDetails::from(&task), &None));
+    assert_eq!(
+        pd.to_zk_text(&None),
+        "---\ntags: [inbox]\n---\n\n# title\n- todoist ::= https://todoist.com/showTask?id=1"
+    );
+
+    let text = "---\ntags: [inbox]\n---\n\n# title\n- task::= ";
+    let mut pd = parse_zk_text(text, &None).unwrap();
+    let details = TaskDetails::from(&task).with_url_property("task".to_string());
+    assert!(zk_handler.add_to_zk_pd(&mut pd, &task_data, &details, &None));
+    assert!(
+        pd.to_zk_text(&None)
+            .ends_with("- task ::= https://todoist.com/showTask?id=1")
+    );
+}
+
+#[test]
+fn test_note_date_stamp() {
+    use crate::parsing::zk_parsing::parse_zk_text;
+    use crate::todoi::config::DateSource;
+    let text = "---\ndate: 2024-12-31 01:09:55\ntags: [inbox]\n---\n\n# title";
+    let mut pd = parse_zk_text(text, &None).unwrap();
+    let note_date_settings = NoteDateSettings {
+        property: Some("date".to_string()),
+        format: "%Y-%m-%d %H:%M".to_string(),
+        source: DateSource::TaskCreated,
+        utc_offset: Some("+02:00".to_string()),
+    };
+    let zk_handler = ZkHandler::new(
+        "/tmp".into(),
+        JournalSettings::default(),
+        note_date_settings,
+        None,
+        PathBuf::from("zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    );
+    let details = TaskDetails {
+        created_at: Some("2025-01-02T22:30:00.000000Z".to_string()),
+        ..Default::default()
+    };
+    let task_data = TaskData::Interactive("note".to_string(), None, None, vec![], vec![]);
+    assert!(zk_handler.add_to_zk_pd(&mut pd, &task_data, &details, &None));
+    let res = pd.to_zk_text(&None);
+    let expected = "---\ndate: 2025-01-03 00:30\ntags: [inbox]\n---\n\n# title";
+    assert_eq!(res, expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_post_create_hook() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    let marker = dir.join("marker");
+    let note = dir.join("note.md");
+    let hook = format!("echo {{file}} > {}", marker.to_string_lossy());
+    let zk_handler = ZkHandler::new(
+        dir.clone(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        Some(hook),
+        PathBuf::from("zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    );
+    zk_handler.run_post_create_hook(&note);
+    let res = std::fs::read_to_string(&marker).unwrap();
+    assert_eq!(res.trim(), note.to_string_lossy());
+}
+
+#[test]
+fn test_instantiate_template() {
+    use crate::parsing::zk_parsing::parse_zk_file;
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let templates = dir.join(".zk").join("templates");
+    std::fs::create_dir_all(&templates).unwrap();
+    std::fs::write(
+        templates.join("article.md"),
+        "---\ntags: [article]\n---\n\n# {{title}}\n- url::= \n- author::= ",
+    )
+    .unwrap();
+    let vars = vec![
+        ("title".to_string(), "Some Article".to_string()),
+        ("url".to_string(), "https://example.com".to_string()),
+    ];
+
+    let file =
+        instantiate_template(&dir, &templates, "article", &vars, &SlugOptions::default()).unwrap();
+    assert_eq!(file, dir.join("some-article.md"));
+    let pd = parse_zk_file(&file).unwrap();
+    let url = pd.get_all_document_components(&|dc| match dc {
+        DocumentComponent::Properties(props, _) => props.iter().any(|p| p.has_name("url")),
+        _ => false,
+    });
+    let DocumentComponent::Properties(props, _) = &url[0] else {
+        panic!("url property not found: {pd:?}");
+    };
+    assert_eq!(
+        props[0].values,
+        vec![PropValue::String("https://example.com".to_string())]
+    );
+    let text = std::fs::read_to_string(&file).unwrap();
+    assert!(text.contains("# Some Article"), "{text:?}");
+    assert!(
+        instantiate_template(&dir, &templates, "article", &vars, &SlugOptions::default()).is_err()
+    );
+}
+
+#[test]
+fn test_missing_zk_binary() {
+    let zk_handler = ZkHandler::new(
+        std::env::temp_dir(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("/nonexistent/pkmt_test/zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    );
+    let err = zk_handler.get_zk_journal_file().unwrap_err();
+    assert!(
+        err.to_string().contains("`zk` CLI was not found"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn test_create_note_native() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let templates = dir.join(".zk").join("templates");
+    std::fs::create_dir_all(&templates).unwrap();
+    let template = templates.join("article.md");
+    std::fs::write(
+        &template,
+        "---\ntags: [inbox]\n---\n\n# {{title}}\n- url::= ",
+    )
+    .unwrap();
+
+    let zk_handler = ZkHandler::new(
+        dir.clone(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("/nonexistent/pkmt_test/zk"),
+        NoteCreationSettings {
+            native: true,
+            id_scheme: IdScheme::Slug,
+            ..Default::default()
+        },
+        BTreeMap::new(),
+    );
+    let file = zk_handler.create_note("My Note", template).unwrap();
+    assert_eq!(file, dir.join("my-note.md"));
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "---\ntags: [inbox]\n---\n\n# My Note\n- url::= "
+    );
+    assert!(zk_handler.create_note("My Note", dir.join("x.md")).is_err());
+    // titles without letters or digits get a timestamp id instead of an empty file name
+    let template = dir.join(".zk").join("templates").join("article.md");
+    let file = zk_handler.create_note("???", template).unwrap();
+    let stem = file.file_stem().unwrap().to_string_lossy().to_string();
+    assert_eq!(stem.len(), 14);
+    assert!(stem.chars().all(|c| c.is_ascii_digit()));
+}
+
+#[test]
+fn test_creator_file_native() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let templates = dir.join(".zk").join("templates");
+    std::fs::create_dir_all(&templates).unwrap();
+    std::fs::write(templates.join("creator.md"), "# {{title}}\n").unwrap();
+
+    let zk_handler = ZkHandler::new(
+        dir.clone(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("/nonexistent/pkmt_test/zk"),
+        NoteCreationSettings {
+            native: true,
+            id_scheme: IdScheme::Slug,
+            ..Default::default()
+        },
+        BTreeMap::new(),
+    );
+    let lookup_path = dir.join("creator_lookup.toml");
+    let create = |title: &str, template| zk_handler.create_note(title, template);
+    let file = get_zk_creator_file(&lookup_path, &templates, "Jane Doe", create).unwrap();
+    assert_eq!(file, dir.join("jane-doe.md"));
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "# Jane Doe\n");
+    // the second lookup finds the note instead of creating another one
+    let create = |title: &str, template| zk_handler.create_note(title, template);
+    let again = get_zk_creator_file(&lookup_path, &templates, "Jane Doe", create).unwrap();
+    assert_eq!(again, file);
+}
+
+#[test]
+fn test_preview() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let templates = dir.join(".zk").join("templates");
+    std::fs::create_dir_all(&templates).unwrap();
+    std::fs::write(
+        templates.join("article.md"),
+        "---\ntags: [inbox]\n---\n\n# {{title}}\n- url::= ",
+    )
+    .unwrap();
+
+    let zk_handler = ZkHandler::new(
+        dir.clone(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("/nonexistent/pkmt_test/zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    )
+    .with_template_dir(templates);
+    let task_data = TaskData::Interactive(
+        "article.md".to_string(),
+        Some("https://example.com".to_string()),
+        Some("Some Article".to_string()),
+        vec!["rust".to_string()],
+        vec!["example".to_string()],
+    );
+    let preview = zk_handler
+        .preview(&task_data, &TaskDetails::default())
+        .unwrap();
+    assert!(preview.contains("# Some Article"), "{preview}");
+    assert!(preview.contains("tags: [inbox, rust]"), "{preview}");
+    assert!(preview.contains("https://example.com"), "{preview}");
+    // nothing is written
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[test]
+fn test_initial_status() {
+    use crate::parsing::zk_parsing::parse_zk_text;
+    let text = "---\ntags: [inbox]\n---\n\n# title\n- status ::= inbox\n- url ::= ";
+    let zk_handler = ZkHandler::new(
+        "/tmp".into(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::from([("yt_playlist".to_string(), "to-watch".to_string())]),
+    );
+    let playlist = TaskData::YtPlaylist(
+        "url".to_string(),
+        "channel".to_string(),
+        "title".to_string(),
+        vec![],
+    );
+    let mut pd = parse_zk_text(text, &None).unwrap();
+    zk_handler.set_initial_status(&mut pd, &playlist);
+    assert_eq!(
+        pd.to_zk_text(&None),
+        "---\ntags: [inbox]\n---\n\n# title\n- status ::= to-watch\n- url ::= "
+    );
+
+    let video = TaskData::Youtube(
+        "url".to_string(),
+        "title".to_string(),
+        "channel".to_string(),
+        vec![],
+        None,
+    );
+    let mut pd = parse_zk_text(text, &None).unwrap();
+    zk_handler.set_initial_status(&mut pd, &video);
+    assert!(pd.to_zk_text(&None).contains("- status ::= inbox"));
+}
+
+#[test]
+fn test_append_to_malformed_journal() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(&dir).unwrap();
+    let journal = dir.join("journal.md");
+    std::fs::write(
+        &journal,
+        "# Today
+```ad-note
+unclosed
+",
+    )
+    .unwrap();
+    let zk_handler = ZkHandler::new(
+        dir.clone(),
+        JournalSettings {
+            date_format: Some("journal.md".to_string()),
+            ..Default::default()
+        },
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    );
+    let mention = DocumentComponent::List(
+        vec![ListElem::new(ParsedDocument::ParsedText(vec![
+            DocumentComponent::FileLink(
+                MentionedFile::FileName("note.md".to_string()),
+                None,
+                Some("note".to_string()),
+            ),
+        ]))],
+        false,
+    );
+    assert!(zk_handler.append_to_zk_journal(mention).unwrap());
+    assert_eq!(
+        std::fs::read_to_string(&journal).unwrap(),
+        "# Today\n```ad-note\nunclosed\n- [note](note.md)"
+    );
+}
+
+#[test]
+fn test_template_dir_override() {
+    use crate::todoi::config::Settings;
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    let (notebook, shared) = (dir.join("notebook"), dir.join("shared_templates"));
+    std::fs::create_dir_all(notebook.join(".zk").join("templates")).unwrap();
+    std::fs::write(notebook.join(".zk/templates/local.md"), "# {{title}}").unwrap();
+    std::fs::create_dir_all(&shared).unwrap();
+    std::fs::write(shared.join("shared.md"), "# {{title}}").unwrap();
+
+    let mut settings = Settings::default();
+    assert_eq!(
+        settings.zk_template_dir(&notebook),
+        notebook.join(".zk/templates")
+    );
+    settings.zk_template_dir = Some(shared.clone());
+    let template_dir = settings.zk_template_dir(&notebook);
+    assert_eq!(template_dir, shared);
+
+    let zk_handler = ZkHandler::new(
+        notebook.clone(),
+        JournalSettings::default(),
+        NoteDateSettings::default(),
+        None,
+        PathBuf::from("/nonexistent/pkmt_test/zk"),
+        NoteCreationSettings::default(),
+        BTreeMap::new(),
+    )
+    .with_template_dir(template_dir.clone());
+    assert_eq!(
+        zk_handler.get_template_names().unwrap(),
+        vec!["shared.md".to_string()]
+    );
+
+    let vars = [("title".to_string(), "From Shared".to_string())];
+    let file = instantiate_template(
+        &notebook,
+        &template_dir,
+        "shared",
+        &vars,
+        &SlugOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(file, notebook.join("from-shared.md"));
+    assert!(
+        instantiate_template(
+            &notebook,
+            &template_dir,
+            "local",
+            &vars,
+            &SlugOptions::default()
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn test_discover_zk_root() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(dir.join(".zk")).unwrap();
+    std::fs::create_dir_all(dir.join("journal").join("2024")).unwrap();
+    // a `.zk` file does not mark a notebook
+    std::fs::write(dir.join("journal").join(".zk"), "").unwrap();
+
+    assert_eq!(
+        discover_zk_root(&dir.join("journal").join("2024")),
+        Some(dir.clone())
+    );
+    assert_eq!(discover_zk_root(&dir), Some(dir.clone()));
+}
@@ -3,7 +3,9 @@ use regex::Regex;
 
 use crate::util::{self, file_link_pattern, link_name_pattern};
 
-use super::{config::Config, todoist_api::TodoistTask, TaskData};
+use super::{
+    config::Config, handlers::TaskDataHandler, todoist_api::TodoistTask, TaskData, TaskDetails,
+};
 #[derive(Debug)]
 pub enum Resolution {
     ToHandle,
@@ -11,10 +13,13 @@ pub enum Resolution {
     Cancel,
 }
 
+/// lets the user choose the template of a task. If preview is given, the resulting note is shown on
+/// stderr and the task is skipped unless it is confirmed.
 pub fn get_interactive_data(
     task: &TodoistTask,
     template_names: &[String],
     config: &Config,
+    preview: Option<&dyn TaskDataHandler>,
 ) -> (Resolution, TaskData) {
     use Resolution::*;
     println!("{}", task.content);
@@ -52,7 +57,7 @@ pub fn get_interactive_data(
         let mut tags = vec![];
         let title = if let Some(title) = captures.get(1) {
             let title = title.as_str().to_string();
-            tags = config.get_keyword_tags(&title);
+            tags = config.get_keyword_tags_for(&title);
             Some(title)
         } else {
             println!("No title capture: {content}");
@@ -75,10 +80,20 @@ pub fn get_interactive_data(
             println!("No url capture: {content}");
             None
         };
-        (
-            ToHandle,
-            TaskData::Interactive(template_name.clone(), url.clone(), title, tags, sources),
-        )
+        let td = TaskData::Interactive(template_name.clone(), url.clone(), title, tags, sources);
+        if let Some(handler) = preview {
+            let details =
+                TaskDetails::from(task).with_url_property(config.settings.task_url_property());
+            match handler.preview(&td, &details) {
+                Ok(text) => eprintln!("{text}"),
+                Err(e) => eprintln!("Failed to render the preview: {e:?}"),
+            }
+            let answer = get_user_input("Create this note? (y/n)");
+            if !matches!(answer.as_deref(), Ok("y")) {
+                return (Skip, TaskData::Unhandled);
+            }
+        }
+        (ToHandle, td)
     } else {
         println!("No url match: {content:?} with {url_re:?}");
         (Skip, TaskData::Unhandled)
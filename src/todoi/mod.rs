@@ -1,26 +1,38 @@
+pub mod audit_log;
 pub mod config;
+mod handled_tasks;
 pub mod handlers;
 mod interactive;
 mod todoist_api;
 mod youtube_details;
+use chrono::{DateTime, Utc};
 use scraper::{Html, Selector};
-use std::{fmt::Debug, path::PathBuf, vec};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Debug,
+    path::PathBuf,
+    vec,
+};
 
 use anyhow::Result;
 use interactive::get_interactive_data;
 use regex::Regex;
+use serde::Serialize;
 use tracing::{debug, info, instrument};
 
+use handled_tasks::HandledTasks;
+
 use crate::{
-    document_component::{DocumentComponent, ListElem, ParsedDocument, PropValue},
+    document_component::{DocumentComponent, ListElem, ParsedDocument, PropValue, Property},
     parsing::{TextMode, parse_all_files_in_dir},
     todoi::{
         config::Config,
-        handlers::handle_tasks_main,
+        handlers::{TaskDataHandler, handle_tasks_main},
         interactive::Resolution,
-        todoist_api::{TodoistAPI, TodoistTask},
+        todoist_api::{CloseTask, TodoistAPI, TodoistTask, get_lonely_tasks},
         youtube_details::{youtube_details, youtube_playlist_details},
     },
+    util::html_decode,
 };
 
 pub fn get_list_elem_with_doc_elem(
@@ -30,45 +42,202 @@ pub fn get_list_elem_with_doc_elem(
     pd.get_list_elem(&|le| le.contents.components().iter().any(elem_selector))
 }
 
+/// options of a todoi run
+#[derive(Debug, Clone, Default)]
+pub struct TodoiOptions {
+    /// mark the handled tasks as completed
+    pub complete_tasks: bool,
+    /// only print the tasks that would be completed, the handled tasks are not recorded for
+    /// only_new
+    pub preview_completions: bool,
+    /// do not run the post-create hooks of the settings
+    pub no_hooks: bool,
+    /// also import the tasks completed since then
+    pub completed_since: Option<DateTime<Utc>>,
+    /// skip the tasks handled in a previous run
+    pub only_new: bool,
+    /// also handle inbox tasks with subtasks or a parent task
+    pub all_tasks: bool,
+    /// only handle the tasks whose content matches it
+    pub filter: Option<Regex>,
+    /// overrides the zk template directory of the settings
+    pub template_dir: Option<PathBuf>,
+    /// list the tasks none of the non-interactive handlers could classify at the end
+    pub report_unhandled: bool,
+    /// show the note of an interactive task before it is confirmed
+    pub preview: bool,
+    /// print the completed tasks as json
+    pub json: bool,
+}
+
 /// gathers tasks and calls the correct handler
-/// tasks are marked as completed if complete_tasks is set
-pub fn main(root_dir: PathBuf, complete_tasks: bool, mode: TextMode) -> Result<()> {
-    let config = Config::load()?;
+pub fn main(root_dir: PathBuf, mode: TextMode, options: TodoiOptions) -> Result<()> {
+    let TodoiOptions {
+        complete_tasks,
+        preview_completions,
+        no_hooks,
+        completed_since,
+        only_new,
+        all_tasks,
+        filter,
+        template_dir,
+        report_unhandled,
+        preview,
+        json,
+    } = options;
+    let mut config = Config::load()?;
+    if no_hooks {
+        config.settings.zk_hooks.post_create = None;
+    }
+    if template_dir.is_some() {
+        config.settings.zk_template_dir = template_dir;
+    }
     let todoist_api = TodoistAPI::new(&config.keys.todoist_api_key);
     let inbox = todoist_api.get_inbox()?;
 
     let mut inbox_tasks = todoist_api.get_project_tasks(&inbox)?;
-    inbox_tasks = todoist_api.get_lonely_tasks(&inbox_tasks);
+    if !all_tasks {
+        inbox_tasks = get_lonely_tasks(&inbox_tasks);
+    }
+    // already completed tasks are imported again, but must not be closed a second time
+    let mut already_completed = HashSet::new();
+    if let Some(since) = completed_since {
+        let completed: Vec<TodoistTask> = todoist_api
+            .get_completed_tasks(since)?
+            .into_iter()
+            .filter(|t| t.project_id.as_ref() == Some(&inbox.id))
+            .collect();
+        info!("Retrieved {} completed todoist tasks.", completed.len());
+        already_completed.extend(completed.iter().map(|t| t.id.clone()));
+        inbox_tasks.extend(completed);
+    }
+    if let Some(filter) = &filter {
+        inbox_tasks = filter_tasks(inbox_tasks, filter);
+    }
     inbox_tasks.sort_by_key(|t| t.content.clone());
     info!("Retrieved todoist tasks.");
     inbox_tasks.dedup_by_key(|t| t.content.clone());
+    let handled_path = HandledTasks::default_path();
+    let mut handled = HandledTasks::load(&handled_path)?;
+    // tasks handled in a previous run are still closed along with the new ones
+    let mut skipped = vec![];
+    if only_new {
+        (inbox_tasks, skipped) = handled.split_new(inbox_tasks);
+        info!(
+            "Skipping {} tasks handled in a previous run.",
+            skipped.len()
+        );
+    }
     debug!("mode: {mode:?}");
-    let completed_tasks = handle_tasks_main(&inbox_tasks, &config, mode, &root_dir)?;
-
-    if complete_tasks {
-        completed_tasks.iter().for_each(|t| {
-            let success = todoist_api.close_task(t);
-            if success {
-                println!("Marked task '{}' as completed", t.content);
-            } else {
-                println!("ERROR: Failed to Mark task '{}' as completed!", t.content);
-            }
-        });
+    let handled_tasks = handle_tasks_main(
+        &inbox_tasks,
+        &config,
+        mode,
+        &root_dir,
+        report_unhandled,
+        preview,
+    )?;
+    // a preview run must not hide the tasks from the next real run
+    if !preview_completions {
+        handled.record(&handled_tasks);
+        handled.save(&handled_path)?;
+    }
+    let completed_tasks: Vec<TodoistTask> = handled_tasks
+        .into_iter()
+        .chain(skipped)
+        .filter(|t| !already_completed.contains(&t.id))
+        .collect();
+
+    if complete_tasks || preview_completions {
+        let report = close_tasks(&todoist_api, &completed_tasks, preview_completions);
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            report.print();
+        }
     }
     Ok(())
 }
 
+/// keeps the tasks whose content matches filter
+fn filter_tasks(tasks: Vec<TodoistTask>, filter: &Regex) -> Vec<TodoistTask> {
+    tasks
+        .into_iter()
+        .filter(|t| filter.is_match(&t.content))
+        .collect()
+}
+
+/// a task that was (or would be) marked as completed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CompletedTask {
+    pub id: String,
+    pub content: String,
+}
+
+impl From<&TodoistTask> for CompletedTask {
+    fn from(t: &TodoistTask) -> Self {
+        CompletedTask {
+            id: t.id.clone(),
+            content: t.content.clone(),
+        }
+    }
+}
+
+/// result of marking the handled tasks as completed
+#[derive(Debug, Default, Serialize)]
+pub struct CompletionReport {
+    /// the tasks were only listed, nothing was closed
+    pub preview: bool,
+    /// tasks that were closed, or would be closed in a preview
+    pub completed: Vec<CompletedTask>,
+    /// tasks that could not be closed
+    pub failed: Vec<CompletedTask>,
+}
+
+impl CompletionReport {
+    pub fn print(&self) {
+        if self.preview {
+            println!("The following tasks would be marked as completed:");
+            self.completed
+                .iter()
+                .for_each(|t| println!("{}: '{}'", t.id, t.content));
+            return;
+        }
+        self.completed
+            .iter()
+            .for_each(|t| println!("Marked task '{}' as completed", t.content));
+        self.failed
+            .iter()
+            .for_each(|t| println!("ERROR: Failed to Mark task '{}' as completed!", t.content));
+    }
+}
+
+fn close_tasks(api: &dyn CloseTask, tasks: &[TodoistTask], preview: bool) -> CompletionReport {
+    let mut report = CompletionReport {
+        preview,
+        ..Default::default()
+    };
+    tasks.iter().for_each(|t| {
+        if preview || api.close_task(t) {
+            report.completed.push(t.into());
+        } else {
+            report.failed.push(t.into());
+        }
+    });
+    report
+}
+
 /// Adds the the given values to the first property in the pd with the given name. Does nothing if the property
 /// is not found
 #[instrument]
 fn fill_all_props_le(pd: &mut ListElem, properties: &[(&str, Vec<PropValue>)]) {
     properties.iter().for_each(|(prop_name, values)| {
         let property = pd.get_document_component_mut(&|dc| match dc {
-            DocumentComponent::Properties(props) => props.iter().any(|p| p.has_name(prop_name)),
+            DocumentComponent::Properties(props, _) => props.iter().any(|p| p.has_name(prop_name)),
             _ => false,
         });
         if let Some(prop) = property
-            && let DocumentComponent::Properties(props) = prop
+            && let DocumentComponent::Properties(props, _) = prop
         {
             props.iter_mut().for_each(|p| {
                 if p.has_name(prop_name) {
@@ -79,13 +248,20 @@ fn fill_all_props_le(pd: &mut ListElem, properties: &[(&str, Vec<PropValue>)]) {
     });
 }
 
+/// replaces the values of a status property
+fn set_status(p: &mut Property, status: &str) {
+    if p.has_name("status") {
+        p.values = vec![PropValue::String(status.to_string())];
+    }
+}
+
 fn get_task_data_non_interactive(
     tasks: &[TodoistTask],
     config: &Config,
 ) -> Vec<(TaskData, TodoistTask)> {
     let tasks = tasks.iter().map(|t| (handle_youtube_task(t, config), t));
     let tasks = tasks.map(|(td, task)| match td {
-        TaskData::Unhandled => (handle_sbs_task(task), task),
+        TaskData::Unhandled => (handle_sbs_task(task, config), task),
         _ => (td, task),
     });
     let tasks = tasks.map(|(td, task)| match td {
@@ -95,20 +271,43 @@ fn get_task_data_non_interactive(
     tasks.map(|(td, task)| (td, task.clone())).collect()
 }
 
+/// returns the tasks the non-interactive handlers could not classify
+fn unhandled_tasks(tasks: &[(TaskData, TodoistTask)]) -> Vec<TodoistTask> {
+    tasks
+        .iter()
+        .filter(|(td, _)| matches!(td, TaskData::Unhandled))
+        .map(|(_, task)| task.clone())
+        .collect()
+}
+
+fn print_unhandled(tasks: &[TodoistTask]) {
+    if tasks.is_empty() {
+        return;
+    }
+    println!("The following tasks could not be classified:");
+    tasks
+        .iter()
+        .for_each(|t| println!("{}: '{}'", t.id, t.content));
+}
+
+/// also returns the tasks that were unhandled before the interactive step. If preview is given,
+/// the notes of interactive tasks are rendered by it and have to be confirmed.
 fn get_task_data_full(
     tasks: &[TodoistTask],
     config: &Config,
     template_names: &[String],
-) -> Vec<(TaskData, TodoistTask)> {
+    preview: Option<&dyn TaskDataHandler>,
+) -> (Vec<(TaskData, TodoistTask)>, Vec<TodoistTask>) {
     let tasks = get_task_data_non_interactive(tasks, config);
+    let unhandled = unhandled_tasks(&tasks);
     // handle interactive
     let mut cancelled = false;
-    tasks
+    let tasks = tasks
         .into_iter()
         .map(|(td, task)| match td {
             TaskData::Unhandled => {
                 if !cancelled {
-                    let (res, td) = get_interactive_data(&task, template_names, config);
+                    let (res, td) = get_interactive_data(&task, template_names, config, preview);
                     println!("interactive resolution for {task:?}: {res:?} with {td:?}");
                     if let Resolution::Cancel = res {
                         cancelled = true;
@@ -120,14 +319,79 @@ fn get_task_data_full(
             }
             _ => (td, task),
         })
-        .collect()
+        .collect();
+    (tasks, unhandled)
 }
 
-#[derive(Debug)]
+/// details of the todoist task that are added to the created note
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskDetails {
+    pub labels: Vec<String>,
+    pub priority: Option<u8>,
+    pub due: Option<String>,
+    /// rfc3339 timestamp of the task creation
+    pub created_at: Option<String>,
+    /// link to the task
+    pub url: Option<String>,
+    /// property the link is added to (default: DEFAULT_TASK_URL_PROPERTY)
+    pub url_property: Option<String>,
+}
+
+impl From<&TodoistTask> for TaskDetails {
+    fn from(task: &TodoistTask) -> Self {
+        Self {
+            labels: task.labels.clone(),
+            priority: task.priority,
+            due: task.due.as_ref().map(|d| d.date.clone()),
+            created_at: task.created_at.clone(),
+            url: task.url.clone(),
+            url_property: None,
+        }
+    }
+}
+
+impl TaskDetails {
+    /// returns the given tags extended by the task labels
+    pub fn merge_tags(&self, tags: &[String]) -> Vec<String> {
+        let mut res = tags.to_vec();
+        self.labels.iter().for_each(|l| {
+            if !res.contains(l) {
+                res.push(l.clone());
+            }
+        });
+        res
+    }
+
+    pub fn with_url_property(mut self, url_property: String) -> Self {
+        self.url_property = Some(url_property);
+        self
+    }
+
+    /// returns the values of the priority, due and task url properties if they are set
+    pub fn properties(&self) -> Vec<(&str, Vec<PropValue>)> {
+        let mut res = vec![];
+        if let Some(priority) = self.priority {
+            res.push(("priority", vec![PropValue::String(priority.to_string())]));
+        }
+        if let Some(due) = &self.due {
+            res.push(("due", vec![PropValue::String(due.clone())]));
+        }
+        if let Some(url) = &self.url {
+            let name = self
+                .url_property
+                .as_deref()
+                .unwrap_or(config::DEFAULT_TASK_URL_PROPERTY);
+            res.push((name, vec![PropValue::String(url.clone())]));
+        }
+        res
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum TaskData {
     Unhandled,
-    /// url, title, channel, tags
-    Youtube(String, String, String, Vec<String>),
+    /// url, title, channel, tags, optional start time in seconds
+    Youtube(String, String, String, Vec<String>, Option<u64>),
     /// url, optional author, optional title, tags, optional description
     Sbs(
         String,
@@ -136,8 +400,8 @@ pub enum TaskData {
         Vec<String>,
         Option<String>,
     ),
-    /// url, channel, title
-    YtPlaylist(String, String, String),
+    /// url, channel, title, tags
+    YtPlaylist(String, String, String, Vec<String>),
     /// template_name, optional url, optional title, tags, sources
     Interactive(
         String,
@@ -152,9 +416,9 @@ impl TaskData {
     fn get_title(&self) -> Option<String> {
         use TaskData::*;
         match self {
-            Youtube(_, title, _, _) => Some(title.to_string()),
+            Youtube(_, title, _, _, _) => Some(title.to_string()),
             Sbs(_, _, title, _, _) => title.clone(),
-            YtPlaylist(_, _, title) => Some(title.to_string()),
+            YtPlaylist(_, _, title, _) => Some(title.to_string()),
             Interactive(_, _, title, _, _) => title.clone(),
             _ => None,
         }
@@ -163,20 +427,41 @@ impl TaskData {
         use TaskData::*;
         match self {
             Unhandled => vec![],
-            Youtube(_, _, _, tags) => tags.clone(),
+            Youtube(_, _, _, tags, _) => tags.clone(),
             Sbs(_, _, _, tags, _) => tags.clone(),
-            YtPlaylist(_, _, _) => vec![],
+            YtPlaylist(_, _, _, tags) => tags.clone(),
             Interactive(_, _, _, tags, _) => tags.clone(),
         }
     }
 
+    /// name used for per task type settings: `youtube`, `sbs`, `yt_playlist` or the template name
+    /// of interactive tasks
+    fn kind(&self) -> Option<&str> {
+        use TaskData::*;
+        match self {
+            Unhandled => None,
+            Youtube(..) => Some("youtube"),
+            Sbs(..) => Some("sbs"),
+            YtPlaylist(..) => Some("yt_playlist"),
+            Interactive(template_name, ..) => Some(template_name.trim_end_matches(".md")),
+        }
+    }
+
+    /// status configured for notes of this task type
+    fn initial_status<'a>(
+        &self,
+        initial_status: &'a BTreeMap<String, String>,
+    ) -> Option<&'a String> {
+        self.kind().and_then(|kind| initial_status.get(kind))
+    }
+
     fn get_url(&self) -> Option<&str> {
         use TaskData::*;
         match self {
             Unhandled => None,
-            Youtube(url, _, _, _) => Some(url),
+            Youtube(url, _, _, _, _) => Some(url),
             Sbs(url, _, _, _, _) => Some(url),
-            YtPlaylist(url, _, _) => Some(url),
+            YtPlaylist(url, _, _, _) => Some(url),
             Interactive(_, url, _, _, _) => url.as_deref(),
         }
     }
@@ -190,24 +475,50 @@ fn handle_youtube_task(task: &TodoistTask, config: &Config) -> TaskData {
         && let Some(video_url) = m.get(0)
     {
         let video_url = video_url.as_str();
-        if let Ok((video_title, authors)) = youtube_details(video_url, &config.keys.yt_api_key) {
+        if let Ok((video_title, authors, description)) =
+            youtube_details(video_url, &config.keys.yt_api_key)
+        {
             let mut tags = vec![];
 
             if let Some(mut ct) = config.get_channel_tags(&authors) {
                 tags.append(&mut ct);
             }
 
-            tags.append(&mut config.get_keyword_tags(&video_title));
+            tags.append(&mut get_content_keyword_tags(
+                config,
+                &video_title,
+                Some(&description),
+            ));
             tags.sort();
             tags.dedup();
-            return TaskData::Youtube(video_url.into(), video_title, authors, tags);
+            return youtube_task_data(video_url, video_title, authors, tags);
         }
     }
     TaskData::Unhandled
 }
 
-#[instrument]
-fn handle_sbs_task(task: &TodoistTask) -> TaskData {
+fn youtube_task_data(url: &str, title: String, channel: String, tags: Vec<String>) -> TaskData {
+    TaskData::Youtube(url.into(), title, channel, tags, youtube_timestamp(url))
+}
+
+/// start time in seconds of the `t=` parameter of a youtube url, given in seconds (`90`, `90s`)
+/// or with units (`1m30s`, `1h2m`)
+fn youtube_timestamp(url: &str) -> Option<u64> {
+    let t_re = Regex::new(r"[?&#]t=(?:(\d+)h)?(?:(\d+)m)?(?:(\d+)s?)?(?:$|&)").unwrap();
+    let c = t_re.captures(url)?;
+    if c.get(1).is_none() && c.get(2).is_none() && c.get(3).is_none() {
+        return None;
+    }
+    let part = |i: usize| {
+        c.get(i)
+            .and_then(|m| m.as_str().parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    Some(part(1) * 3600 + part(2) * 60 + part(3))
+}
+
+#[instrument(skip(config))]
+fn handle_sbs_task(task: &TodoistTask, config: &Config) -> TaskData {
     let sbs_link_re =
         Regex::new(r"https://ckarchive\.com/b/[a-zA-Z0-9]*\?ck_subscriber_id=2334581400").unwrap();
     let sbs_website_re = Regex::new(r"https://www.strongerbyscience.com/[0-9a-zA-Z-]+/").unwrap();
@@ -231,7 +542,7 @@ fn handle_sbs_task(task: &TodoistTask) -> TaskData {
         let text = runtime.block_on(res.text()).unwrap();
 
         let author = if let Some(author) = author_re.captures(&text) {
-            let mut author = author.get(1).unwrap().as_str().to_string();
+            let mut author = html_decode(author.get(1).unwrap().as_str());
             if author.ends_with('.') {
                 author.remove(author.len() - 1);
             }
@@ -251,14 +562,15 @@ fn handle_sbs_task(task: &TodoistTask) -> TaskData {
             None
         };
 
-        let title = if let (Some(start), Some(end)) = (text.find("<title>"), text.find("</title>"))
-        {
-            let title = text[start + 7..end].trim_end_matches(" &#8226; Stronger by Science");
-            Some(title.to_string())
-        } else {
-            None
-        };
-        let tags = vec!["fitness".to_string()];
+        let title = sbs_title(&doc, &config.settings.sbs.title_suffix);
+        let mut tags = vec!["fitness".to_string()];
+        tags.append(&mut get_content_keyword_tags(
+            config,
+            title.as_deref().unwrap_or_default(),
+            desc.as_deref(),
+        ));
+        tags.sort();
+        tags.dedup();
         let res = TaskData::Sbs(article_url.to_string(), author, title, tags, desc);
         debug!("found {res:?} for {task:?}");
         return res;
@@ -267,6 +579,25 @@ fn handle_sbs_task(task: &TodoistTask) -> TaskData {
     TaskData::Unhandled
 }
 
+/// returns the `og:title` or `<title>` of the page with the suffix matching the
+/// title_suffix pattern removed
+fn sbs_title(doc: &Html, title_suffix: &str) -> Option<String> {
+    let og_title = Selector::parse("meta[property=\"og:title\"]").unwrap();
+    let title = Selector::parse("title").unwrap();
+    let title = doc
+        .select(&og_title)
+        .find_map(|e| e.value().attr("content").map(|t| t.to_string()))
+        .or_else(|| doc.select(&title).next().map(|e| e.text().collect()))?;
+    let title = match Regex::new(&format!("(?:{title_suffix})$")) {
+        Ok(suffix_re) => suffix_re.replace(title.trim(), "").to_string(),
+        Err(e) => {
+            println!("Invalid sbs title suffix pattern {title_suffix:?}: {e}");
+            title
+        }
+    };
+    Some(title.trim().to_string())
+}
+
 fn handle_youtube_playlist(task: &TodoistTask, config: &Config) -> TaskData {
     let playlist_re = Regex::new(r"https://www\.youtube\.com/playlist\?list=[a-zA-Z0-9]+").unwrap();
     if playlist_re.captures(&task.content).is_some() {
@@ -274,19 +605,35 @@ fn handle_youtube_playlist(task: &TodoistTask, config: &Config) -> TaskData {
         if let Ok((description, channel)) =
             youtube_playlist_details(&playlist_url, &config.keys.yt_api_key)
         {
-            return TaskData::YtPlaylist(playlist_url, channel, description);
+            let tags = get_content_keyword_tags(config, &description, None);
+            return TaskData::YtPlaylist(playlist_url, channel, description, tags);
         }
     }
     TaskData::Unhandled
 }
 
+/// returns the keyword tags matching the title or the description
+fn get_content_keyword_tags(
+    config: &Config,
+    title: &str,
+    description: Option<&str>,
+) -> Vec<String> {
+    let mut tags = config.get_keyword_tags_for(title);
+    if let Some(description) = description {
+        tags.append(&mut config.get_keyword_tags_for(description));
+    }
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
 fn url_is_duplicate(url: &str, root_dir: &PathBuf, mode: &TextMode) -> Result<bool> {
     let parsed_documents = parse_all_files_in_dir(root_dir, mode)?;
     let mut res = false;
     parsed_documents.iter().for_each(|pd| {
         if pd
             .get_document_component(&|dc: &DocumentComponent| {
-                if let DocumentComponent::Properties(props) = dc {
+                if let DocumentComponent::Properties(props, _) = dc {
                     props.iter().any(|p| {
                         p.has_name("url") && p.has_value(&PropValue::String(url.to_string()))
                     })
@@ -301,3 +648,157 @@ fn url_is_duplicate(url: &str, root_dir: &PathBuf, mode: &TextMode) -> Result<bo
     });
     Ok(res)
 }
+
+#[test]
+fn test_preview_does_not_close_tasks() {
+    use std::cell::Cell;
+    struct MockAPI {
+        closed: Cell<usize>,
+    }
+    impl CloseTask for MockAPI {
+        fn close_task(&self, _task: &TodoistTask) -> bool {
+            self.closed.set(self.closed.get() + 1);
+            true
+        }
+    }
+    let api = MockAPI {
+        closed: Cell::new(0),
+    };
+    let tasks = vec![TodoistTask {
+        id: "1".to_string(),
+        content: "task".to_string(),
+        parent_id: None,
+        project_id: None,
+        labels: vec![],
+        priority: None,
+        due: None,
+        created_at: None,
+        url: None,
+    }];
+    let report = close_tasks(&api, &tasks, true);
+    assert_eq!(api.closed.get(), 0);
+    assert_eq!(report.completed, vec![CompletedTask::from(&tasks[0])]);
+    assert!(
+        serde_json::to_string(&report)
+            .unwrap()
+            .contains(r#""preview":true"#)
+    );
+    let report = close_tasks(&api, &tasks, false);
+    assert_eq!(api.closed.get(), 1);
+    assert!(!report.preview);
+    assert_eq!(report.completed.len(), 1);
+}
+
+#[test]
+fn test_filter_tasks() {
+    let task = |id: &str, content: &str| TodoistTask {
+        id: id.to_string(),
+        content: content.to_string(),
+        parent_id: None,
+        project_id: None,
+        labels: vec![],
+        priority: None,
+        due: None,
+        created_at: None,
+        url: None,
+    };
+    let tasks = vec![
+        task("1", "https://example.com"),
+        task("2", "buy milk"),
+        task("3", "paper @read"),
+    ];
+    let filter = Regex::new(r"^https?://|@read").unwrap();
+    assert_eq!(
+        filter_tasks(tasks, &filter),
+        vec![task("1", "https://example.com"), task("3", "paper @read")]
+    );
+}
+
+#[test]
+fn test_sbs_title() {
+    let doc = Html::parse_document(include_str!("test_data/sbs_article.html"));
+    let suffix = config::SbsSettings::default().title_suffix;
+    assert_eq!(
+        sbs_title(&doc, &suffix),
+        Some("Squats & Deadlifts: What\u{2019}s the Deal?".to_string())
+    );
+    assert_eq!(
+        sbs_title(&doc, ""),
+        Some(
+            "Squats & Deadlifts: What\u{2019}s the Deal? \u{2022} Stronger by Science".to_string()
+        )
+    );
+    // scraper already decodes entities, so escaped entities must stay as they are
+    let doc = Html::parse_document("<html><head><title>a &amp;lt; b</title></head></html>");
+    assert_eq!(sbs_title(&doc, ""), Some("a &lt; b".to_string()));
+}
+
+#[test]
+fn test_youtube_timestamp() {
+    let video = |url: &str| youtube_task_data(url, "title".into(), "channel".into(), vec![]);
+    let timestamp = |td: TaskData| match td {
+        TaskData::Youtube(_, _, _, _, timestamp) => timestamp,
+        other => panic!("not a video: {other:?}"),
+    };
+    assert_eq!(
+        timestamp(video("https://www.youtube.com/watch?v=abc&t=90")),
+        Some(90)
+    );
+    assert_eq!(
+        timestamp(video("https://www.youtube.com/watch?v=abc&t=1m30s")),
+        Some(90)
+    );
+    assert_eq!(
+        timestamp(video("https://youtu.be/abc?t=1h2m3s")),
+        Some(3723)
+    );
+    assert_eq!(timestamp(video("https://youtu.be/abc")), None);
+    assert_eq!(timestamp(video("https://youtu.be/abc?si=x&t=")), None);
+}
+
+#[test]
+fn test_keyword_tags_from_description() {
+    use config::{Keys, Settings, Tags};
+    let tags: Tags = toml::from_str(
+        "yt_tag = []\n[[kw_tag]]\nkeyword = \"Hypertrophy\"\ntags = [\"fitness/hypertrophy\"]",
+    )
+    .unwrap();
+    let keys = Keys {
+        yt_api_key: String::new(),
+        todoist_api_key: String::new(),
+    };
+    let config = Config::new(keys, tags, Settings::default());
+    let tags = get_content_keyword_tags(
+        &config,
+        "Some article",
+        Some("A deep dive into hypertrophy research"),
+    );
+    assert_eq!(tags, vec!["fitness/hypertrophy".to_string()]);
+    assert!(get_content_keyword_tags(&config, "Some article", None).is_empty());
+}
+
+#[test]
+fn test_report_unhandled() {
+    use config::{Keys, Settings, Tags};
+    let tags: Tags = toml::from_str("yt_tag = []\nkw_tag = []").unwrap();
+    let keys = Keys {
+        yt_api_key: String::new(),
+        todoist_api_key: String::new(),
+    };
+    let config = Config::new(keys, tags, Settings::default());
+    let task = TodoistTask {
+        id: "1".to_string(),
+        content: "buy milk".to_string(),
+        parent_id: None,
+        project_id: None,
+        labels: vec![],
+        priority: None,
+        due: None,
+        created_at: None,
+        url: None,
+    };
+    let tasks = get_task_data_non_interactive(std::slice::from_ref(&task), &config);
+    let unhandled = unhandled_tasks(&tasks);
+    assert_eq!(unhandled.len(), 1);
+    assert_eq!(unhandled[0].content, "buy milk");
+}
@@ -1,7 +1,10 @@
 use std::collections::HashSet;
 
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+
+use crate::error::PkmtError;
 /*
 {"id": "2168048243",
                 "parent_id": null,
@@ -19,7 +22,7 @@ use serde::Deserialize;
 */
 #[derive(Deserialize, Debug)]
 pub struct TodoistProject {
-    id: String,
+    pub id: String,
     is_inbox_project: bool,
 }
 
@@ -53,11 +56,108 @@ pub struct TodoistProject {
 */
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct TodoistTask {
-    id: String,
+    pub id: String,
     pub content: String,
     pub parent_id: Option<String>,
+    pub project_id: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// 1 (normal) to 4 (urgent)
+    pub priority: Option<u8>,
+    pub due: Option<TodoistDue>,
+    #[serde(alias = "added_at")]
+    pub created_at: Option<String>,
+    /// link to the task in the todoist app
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TodoistDue {
+    pub date: String,
+    pub datetime: Option<String>,
+}
+
+/*
+{
+    "items": [
+        {
+            "content": "Buy Milk",
+            "meta_data": null,
+            "user_id": "2671355",
+            "task_id": "2995104339",
+            "note_count": 0,
+            "project_id": "2203306141",
+            "section_id": "7025",
+            "completed_at": "2015-02-17T15:40:41.000000Z",
+            "id": "1899066186"
+        }
+    ],
+    "projects": {},
+    "sections": {}
+}
+*/
+#[derive(Deserialize, Debug)]
+struct CompletedItems {
+    items: Vec<CompletedItem>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CompletedItem {
+    task_id: String,
+    content: String,
+    project_id: Option<String>,
 }
 
+impl From<CompletedItem> for TodoistTask {
+    fn from(item: CompletedItem) -> Self {
+        TodoistTask {
+            id: item.task_id,
+            content: item.content,
+            parent_id: None,
+            project_id: item.project_id,
+            labels: vec![],
+            priority: None,
+            due: None,
+            created_at: None,
+            url: None,
+        }
+    }
+}
+
+/// parses a response of the sync api's completed items endpoint
+fn parse_completed_items(text: &str) -> Result<Vec<TodoistTask>> {
+    let items: CompletedItems =
+        serde_json::from_str(text).context(format!("Could not parse {text}"))?;
+    Ok(items.items.into_iter().map(TodoistTask::from).collect())
+}
+
+/// returns the tasks that are neither subtasks nor parents of other tasks in tasks.
+/// Subtasks are excluded even if their parent is not contained in tasks.
+pub fn get_lonely_tasks(tasks: &[TodoistTask]) -> Vec<TodoistTask> {
+    let ids_to_filter: HashSet<String> = tasks
+        .iter()
+        .filter_map(|t| {
+            t.parent_id
+                .as_ref()
+                .map(|parent_id| (t.id.clone(), parent_id.clone()))
+        })
+        .flat_map(|(a, b)| [a.to_string(), b.to_string()])
+        .collect();
+    tasks
+        .iter()
+        .filter(|t| !ids_to_filter.contains(&t.id))
+        .cloned()
+        .collect()
+}
+
+pub trait CloseTask {
+    /// marks the task as completed, returns true on success
+    fn close_task(&self, task: &TodoistTask) -> bool;
+}
+
+const PROJECTS_URL: &str = "https://api.todoist.com/rest/v2/projects";
+
 pub struct TodoistAPI {
     todoist_api_key: String,
     runtime: tokio::runtime::Runtime,
@@ -71,66 +171,93 @@ impl TodoistAPI {
         }
     }
 
-    pub fn get_inbox(&self) -> Result<TodoistProject> {
-        let tmp = self
-            .get_all_projects()
+    pub fn get_inbox(&self) -> Result<TodoistProject, PkmtError> {
+        self.get_all_projects()?
             .into_iter()
-            .find(|p| p.is_inbox_project);
-        tmp.context("Inbox does not exist!")
+            .find(|p| p.is_inbox_project)
+            .ok_or_else(|| PkmtError::Config("Inbox project not found".to_string()))
     }
 
-    pub fn get_project_tasks(&self, project: &TodoistProject) -> Result<Vec<TodoistTask>> {
+    pub fn get_project_tasks(
+        &self,
+        project: &TodoistProject,
+    ) -> Result<Vec<TodoistTask>, PkmtError> {
+        let url = "https://api.todoist.com/rest/v2/tasks";
         let res = self
-            .req_base("https://api.todoist.com/rest/v2/tasks")
+            .req_base(url)
             .query(&[("project_id", &project.id)])
             .send();
-        let res = self.runtime.block_on(res)?;
+        let res = self
+            .runtime
+            .block_on(res)
+            .map_err(|e| PkmtError::network(url, e))?;
         if res.status() != 200 {
             println!(
                 "ERROR: failed to retrieve Todoist tasks for project {}!",
                 project.id
             );
         }
-        let text = self.runtime.block_on(res.text())?;
-        serde_json::from_str(&text).context(format!("Could not parse {text}"))
-    }
-
-    pub fn get_lonely_tasks(&self, tasks: &[TodoistTask]) -> Vec<TodoistTask> {
-        let ids_to_filter: HashSet<String> = tasks
-            .iter()
-            .filter_map(|t| {
-                t.parent_id
-                    .as_ref()
-                    .map(|parent_id| (t.id.clone(), parent_id.clone()))
-            })
-            .flat_map(|(a, b)| [a.to_string(), b.to_string()])
-            .collect();
-        tasks
-            .iter()
-            .filter(|t| !ids_to_filter.contains(&t.id))
-            .cloned()
-            .collect()
+        let text = self
+            .runtime
+            .block_on(res.text())
+            .map_err(|e| PkmtError::network(url, e))?;
+        serde_json::from_str(&text)
+            .context(format!("Could not parse {text}"))
+            .map_err(|e| PkmtError::network(url, e))
     }
 
-    pub fn close_task(&self, task: &TodoistTask) -> bool {
-        let res = self.req_base_post(&format!(
-            "https://api.todoist.com/rest/v2/tasks/{}/close",
-            task.id
-        ));
-        let res = self.runtime.block_on(res.send()).unwrap();
-        res.status().as_u16() == 204
+    /// returns the tasks completed since the given time. Only content and ids are set.
+    pub fn get_completed_tasks(&self, since: DateTime<Utc>) -> Result<Vec<TodoistTask>, PkmtError> {
+        // maximum page size of the endpoint
+        const LIMIT: usize = 200;
+        let url = "https://api.todoist.com/sync/v9/completed/get_all";
+        let since = since.format("%Y-%m-%dT%H:%M").to_string();
+        let mut res = vec![];
+        loop {
+            let req = self
+                .req_base(url)
+                .query(&[
+                    ("since", since.clone()),
+                    ("limit", LIMIT.to_string()),
+                    ("offset", res.len().to_string()),
+                ])
+                .send();
+            let resp = self
+                .runtime
+                .block_on(req)
+                .map_err(|e| PkmtError::network(url, e))?;
+            if resp.status() != 200 {
+                println!("ERROR: failed to retrieve completed Todoist tasks!");
+            }
+            let text = self
+                .runtime
+                .block_on(resp.text())
+                .map_err(|e| PkmtError::network(url, e))?;
+            let tasks = parse_completed_items(&text).map_err(|e| PkmtError::network(url, e))?;
+            let count = tasks.len();
+            res.extend(tasks);
+            if count < LIMIT {
+                break;
+            }
+        }
+        Ok(res)
     }
 
-    fn get_all_projects(&self) -> Vec<TodoistProject> {
-        let url = "https://api.todoist.com/rest/v2/projects";
-        let req = self.req_base(url).try_clone().unwrap();
-
-        let res = self.runtime.block_on(req.send()).unwrap();
+    fn get_all_projects(&self) -> Result<Vec<TodoistProject>, PkmtError> {
+        let res = self
+            .runtime
+            .block_on(self.req_base(PROJECTS_URL).send())
+            .map_err(|e| PkmtError::network(PROJECTS_URL, e))?;
         if res.status() != 200 {
             println!("ERROR: failed to retrieve projects from Todoist!");
         }
-        let text = self.runtime.block_on(res.text()).unwrap();
-        serde_json::from_str(&text).unwrap()
+        let text = self
+            .runtime
+            .block_on(res.text())
+            .map_err(|e| PkmtError::network(PROJECTS_URL, e))?;
+        serde_json::from_str(&text)
+            .context(format!("Could not parse {text}"))
+            .map_err(|e| PkmtError::network(PROJECTS_URL, e))
     }
 
     fn req_base(&self, url: &str) -> reqwest::RequestBuilder {
@@ -145,3 +272,75 @@ impl TodoistAPI {
             .header("Authorization", format!("Bearer {}", self.todoist_api_key))
     }
 }
+
+impl CloseTask for TodoistAPI {
+    fn close_task(&self, task: &TodoistTask) -> bool {
+        let res = self.req_base_post(&format!(
+            "https://api.todoist.com/rest/v2/tasks/{}/close",
+            task.id
+        ));
+        let res = self.runtime.block_on(res.send()).unwrap();
+        res.status().as_u16() == 204
+    }
+}
+
+#[test]
+fn test_parse_completed_items() {
+    let text = r#"{
+        "items": [
+            {
+                "content": "https://example.com",
+                "meta_data": null,
+                "user_id": "2671355",
+                "task_id": "2995104339",
+                "note_count": 0,
+                "project_id": "2203306141",
+                "section_id": null,
+                "completed_at": "2015-02-17T15:40:41.000000Z",
+                "id": "1899066186"
+            }
+        ],
+        "projects": {},
+        "sections": {}
+    }"#;
+    let tasks = parse_completed_items(text).unwrap();
+    assert_eq!(
+        tasks,
+        vec![TodoistTask {
+            id: "2995104339".to_string(),
+            content: "https://example.com".to_string(),
+            parent_id: None,
+            project_id: Some("2203306141".to_string()),
+            labels: vec![],
+            priority: None,
+            due: None,
+            created_at: None,
+            url: None,
+        }]
+    );
+}
+
+#[test]
+fn test_get_lonely_tasks() {
+    let task = |id: &str, parent_id: Option<&str>| TodoistTask {
+        id: id.to_string(),
+        content: format!("task {id}"),
+        parent_id: parent_id.map(|p| p.to_string()),
+        project_id: None,
+        labels: vec![],
+        priority: None,
+        due: None,
+        created_at: None,
+        url: None,
+    };
+    let tasks = vec![
+        task("lonely", None),
+        task("parent", None),
+        task("child", Some("parent")),
+        task("orphan", Some("not_in_inbox")),
+        task("grandchild", Some("child")),
+    ];
+    // parents, their subtasks and subtasks of tasks outside the list are excluded
+    assert_eq!(get_lonely_tasks(&tasks), vec![task("lonely", None)]);
+    assert_eq!(get_lonely_tasks(&[]), vec![]);
+}
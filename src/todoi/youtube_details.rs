@@ -1,8 +1,8 @@
 use anyhow::{Result, bail};
 use std::str::FromStr;
 
-/// returns (title, channel)
-pub fn youtube_details(video_url: &str, api_key: &str) -> Result<(String, String)> {
+/// returns (title, channel, description)
+pub fn youtube_details(video_url: &str, api_key: &str) -> Result<(String, String, String)> {
     let client = reqwest::Client::new();
     let resolved = client.get(video_url).send();
     let runtime = tokio::runtime::Runtime::new()?;
@@ -37,8 +37,9 @@ pub fn youtube_details(video_url: &str, api_key: &str) -> Result<(String, String
         let snippet = js["items"].pop()["snippet"].clone();
         let title = snippet["title"].to_string();
         let channel = snippet["channelTitle"].to_string();
+        let description = snippet["description"].to_string();
 
-        Ok((title, channel))
+        Ok((title, channel, description))
     } else {
         bail!("Could not extract url from {video_url}!");
     }
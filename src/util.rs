@@ -1,21 +1,159 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use regex::Captures;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument};
 
 pub const SPACES_PER_INDENT: usize = 4;
 
+/// built-in replacements applied to the text of a file before it is parsed
+pub fn default_substitutions() -> Vec<(String, String)> {
+    [
+        ("−", "-"),
+        ("—", "-"),
+        ("∗", "*"),
+        ("∈", "\\in "),
+        ("“", "\""),
+        ("”", "\""),
+        ("∃", "EXISTS"),
+        ("’", "'"),
+        ("–", "-"),
+    ]
+    .into_iter()
+    .map(|(from, to)| (from.to_string(), to.to_string()))
+    .collect()
+}
+
+/// applies the built-in substitutions
 pub fn apply_substitutions(text: &str) -> String {
-    text.replace(['−', '—'], "-")
-        .replace('∗', "*")
-        .replace('∈', "\\in ")
-        .replace("“", "\"")
-        .replace("”", "\"")
-        .replace("∃", "EXISTS")
-        .replace("’", "'")
-        .replace("–", "-")
-        .replace("“", "\"")
+    apply_substitution_table(text, &default_substitutions())
+}
+
+/// applies the substitutions in order, empty patterns are skipped
+pub fn apply_substitution_table(text: &str, substitutions: &[(String, String)]) -> String {
+    substitutions
+        .iter()
+        .filter(|(from, _)| !from.is_empty())
+        .fold(text.to_string(), |acc, (from, to)| acc.replace(from, to))
+}
+
+/// replaces from with to and updates offsets (which has an entry for every byte of text and its
+/// end) accordingly. Replaced bytes map to the start of the replaced text.
+pub fn replace_mapped(text: &str, offsets: &[usize], from: &str, to: &str) -> (String, Vec<usize>) {
+    let mut res = String::new();
+    let mut res_offsets = vec![];
+    let mut last = 0;
+    for (i, _) in text.match_indices(from) {
+        res.push_str(&text[last..i]);
+        res_offsets.extend_from_slice(&offsets[last..i]);
+        res.push_str(to);
+        res_offsets.extend(std::iter::repeat_n(offsets[i], to.len()));
+        last = i + from.len();
+    }
+    res.push_str(&text[last..]);
+    res_offsets.extend_from_slice(&offsets[last..]);
+    (res, res_offsets)
+}
+
+/// replaces named and numeric html entities, unknown entities are kept as is
+pub fn html_decode(text: &str) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some(' '),
+                    "lsquo" => Some('\u{2018}'),
+                    "rsquo" => Some('\u{2019}'),
+                    "ldquo" => Some('\u{201c}'),
+                    "rdquo" => Some('\u{201d}'),
+                    "ndash" => Some('\u{2013}'),
+                    "mdash" => Some('\u{2014}'),
+                    "hellip" => Some('\u{2026}'),
+                    "bull" => Some('\u{2022}'),
+                    _ => None,
+                }
+            };
+            c.map(|c| (c, end))
+        });
+        if let Some((c, end)) = decoded {
+            res.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            res.push('&');
+            rest = &rest[1..];
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+/// unit of the indentation of nested list items
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentUnit {
+    Tab,
+    Spaces(usize),
+}
+
+impl IndentUnit {
+    /// detects the unit from the indentation of the list items in text, lines in code fences are
+    /// ignored. Text without indented items uses SPACES_PER_INDENT spaces.
+    pub fn detect(text: &str) -> Self {
+        let mut unit = 0;
+        let mut in_fence = false;
+        for line in text.lines() {
+            let rest = line.trim_start_matches([' ', '\t']);
+            if rest.starts_with("```") {
+                in_fence = !in_fence;
+                continue;
+            }
+            if in_fence || !(rest.starts_with("- ") || rest == "-") {
+                continue;
+            }
+            let indent = &line[..line.len() - rest.len()];
+            if indent.contains('\t') {
+                return IndentUnit::Tab;
+            }
+            unit = gcd(unit, indent.len());
+        }
+        if unit == 0 {
+            IndentUnit::Spaces(SPACES_PER_INDENT)
+        } else {
+            IndentUnit::Spaces(unit)
+        }
+    }
+
+    /// one level of indentation
+    pub fn to_text(&self) -> String {
+        match self {
+            IndentUnit::Tab => "\t".to_string(),
+            IndentUnit::Spaces(n) => " ".repeat(*n),
+        }
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
 }
 
 pub fn indent_spaces(line: &str) -> usize {
@@ -75,9 +213,13 @@ pub fn starts_with_blank_line(text: &str) -> bool {
     }
 }
 
+/// files below root_dir with one of the allowed extensions (all if None). With max_depth, only
+/// files in at most that many levels of subdirectories are returned, 0 means root_dir only.
+/// The files are sorted by path.
 pub fn files_in_tree<T: AsRef<Path>>(
     root_dir: T,
     allowed_extensions: &Option<Vec<&str>>,
+    max_depth: Option<usize>,
 ) -> Result<Vec<PathBuf>> {
     let mut res = vec![];
     let root_dir = root_dir.as_ref().canonicalize()?;
@@ -85,8 +227,10 @@ pub fn files_in_tree<T: AsRef<Path>>(
     let tmp: Result<()> = dir_entry.into_iter().try_for_each(|f| {
         let path = f.unwrap().path();
         if path.is_dir() {
-            let rec = files_in_tree(&path, allowed_extensions)?;
-            res.extend(rec);
+            if max_depth != Some(0) {
+                let rec = files_in_tree(&path, allowed_extensions, max_depth.map(|d| d - 1))?;
+                res.extend(rec);
+            }
         } else if let Some(ext) = path.extension() {
             if let Some(extensions) = allowed_extensions {
                 if extensions.contains(&ext.to_str().unwrap_or("should not be found")) {
@@ -101,18 +245,138 @@ pub fn files_in_tree<T: AsRef<Path>>(
     if tmp.is_err() {
         bail!("Encountered error: {tmp:?}!")
     }
+    // read_dir returns the entries in filesystem order
+    res.sort();
+    Ok(res)
+}
+
+/// returns all directories below root_dir (excluding root_dir itself) in at most max_depth
+/// levels. Hidden directories and symlinks are skipped
+pub fn dirs_in_tree<T: AsRef<Path>>(root_dir: T, max_depth: Option<usize>) -> Result<Vec<PathBuf>> {
+    let root_dir = root_dir.as_ref().canonicalize()?;
+    let mut res = vec![];
+    if max_depth == Some(0) {
+        return Ok(res);
+    }
+    for entry in root_dir.read_dir()? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+        if path.is_dir() && !path.is_symlink() && !hidden {
+            res.extend(dirs_in_tree(&path, max_depth.map(|d| d - 1))?);
+            res.push(path);
+        }
+    }
     Ok(res)
 }
 
+/// writes contents to a temporary file next to path and renames it to path, so path never holds
+/// a partially written file
+pub fn write_atomic<T: AsRef<Path>>(path: T, contents: &str) -> Result<()> {
+    let path = path.as_ref();
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".pkmt-tmp");
+    let tmp = path.with_file_name(tmp_name);
+    std::fs::write(&tmp, contents).context(format!("Could not write {tmp:?}"))?;
+    std::fs::rename(&tmp, path).context(format!("Could not move {tmp:?} to {path:?}"))
+}
+
+/// how file names are derived from titles
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SlugOptions {
+    pub lowercase: bool,
+    /// replaces whitespace and punctuation between words
+    pub separator: String,
+    /// replace umlauts and `ß` by `ae`, `oe`, `ue` and `ss`
+    pub transliterate: bool,
+    /// maximum number of characters
+    pub max_len: Option<usize>,
+}
+
+impl Default for SlugOptions {
+    fn default() -> Self {
+        Self {
+            lowercase: true,
+            separator: "-".to_string(),
+            transliterate: true,
+            max_len: None,
+        }
+    }
+}
+
+/// file name for a note with the given title. Quotes and apostrophes are removed, runs of other
+/// characters that are not alphanumeric are replaced by the separator.
+pub fn slugify(title: &str, opts: &SlugOptions) -> String {
+    let mut words = vec![];
+    let mut word = String::new();
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            let transliterated = match c {
+                'ä' => "ae",
+                'ö' => "oe",
+                'ü' => "ue",
+                'Ä' => "Ae",
+                'Ö' => "Oe",
+                'Ü' => "Ue",
+                'ß' => "ss",
+                _ => "",
+            };
+            if opts.transliterate && !transliterated.is_empty() {
+                word.push_str(transliterated);
+            } else {
+                word.push(c);
+            }
+        } else if !matches!(c, '\'' | '"' | '`' | '’' | '‘' | '“' | '”') && !word.is_empty()
+        {
+            words.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    let mut slug = words.join(&opts.separator);
+    if opts.lowercase {
+        slug = slug.to_lowercase();
+    }
+    if let Some(max_len) = opts.max_len
+        && slug.chars().count() > max_len
+    {
+        slug = slug.chars().take(max_len).collect();
+        slug = slug.trim_end_matches(opts.separator.as_str()).to_string();
+    }
+    slug
+}
+
 pub fn link_name_pattern() -> String {
     r####"((?:[\sa-zA-ZüäöÜÄÖ0-9'’’?!\.:\-/|•·$§@~&+%,()\\{}\[\]#*"]|[^\u0000-\u007F])+)"####
         .to_string()
 }
 
+/// like link_name_pattern, but matches as little as possible so that a link followed by other
+/// links on the same line ends at its own `](`
+pub fn shortest_link_name_pattern() -> String {
+    format!("{}?)", link_name_pattern().strip_suffix(')').unwrap())
+}
+
+static URL_SCHEME_RE: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.\-]*://").unwrap());
+
+/// whether a link target is a url like `https://...` or `mailto:...` rather than a local file
+pub fn is_external_url(target: &str) -> bool {
+    URL_SCHEME_RE.is_match(target) || target.starts_with("mailto:")
+}
+
 pub fn file_link_pattern() -> String {
     r"([\sa-zA-Z0-9'?!\.:\-/_=%&@#]+)".to_string()
 }
 
+/// rest of a dataview-style inline field `(key:: value)` after the opening `(`, captures key and value
+pub fn inline_field_pattern() -> String {
+    r"^([\p{L}\p{N}_\-]+)::[ \t]*([^()\n]*?)[ \t]*\)".to_string()
+}
+
 pub fn _indent_level(line: &str) -> usize {
     let indent_pattern = " ".repeat(SPACES_PER_INDENT);
     let line = line.replace("\t", &indent_pattern);
@@ -140,3 +404,119 @@ pub fn _overlapping_captures(
         res.push(captures);
     }
 }
+
+#[test]
+fn test_html_decode() {
+    assert_eq!(
+        html_decode("Squats &amp; Deadlifts: What&#8217;s new &#x2022; &unknown; & more"),
+        "Squats & Deadlifts: What\u{2019}s new \u{2022} &unknown; & more"
+    );
+}
+
+#[test]
+fn test_default_substitutions() {
+    let cases = [
+        ("a − b", "a - b"),
+        ("a — b", "a - b"),
+        ("a ∗ b", "a * b"),
+        ("x ∈ A", "x \\in  A"),
+        ("“quoted”", "\"quoted\""),
+        ("∃ x", "EXISTS x"),
+        ("it’s", "it's"),
+        ("1–2", "1-2"),
+    ];
+    let substitutions = default_substitutions();
+    assert_eq!(substitutions.len(), 9);
+    for (text, expected) in cases {
+        assert_eq!(apply_substitution_table(text, &substitutions), expected);
+    }
+}
+
+#[test]
+fn test_custom_substitution_applied_during_parse() {
+    use crate::parsing::logseq_parsing::{parse_logseq_text, parse_logseq_text_with};
+    let mut substitutions = default_substitutions();
+    substitutions.push(("→".to_string(), "->".to_string()));
+    let pd = parse_logseq_text_with("- a → b", &None, false, &substitutions).unwrap();
+    assert_eq!(pd.to_logseq_text(&None), "- a -> b");
+    // without the built-in table, ’ reaches the lexer
+    assert!(parse_logseq_text_with("- it’s", &None, false, &[]).is_err());
+    let pd = parse_logseq_text("- it’s b", &None).unwrap();
+    assert_eq!(pd.to_logseq_text(&None), "- it's b");
+}
+
+#[test]
+fn test_files_in_tree_max_depth() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(dir.join("a").join("b")).unwrap();
+    for f in ["top.md", "a/one.md", "a/b/two.md"] {
+        std::fs::write(dir.join(f), "").unwrap();
+    }
+    let dir = dir.canonicalize().unwrap();
+    let files = |max_depth| files_in_tree(&dir, &Some(vec!["md"]), max_depth).unwrap();
+    assert_eq!(files(Some(0)), vec![dir.join("top.md")]);
+    assert_eq!(
+        files(Some(1)),
+        vec![dir.join("a/one.md"), dir.join("top.md")]
+    );
+    assert_eq!(files(None).len(), 3);
+}
+
+#[test]
+fn test_files_in_tree_sorted() {
+    let dir_tmp = tempfile::tempdir().unwrap();
+    let dir = dir_tmp.path().to_path_buf();
+    std::fs::create_dir_all(dir.join("m")).unwrap();
+    for f in ["z.md", "a.md", "m/b.md", "m/a.md", "c.md"] {
+        std::fs::write(dir.join(f), "").unwrap();
+    }
+    let dir = dir.canonicalize().unwrap();
+    let files = files_in_tree(&dir, &Some(vec!["md"]), None).unwrap();
+    assert_eq!(files, files_in_tree(&dir, &Some(vec!["md"]), None).unwrap());
+    assert_eq!(
+        files,
+        ["a.md", "c.md", "m/a.md", "m/b.md", "z.md"]
+            .iter()
+            .map(|f| dir.join(f))
+            .collect::<Vec<PathBuf>>()
+    );
+}
+
+#[test]
+fn test_slugify() {
+    let opts = SlugOptions::default();
+    assert_eq!(
+        slugify("Über Größe: what's \"new\"?! (Part 2)", &opts),
+        "ueber-groesse-whats-new-part-2"
+    );
+    assert_eq!(slugify("  --Some   Article--  ", &opts), "some-article");
+
+    let opts = SlugOptions {
+        lowercase: false,
+        separator: "_".to_string(),
+        transliterate: false,
+        max_len: Some(9),
+    };
+    assert_eq!(slugify("Über Größe und mehr", &opts), "Über_Größ");
+    // no separator at the end of a shortened slug
+    assert_eq!(slugify("Abcdefgh ij", &opts), "Abcdefgh");
+}
+
+#[test]
+fn test_detect_indent_unit() {
+    assert_eq!(IndentUnit::detect("- a\n\t- b"), IndentUnit::Tab);
+    assert_eq!(
+        IndentUnit::detect("- a\n  - b\n    - c\n      text"),
+        IndentUnit::Spaces(2)
+    );
+    assert_eq!(
+        IndentUnit::detect("just text\n  indented"),
+        IndentUnit::Spaces(SPACES_PER_INDENT)
+    );
+    // list items in code blocks do not count
+    assert_eq!(
+        IndentUnit::detect("- a\n    - b\n    ```\n  - not an item\n    ```"),
+        IndentUnit::Spaces(4)
+    );
+}